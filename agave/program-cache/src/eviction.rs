@@ -0,0 +1,192 @@
+//! Program cache eviction strategies.
+
+use {
+    crate::LoadedProgram,
+    rand::{thread_rng, Rng},
+    solana_sdk::{clock::Slot, pubkey::Pubkey},
+    std::sync::{atomic::Ordering, Arc},
+};
+
+/// A percentage, clamped to the range `0..=100`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Percentage(u8);
+
+impl Percentage {
+    /// Apply the percentage to a value, rounding down.
+    pub fn apply_to(&self, value: usize) -> usize {
+        // The result never exceeds `value`, so it always fits in a usize.
+        (value as u128 * u128::from(self.0) / 100) as usize
+    }
+}
+
+impl From<u8> for Percentage {
+    fn from(percentage: u8) -> Self {
+        Self(percentage.min(100))
+    }
+}
+
+/// A strategy for choosing which loaded programs to evict from the cache.
+pub trait EvictionStrategy {
+    /// Remove `num_to_evict` victims from `candidates` and return them.
+    fn select_victims(
+        &self,
+        candidates: &mut Vec<(Pubkey, Arc<LoadedProgram>)>,
+        num_to_evict: usize,
+        now: Slot,
+    ) -> Vec<(Pubkey, Arc<LoadedProgram>)>;
+}
+
+/// Repeatedly sample two random candidates and evict the one with the lower
+/// decayed usage counter.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TwoRandomChoices;
+
+impl EvictionStrategy for TwoRandomChoices {
+    fn select_victims(
+        &self,
+        candidates: &mut Vec<(Pubkey, Arc<LoadedProgram>)>,
+        num_to_evict: usize,
+        now: Slot,
+    ) -> Vec<(Pubkey, Arc<LoadedProgram>)> {
+        let mut rng = thread_rng();
+        let num_to_evict = num_to_evict.min(candidates.len());
+        let mut victims = Vec::with_capacity(num_to_evict);
+        for _ in 0..num_to_evict {
+            let index1 = rng.gen_range(0..candidates.len());
+            let index2 = rng.gen_range(0..candidates.len());
+            let usage_counter1 = candidates[index1].1.decayed_usage_counter(now);
+            let usage_counter2 = candidates[index2].1.decayed_usage_counter(now);
+            let index = if usage_counter1 < usage_counter2 {
+                index1
+            } else {
+                index2
+            };
+            victims.push(candidates.swap_remove(index));
+        }
+        victims
+    }
+}
+
+/// Evict the least recently accessed programs, breaking ties by evicting the
+/// least frequently invoked first.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LeastRecentlyUsed;
+
+impl EvictionStrategy for LeastRecentlyUsed {
+    fn select_victims(
+        &self,
+        candidates: &mut Vec<(Pubkey, Arc<LoadedProgram>)>,
+        num_to_evict: usize,
+        _now: Slot,
+    ) -> Vec<(Pubkey, Arc<LoadedProgram>)> {
+        candidates.sort_by_cached_key(|(_key, program)| {
+            (
+                program.latest_access_slot.load(Ordering::Relaxed),
+                program.ix_usage_counter.load(Ordering::Relaxed),
+                program.tx_usage_counter.load(Ordering::Relaxed),
+            )
+        });
+        let num_to_evict = num_to_evict.min(candidates.len());
+        candidates.drain(..num_to_evict).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::LoadedProgramType,
+        std::{collections::HashSet, sync::atomic::AtomicU64},
+    };
+
+    fn new_candidate(
+        latest_access_slot: Slot,
+        ix_usage_counter: u64,
+        tx_usage_counter: u64,
+    ) -> (Pubkey, Arc<LoadedProgram>) {
+        let mut program = LoadedProgram::new_tombstone(0, LoadedProgramType::Closed);
+        program.latest_access_slot = AtomicU64::new(latest_access_slot);
+        program.ix_usage_counter = AtomicU64::new(ix_usage_counter);
+        program.tx_usage_counter = AtomicU64::new(tx_usage_counter);
+        (Pubkey::new_unique(), Arc::new(program))
+    }
+
+    fn keys(candidates: &[(Pubkey, Arc<LoadedProgram>)]) -> Vec<Pubkey> {
+        candidates.iter().map(|(key, _program)| *key).collect()
+    }
+
+    #[test]
+    fn test_percentage() {
+        assert_eq!(Percentage::from(90).apply_to(1000), 900);
+        assert_eq!(Percentage::from(33).apply_to(10), 3);
+        assert_eq!(Percentage::from(0).apply_to(1000), 0);
+        assert_eq!(Percentage::from(200), Percentage::from(100));
+        assert_eq!(Percentage::from(100).apply_to(usize::MAX), usize::MAX);
+        assert_eq!(Percentage::from(50).apply_to(usize::MAX), usize::MAX / 2);
+    }
+
+    #[test]
+    fn test_two_random_choices() {
+        let mut candidates = (0..10)
+            .map(|usage| new_candidate(0, 0, usage))
+            .collect::<Vec<_>>();
+        let all_keys = keys(&candidates).into_iter().collect::<HashSet<_>>();
+
+        let victims = TwoRandomChoices.select_victims(&mut candidates, 4, 0);
+        assert_eq!(victims.len(), 4);
+        assert_eq!(candidates.len(), 6);
+        let remaining_keys = keys(&victims)
+            .into_iter()
+            .chain(keys(&candidates))
+            .collect::<HashSet<_>>();
+        assert_eq!(remaining_keys, all_keys);
+
+        // Asking for more victims than candidates evicts every candidate.
+        let victims = TwoRandomChoices.select_victims(&mut candidates, 100, 0);
+        assert_eq!(victims.len(), 6);
+        assert!(candidates.is_empty());
+    }
+
+    #[test]
+    fn test_two_random_choices_single_candidate() {
+        let mut candidates = vec![new_candidate(0, 0, 1)];
+        let victims = TwoRandomChoices.select_victims(&mut candidates, 1, 0);
+        assert_eq!(victims.len(), 1);
+        assert!(candidates.is_empty());
+        assert!(TwoRandomChoices
+            .select_victims(&mut candidates, 1, 0)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_decayed_usage_counter() {
+        let (_key, program) = new_candidate(10, 0, 64);
+        assert_eq!(program.decayed_usage_counter(5), 64);
+        assert_eq!(program.decayed_usage_counter(10), 64);
+        assert_eq!(program.decayed_usage_counter(12), 16);
+        assert_eq!(program.decayed_usage_counter(17), 0);
+        assert_eq!(program.decayed_usage_counter(Slot::MAX), 0);
+    }
+
+    #[test]
+    fn test_least_recently_used() {
+        let recent = new_candidate(5, 0, 0);
+        let old = new_candidate(1, 10, 10);
+        let old_frequent = new_candidate(1, 20, 0);
+        let older = new_candidate(0, 100, 100);
+        let mut candidates = vec![
+            recent.clone(),
+            old_frequent.clone(),
+            old.clone(),
+            older.clone(),
+        ];
+
+        let victims = LeastRecentlyUsed.select_victims(&mut candidates, 2, 5);
+        assert_eq!(keys(&victims), vec![older.0, old.0]);
+        assert_eq!(keys(&candidates), vec![old_frequent.0, recent.0]);
+
+        let victims = LeastRecentlyUsed.select_victims(&mut candidates, 100, 5);
+        assert_eq!(keys(&victims), vec![old_frequent.0, recent.0]);
+        assert!(candidates.is_empty());
+    }
+}
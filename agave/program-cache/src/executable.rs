@@ -0,0 +1,48 @@
+//! VM-agnostic program representations.
+//!
+//! The cache never inspects compiled programs itself. A VM backend provides
+//! runtime environments, which verify and compile programs into executables,
+//! and downcasts both back to its own types when executing.
+
+use std::{any::Any, error::Error, sync::Arc};
+
+/// A runtime environment programs are verified and compiled against, such as
+/// a VM configuration along with its registered syscalls.
+pub trait RuntimeEnvironment: Send + Sync {
+    /// Verify and compile a program against this environment.
+    fn load_executable(
+        self: Arc<Self>,
+        elf_bytes: &[u8],
+    ) -> Result<Arc<dyn Executable>, Box<dyn Error>>;
+
+    fn as_any(&self) -> &dyn Any;
+}
+
+pub type ProgramRuntimeEnvironment = Arc<dyn RuntimeEnvironment>;
+
+/// A verified program, ready to be executed by the VM backend which
+/// compiled it.
+pub trait Executable: Send + Sync {
+    /// The runtime environment the program was compiled against.
+    fn get_loader(&self) -> &ProgramRuntimeEnvironment;
+
+    fn as_any(&self) -> &dyn Any;
+}
+
+/// A runtime environment without a VM backend, which fails to load any
+/// program.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct EmptyRuntimeEnvironment;
+
+impl RuntimeEnvironment for EmptyRuntimeEnvironment {
+    fn load_executable(
+        self: Arc<Self>,
+        _elf_bytes: &[u8],
+    ) -> Result<Arc<dyn Executable>, Box<dyn Error>> {
+        Err("No VM backend".into())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
@@ -0,0 +1,194 @@
+//! In-memory fork graph.
+
+use {
+    crate::{BlockRelation, ForkGraph},
+    solana_sdk::{
+        clock::{Epoch, Slot},
+        epoch_schedule::EpochSchedule,
+    },
+    std::collections::HashMap,
+};
+
+/// A fork graph backed by the parent link of every slot descending from the
+/// latest root.
+pub struct InMemoryForkGraph {
+    /// The parent of every known slot, excluding the root.
+    parents: HashMap<Slot, Slot>,
+    root: Slot,
+    epoch_schedule: EpochSchedule,
+}
+
+impl InMemoryForkGraph {
+    pub fn new(root: Slot, epoch_schedule: EpochSchedule) -> Self {
+        Self {
+            parents: HashMap::new(),
+            root,
+            epoch_schedule,
+        }
+    }
+
+    pub fn root(&self) -> Slot {
+        self.root
+    }
+
+    /// Whether the slot is the root or descends from it.
+    pub fn contains(&self, slot: Slot) -> bool {
+        slot == self.root || self.parents.contains_key(&slot)
+    }
+
+    /// Insert a new slot as a child of `parent`.
+    ///
+    /// Returns `false` if the slot is already known, the parent is unknown,
+    /// or the slot does not come after its parent.
+    pub fn insert_slot(&mut self, slot: Slot, parent: Slot) -> bool {
+        if self.contains(slot) || !self.contains(parent) || slot <= parent {
+            return false;
+        }
+        self.parents.insert(slot, parent);
+        true
+    }
+
+    /// Set a new root, pruning every slot which does not descend from it.
+    ///
+    /// Returns `false` if the new root does not descend from the current
+    /// root.
+    ///
+    /// The slots between the current and the new root are forgotten, so a
+    /// `ProgramCache` using this fork graph must be pruned to the new root
+    /// before calling this. Otherwise it drops the programs deployed in
+    /// those slots.
+    pub fn set_root(&mut self, new_root: Slot) -> bool {
+        if !self.contains(new_root) {
+            return false;
+        }
+        let pruned = self
+            .parents
+            .keys()
+            .copied()
+            .filter(|slot| !self.is_ancestor(new_root, *slot))
+            .collect::<Vec<_>>();
+        for slot in pruned {
+            self.parents.remove(&slot);
+        }
+        self.root = new_root;
+        true
+    }
+
+    /// Iterate over the ancestors of a known slot, from its parent up to the
+    /// root.
+    fn ancestors(&self, slot: Slot) -> impl Iterator<Item = Slot> + '_ {
+        std::iter::successors(self.parents.get(&slot).copied(), |slot| {
+            self.parents.get(slot).copied()
+        })
+    }
+
+    /// Whether `ancestor` is a strict ancestor of the known slot `slot`.
+    fn is_ancestor(&self, ancestor: Slot, slot: Slot) -> bool {
+        ancestor < slot
+            && self
+                .ancestors(slot)
+                .take_while(|parent| *parent >= ancestor)
+                .any(|parent| parent == ancestor)
+    }
+}
+
+impl ForkGraph for InMemoryForkGraph {
+    fn relationship(&self, a: Slot, b: Slot) -> BlockRelation {
+        if !self.contains(a) || !self.contains(b) {
+            BlockRelation::Unknown
+        } else if a == b {
+            BlockRelation::Equal
+        } else if self.is_ancestor(a, b) {
+            BlockRelation::Ancestor
+        } else if self.is_ancestor(b, a) {
+            BlockRelation::Descendant
+        } else {
+            BlockRelation::Unrelated
+        }
+    }
+
+    fn slot_epoch(&self, slot: Slot) -> Option<Epoch> {
+        self.contains(slot)
+            .then(|| self.epoch_schedule.get_epoch(slot))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //     0
+    //     |
+    //     1
+    //    / \
+    //   2   3
+    //   |   |
+    //   4   5
+    fn new_fork_graph() -> InMemoryForkGraph {
+        let mut fork_graph = InMemoryForkGraph::new(0, EpochSchedule::custom(32, 32, false));
+        for (slot, parent) in [(1, 0), (2, 1), (3, 1), (4, 2), (5, 3)] {
+            assert!(fork_graph.insert_slot(slot, parent));
+        }
+        fork_graph
+    }
+
+    #[test]
+    fn test_insert_slot() {
+        let mut fork_graph = new_fork_graph();
+        // Already known.
+        assert!(!fork_graph.insert_slot(4, 2));
+        // Unknown parent.
+        assert!(!fork_graph.insert_slot(7, 6));
+        // Not after its parent.
+        assert!(!fork_graph.insert_slot(3, 4));
+        assert!(fork_graph.insert_slot(6, 4));
+        assert!(fork_graph.contains(6));
+    }
+
+    #[test]
+    fn test_relationship() {
+        let fork_graph = new_fork_graph();
+        assert_eq!(fork_graph.relationship(2, 2), BlockRelation::Equal);
+        assert_eq!(fork_graph.relationship(0, 4), BlockRelation::Ancestor);
+        assert_eq!(fork_graph.relationship(1, 5), BlockRelation::Ancestor);
+        assert_eq!(fork_graph.relationship(4, 0), BlockRelation::Descendant);
+        assert_eq!(fork_graph.relationship(4, 5), BlockRelation::Unrelated);
+        assert_eq!(fork_graph.relationship(2, 3), BlockRelation::Unrelated);
+        assert_eq!(fork_graph.relationship(4, 6), BlockRelation::Unknown);
+        assert_eq!(fork_graph.relationship(6, 4), BlockRelation::Unknown);
+    }
+
+    #[test]
+    fn test_set_root() {
+        let mut fork_graph = new_fork_graph();
+        // Not a descendant of the current root.
+        assert!(!fork_graph.set_root(6));
+
+        assert!(fork_graph.set_root(2));
+        assert_eq!(fork_graph.root(), 2);
+        // The other fork is pruned, as are the slots below the new root.
+        for slot in [0, 1, 3, 5] {
+            assert!(!fork_graph.contains(slot));
+        }
+        assert_eq!(fork_graph.relationship(2, 4), BlockRelation::Ancestor);
+        assert_eq!(fork_graph.relationship(4, 2), BlockRelation::Descendant);
+        assert_eq!(fork_graph.relationship(1, 4), BlockRelation::Unknown);
+        assert_eq!(fork_graph.relationship(5, 4), BlockRelation::Unknown);
+
+        // Pruned slots cannot become the root again.
+        assert!(!fork_graph.set_root(3));
+        assert!(!fork_graph.insert_slot(6, 5));
+        assert!(fork_graph.insert_slot(6, 4));
+        assert_eq!(fork_graph.relationship(2, 6), BlockRelation::Ancestor);
+    }
+
+    #[test]
+    fn test_slot_epoch() {
+        let mut fork_graph = new_fork_graph();
+        assert!(fork_graph.insert_slot(40, 5));
+        assert_eq!(fork_graph.slot_epoch(0), Some(0));
+        assert_eq!(fork_graph.slot_epoch(5), Some(0));
+        assert_eq!(fork_graph.slot_epoch(40), Some(1));
+        assert_eq!(fork_graph.slot_epoch(6), None);
+    }
+}
@@ -1,16 +1,35 @@
 //! Agave Program Cache.
 
+pub mod eviction;
+pub mod executable;
+pub mod fork_graph;
+pub mod stats;
+
 use {
+    crate::{
+        eviction::{EvictionStrategy, Percentage, TwoRandomChoices},
+        executable::{EmptyRuntimeEnvironment, Executable, ProgramRuntimeEnvironment},
+        stats::Stats,
+    },
     solana_sdk::{
+        bpf_loader, bpf_loader_deprecated, bpf_loader_upgradeable,
         clock::{Epoch, Slot},
+        epoch_schedule::EpochSchedule,
+        loader_v4,
         pubkey::Pubkey,
     },
     std::{
         collections::HashMap,
-        sync::{atomic::AtomicU64, Arc, Condvar, Mutex, RwLock},
+        error::Error,
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc, Condvar, Mutex, RwLock,
+        },
+        thread::{self, ThreadId},
     },
 };
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum BlockRelation {
     Ancestor,
     Equal,
@@ -28,14 +47,35 @@ pub trait ForkGraph {
 }
 
 pub enum LoadedProgramType {
-    // FailedVerification(ProgramRuntimeEnvironment),
+    /// Tombstone for programs which did not pass the verifier.
+    FailedVerification(ProgramRuntimeEnvironment),
+    /// Tombstone for programs which were closed or never deployed.
     Closed,
+    /// Tombstone for programs which were modified in the current slot.
     DelayVisibility,
-    // Unloaded(ProgramRuntimeEnvironment),
-    // LegacyV0(Executable<InvokeContext<'static>>),
-    // LegacyV1(Executable<InvokeContext<'static>>),
-    // Typed(Executable<InvokeContext<'static>>),
-    // Builtin(BuiltinProgram<InvokeContext<'static>>),
+    /// A verified program which has been evicted, retained to keep its
+    /// usage counters.
+    Unloaded(ProgramRuntimeEnvironment),
+    LegacyV0(Arc<dyn Executable>),
+    LegacyV1(Arc<dyn Executable>),
+    Typed(Arc<dyn Executable>),
+    /// A program implemented natively by the VM backend, which dispatches it
+    /// by program id.
+    Builtin,
+}
+
+impl LoadedProgramType {
+    /// The runtime environment the program was verified against, if any.
+    pub fn get_environment(&self) -> Option<&ProgramRuntimeEnvironment> {
+        match self {
+            LoadedProgramType::LegacyV0(executable)
+            | LoadedProgramType::LegacyV1(executable)
+            | LoadedProgramType::Typed(executable) => Some(executable.get_loader()),
+            LoadedProgramType::FailedVerification(environment)
+            | LoadedProgramType::Unloaded(environment) => Some(environment),
+            _ => None,
+        }
+    }
 }
 
 pub struct LoadedProgram {
@@ -48,22 +88,202 @@ pub struct LoadedProgram {
     pub latest_access_slot: AtomicU64,
 }
 
-// pub struct ProgramRuntimeEnvironments {
-//     pub program_runtime_v1: ProgramRuntimeEnvironment,
-//     pub program_runtime_v2: ProgramRuntimeEnvironment,
-// }
+/// The number of slots a newly deployed program remains invisible for.
+pub const DELAY_VISIBILITY_SLOT_OFFSET: Slot = 1;
+/// The number of loaded programs the cache holds before eviction targets
+/// are applied.
+pub const MAX_LOADED_ENTRY_COUNT: usize = 256;
+
+impl LoadedProgram {
+    /// Verify and compile a program against `program_runtime_environment`.
+    pub fn new(
+        loader_key: &Pubkey,
+        program_runtime_environment: ProgramRuntimeEnvironment,
+        deployment_slot: Slot,
+        effective_slot: Slot,
+        elf_bytes: &[u8],
+        account_size: usize,
+    ) -> Result<Self, Box<dyn Error>> {
+        let executable = program_runtime_environment.load_executable(elf_bytes)?;
+        let program = if bpf_loader_deprecated::check_id(loader_key) {
+            LoadedProgramType::LegacyV0(executable)
+        } else if bpf_loader::check_id(loader_key) || bpf_loader_upgradeable::check_id(loader_key) {
+            LoadedProgramType::LegacyV1(executable)
+        } else if loader_v4::check_id(loader_key) {
+            LoadedProgramType::Typed(executable)
+        } else {
+            return Err("Unsupported loader".into());
+        };
+        Ok(Self {
+            program,
+            account_size,
+            deployment_slot,
+            effective_slot,
+            tx_usage_counter: AtomicU64::default(),
+            ix_usage_counter: AtomicU64::default(),
+            latest_access_slot: AtomicU64::new(0),
+        })
+    }
+
+    /// Create a builtin program entry, implemented natively by the VM
+    /// backend.
+    pub fn new_builtin(deployment_slot: Slot, account_size: usize) -> Self {
+        Self {
+            program: LoadedProgramType::Builtin,
+            account_size,
+            deployment_slot,
+            effective_slot: deployment_slot,
+            tx_usage_counter: AtomicU64::default(),
+            ix_usage_counter: AtomicU64::default(),
+            latest_access_slot: AtomicU64::new(0),
+        }
+    }
+
+    /// Create a tombstone entry, marking a program as unusable.
+    pub fn new_tombstone(slot: Slot, reason: LoadedProgramType) -> Self {
+        let tombstone = Self {
+            program: reason,
+            account_size: 0,
+            deployment_slot: slot,
+            effective_slot: slot,
+            tx_usage_counter: AtomicU64::default(),
+            ix_usage_counter: AtomicU64::default(),
+            latest_access_slot: AtomicU64::new(0),
+        };
+        debug_assert!(tombstone.is_tombstone());
+        tombstone
+    }
+
+    /// An unloaded copy of the entry, keeping its usage counters, if it
+    /// holds a compiled program.
+    pub fn to_unloaded(&self) -> Option<Self> {
+        let environment = match &self.program {
+            LoadedProgramType::LegacyV0(executable)
+            | LoadedProgramType::LegacyV1(executable)
+            | LoadedProgramType::Typed(executable) => executable.get_loader().clone(),
+            _ => return None,
+        };
+        Some(Self {
+            program: LoadedProgramType::Unloaded(environment),
+            account_size: self.account_size,
+            deployment_slot: self.deployment_slot,
+            effective_slot: self.effective_slot,
+            tx_usage_counter: AtomicU64::new(self.tx_usage_counter.load(Ordering::Relaxed)),
+            ix_usage_counter: AtomicU64::new(self.ix_usage_counter.load(Ordering::Relaxed)),
+            latest_access_slot: AtomicU64::new(self.latest_access_slot.load(Ordering::Relaxed)),
+        })
+    }
+
+    pub fn is_tombstone(&self) -> bool {
+        matches!(
+            self.program,
+            LoadedProgramType::FailedVerification(_)
+                | LoadedProgramType::Closed
+                | LoadedProgramType::DelayVisibility
+        )
+    }
+
+    /// Whether the entry holds a compiled program.
+    pub fn is_loaded(&self) -> bool {
+        matches!(
+            self.program,
+            LoadedProgramType::LegacyV0(_)
+                | LoadedProgramType::LegacyV1(_)
+                | LoadedProgramType::Typed(_)
+        )
+    }
+
+    fn is_implicit_delay_visibility_tombstone(&self, slot: Slot) -> bool {
+        self.effective_slot.saturating_sub(self.deployment_slot) == DELAY_VISIBILITY_SLOT_OFFSET
+            && slot >= self.deployment_slot
+            && slot < self.effective_slot
+    }
+
+    pub fn update_access_slot(&self, slot: Slot) {
+        let _ = self.latest_access_slot.fetch_max(slot, Ordering::Relaxed);
+    }
+
+    /// The transaction usage counter, halved for every slot since the program
+    /// was last accessed.
+    pub fn decayed_usage_counter(&self, now: Slot) -> u64 {
+        let last_access = self.latest_access_slot.load(Ordering::Relaxed);
+        let decaying_for = now.saturating_sub(last_access).min(63);
+        self.tx_usage_counter.load(Ordering::Relaxed) >> decaying_for
+    }
+}
+
+/// Criteria a cached program must meet to be returned by
+/// `ProgramCache::extract`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LoadedProgramMatchCriteria {
+    DeployedOnOrAfterSlot(Slot),
+    Tombstone,
+    NoCriteria,
+}
 
-pub struct ProgramRuntimeEnvironments;
+/// The runtime environments programs are compiled against in an epoch.
+#[derive(Clone)]
+pub struct ProgramRuntimeEnvironments {
+    /// For programs owned by the legacy BPF loaders.
+    pub program_runtime_v1: ProgramRuntimeEnvironment,
+    /// For programs owned by loader-v4.
+    pub program_runtime_v2: ProgramRuntimeEnvironment,
+}
 
+impl Default for ProgramRuntimeEnvironments {
+    fn default() -> Self {
+        let empty_environment: ProgramRuntimeEnvironment = Arc::new(EmptyRuntimeEnvironment);
+        Self {
+            program_runtime_v1: empty_environment.clone(),
+            program_runtime_v2: empty_environment,
+        }
+    }
+}
+
+/// Globally shared counter, advanced every time a loading task finishes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
 pub struct LoadingTaskCookie(u64);
+
+impl LoadingTaskCookie {
+    fn bump(self) -> Self {
+        Self(self.0.wrapping_add(1))
+    }
+}
+
+/// Suspends transaction batches until a program they are missing has been
+/// loaded by another thread.
+#[derive(Default)]
 pub struct LoadingTaskWaiter {
     pub cookie: Mutex<LoadingTaskCookie>,
     pub cond: Condvar,
 }
 
+impl LoadingTaskWaiter {
+    pub fn cookie(&self) -> LoadingTaskCookie {
+        *self.cookie.lock().unwrap()
+    }
+
+    /// Advance the cookie and wake every waiting thread.
+    pub fn notify(&self) {
+        let mut cookie = self.cookie.lock().unwrap();
+        *cookie = cookie.bump();
+        self.cond.notify_all();
+    }
+
+    /// Block until the cookie has advanced past `cookie`.
+    pub fn wait(&self, cookie: LoadingTaskCookie) -> LoadingTaskCookie {
+        let cookie_guard = self.cookie.lock().unwrap();
+        *self
+            .cond
+            .wait_while(cookie_guard, |current_cookie| *current_cookie == cookie)
+            .unwrap()
+    }
+}
+
+#[derive(Default)]
 pub struct SecondLevel {
     pub slot_versions: Vec<Arc<LoadedProgram>>,
-    pub cooperative_loading_lock: Option<(Slot, std::thread::ThreadId)>,
+    pub cooperative_loading_lock: Option<(Slot, ThreadId)>,
 }
 
 pub struct ProgramCache<FG: ForkGraph> {
@@ -73,11 +293,472 @@ pub struct ProgramCache<FG: ForkGraph> {
     pub environments: ProgramRuntimeEnvironments,
     pub upcoming_environments: Option<ProgramRuntimeEnvironments>,
     pub programs_to_recompile: Vec<(Pubkey, Arc<LoadedProgram>)>,
-    // pub stats: Stats,
+    pub stats: Stats,
     pub fork_graph: Option<Arc<RwLock<FG>>>,
     pub loading_task_waiter: Arc<LoadingTaskWaiter>,
 }
 
+impl<FG: ForkGraph> ProgramCache<FG> {
+    pub fn new(root_slot: Slot, root_epoch: Epoch) -> Self {
+        Self {
+            entries: HashMap::new(),
+            latest_root_slot: root_slot,
+            latest_root_epoch: root_epoch,
+            environments: ProgramRuntimeEnvironments::default(),
+            upcoming_environments: None,
+            programs_to_recompile: Vec::new(),
+            stats: Stats::default(),
+            fork_graph: None,
+            loading_task_waiter: Arc::new(LoadingTaskWaiter::default()),
+        }
+    }
+
+    pub fn set_fork_graph(&mut self, fork_graph: Arc<RwLock<FG>>) {
+        self.fork_graph = Some(fork_graph);
+    }
+
+    /// Stage the environments of the next epoch, queuing every loaded program
+    /// for recompilation, most used first.
+    pub fn set_upcoming_environments(
+        &mut self,
+        upcoming_environments: ProgramRuntimeEnvironments,
+        now: Slot,
+    ) {
+        self.upcoming_environments = Some(upcoming_environments);
+        self.programs_to_recompile = self.get_flattened_entries();
+        self.programs_to_recompile
+            .sort_by_cached_key(|(_key, program)| program.decayed_usage_counter(now));
+    }
+
+    /// The number of slots at the end of an epoch during which programs are
+    /// recompiled for the upcoming environments.
+    pub fn recompilation_phase_slots(slots_in_epoch: u64) -> u64 {
+        (MAX_LOADED_ENTRY_COUNT as u64).min(slots_in_epoch) / 2
+    }
+
+    /// Whether `slot` is within the recompilation phase at the end of its
+    /// epoch.
+    pub fn is_in_recompilation_phase(slot: Slot, epoch_schedule: &EpochSchedule) -> bool {
+        let (epoch, slot_index) = epoch_schedule.get_epoch_and_slot_index(slot);
+        let slots_in_epoch = epoch_schedule.get_slots_in_epoch(epoch);
+        slots_in_epoch.saturating_sub(slot_index) <= Self::recompilation_phase_slots(slots_in_epoch)
+    }
+
+    /// Recompile the most used programs queued in `programs_to_recompile`
+    /// against the upcoming environments, if `slot` is within the
+    /// recompilation phase.
+    ///
+    /// The queue is spread across the remaining slots of the phase, so that
+    /// it is drained by the epoch boundary. Recompiled programs only become
+    /// effective in the upcoming epoch. Returns the number of programs
+    /// inserted.
+    pub fn recompile_for_upcoming_environments<F>(
+        &mut self,
+        slot: Slot,
+        epoch_schedule: &EpochSchedule,
+        mut recompile: F,
+    ) -> usize
+    where
+        F: FnMut(&Pubkey, &LoadedProgram, &ProgramRuntimeEnvironments) -> Option<LoadedProgram>,
+    {
+        let Some(upcoming_environments) = self.upcoming_environments.as_ref() else {
+            return 0;
+        };
+        if !Self::is_in_recompilation_phase(slot, epoch_schedule) {
+            return 0;
+        }
+        let (epoch, slot_index) = epoch_schedule.get_epoch_and_slot_index(slot);
+        let slots_remaining = epoch_schedule
+            .get_slots_in_epoch(epoch)
+            .saturating_sub(slot_index)
+            .max(1);
+        let upcoming_epoch_start_slot = epoch_schedule.get_first_slot_in_epoch(epoch + 1);
+        let num_to_recompile = self
+            .programs_to_recompile
+            .len()
+            .div_ceil(slots_remaining as usize);
+        let mut recompiled_programs = Vec::with_capacity(num_to_recompile);
+        for _ in 0..num_to_recompile {
+            let Some((key, program)) = self.programs_to_recompile.pop() else {
+                break;
+            };
+            let Some(mut recompiled) = recompile(&key, &program, upcoming_environments) else {
+                continue;
+            };
+            recompiled.effective_slot = recompiled.effective_slot.max(upcoming_epoch_start_slot);
+            recompiled.tx_usage_counter =
+                AtomicU64::new(program.tx_usage_counter.load(Ordering::Relaxed));
+            recompiled.ix_usage_counter =
+                AtomicU64::new(program.ix_usage_counter.load(Ordering::Relaxed));
+            recompiled_programs.push((key, Arc::new(recompiled)));
+        }
+        let num_recompiled = recompiled_programs.len();
+        for (key, recompiled) in recompiled_programs {
+            self.assign_program(key, recompiled);
+        }
+        num_recompiled
+    }
+
+    /// Move the root to `new_root_slot`, dropping every program version which
+    /// is no longer reachable.
+    ///
+    /// Versions deployed on forks unrelated to the new root are removed, as
+    /// are versions superseded by a newer version at or below the root. If
+    /// the root crosses into a new epoch, the upcoming environments become
+    /// the current environments.
+    ///
+    /// Must be called before the root of the fork graph moves to
+    /// `new_root_slot`, while the fork graph still relates the slots between
+    /// the previous and the new root.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the fork graph no longer relates the previous root to
+    /// `new_root_slot` as an ancestor.
+    pub fn prune(&mut self, new_root_slot: Slot, new_root_epoch: Epoch) {
+        debug_assert!(self.fork_graph.is_some());
+        debug_assert!(self.latest_root_slot <= new_root_slot);
+        let mut recompilation_phase_ends = false;
+        if self.latest_root_epoch != new_root_epoch {
+            self.latest_root_epoch = new_root_epoch;
+            if let Some(upcoming_environments) = self.upcoming_environments.take() {
+                recompilation_phase_ends = true;
+                self.environments = upcoming_environments;
+                self.programs_to_recompile.clear();
+            }
+        }
+        let fork_graph = self.fork_graph.as_ref().unwrap().read().unwrap();
+        let latest_root_slot = self.latest_root_slot;
+        assert!(
+            matches!(
+                fork_graph.relationship(latest_root_slot, new_root_slot),
+                BlockRelation::Equal | BlockRelation::Ancestor
+            ),
+            "the program cache must be pruned before the fork graph root moves"
+        );
+        let environments = &self.environments;
+        let stats = &self.stats;
+        for second_level in self.entries.values_mut() {
+            let mut first_ancestor_found = false;
+            let mut first_ancestor_env = None;
+            second_level.slot_versions.reverse();
+            second_level.slot_versions.retain(|entry| {
+                // Drop versions compiled for the environments of the previous
+                // epoch.
+                if recompilation_phase_ends && !Self::matches_environment(entry, environments) {
+                    stats.prunes_environment.fetch_add(1, Ordering::Relaxed);
+                    return false;
+                }
+                let relation = fork_graph.relationship(entry.deployment_slot, new_root_slot);
+                if entry.deployment_slot >= new_root_slot {
+                    matches!(relation, BlockRelation::Equal | BlockRelation::Descendant)
+                } else if matches!(relation, BlockRelation::Ancestor)
+                    || entry.deployment_slot <= latest_root_slot
+                {
+                    // Only the newest version below the root remains
+                    // reachable.
+                    if !first_ancestor_found {
+                        first_ancestor_found = true;
+                        first_ancestor_env = entry.program.get_environment().cloned();
+                        return true;
+                    }
+                    // A version compiled for other environments may still be
+                    // used by slots of the previous epoch.
+                    if let (Some(entry_env), Some(env)) =
+                        (entry.program.get_environment(), &first_ancestor_env)
+                    {
+                        if !Arc::ptr_eq(entry_env, env) {
+                            return true;
+                        }
+                    }
+                    stats.prunes_orphan.fetch_add(1, Ordering::Relaxed);
+                    false
+                } else {
+                    stats.prunes_orphan.fetch_add(1, Ordering::Relaxed);
+                    false
+                }
+            });
+            second_level.slot_versions.reverse();
+        }
+        drop(fork_graph);
+        self.remove_programs_with_no_entries();
+        self.latest_root_slot = new_root_slot;
+    }
+
+    fn remove_programs_with_no_entries(&mut self) {
+        self.entries.retain(|_key, second_level| {
+            !second_level.slot_versions.is_empty()
+                || second_level.cooperative_loading_lock.is_some()
+        });
+    }
+
+    /// Insert a single program version, keeping the versions of each program
+    /// ordered by deployment slot.
+    ///
+    /// Unloaded entries are reloaded in place. Returns `true` if any other
+    /// entry for the same deployment already existed.
+    pub fn assign_program(&mut self, key: Pubkey, entry: Arc<LoadedProgram>) -> bool {
+        let slot_versions = &mut self.entries.entry(key).or_default().slot_versions;
+        match slot_versions.binary_search_by(|at| {
+            at.deployment_slot
+                .cmp(&entry.deployment_slot)
+                .then(at.effective_slot.cmp(&entry.effective_slot))
+        }) {
+            Ok(index) => {
+                let existing = slot_versions.get_mut(index).unwrap();
+                match (&existing.program, &entry.program) {
+                    (LoadedProgramType::Builtin, LoadedProgramType::Builtin)
+                    | (LoadedProgramType::Unloaded(_), LoadedProgramType::LegacyV0(_))
+                    | (LoadedProgramType::Unloaded(_), LoadedProgramType::LegacyV1(_))
+                    | (LoadedProgramType::Unloaded(_), LoadedProgramType::Typed(_)) => {
+                        entry.tx_usage_counter.fetch_add(
+                            existing.tx_usage_counter.load(Ordering::Relaxed),
+                            Ordering::Relaxed,
+                        );
+                        entry.ix_usage_counter.fetch_add(
+                            existing.ix_usage_counter.load(Ordering::Relaxed),
+                            Ordering::Relaxed,
+                        );
+                        *existing = entry;
+                        self.stats.reloads.fetch_add(1, Ordering::Relaxed);
+                        false
+                    }
+                    _ => {
+                        self.stats.replacements.fetch_add(1, Ordering::Relaxed);
+                        true
+                    }
+                }
+            }
+            Err(index) => {
+                self.stats.insertions.fetch_add(1, Ordering::Relaxed);
+                slot_versions.insert(index, entry);
+                false
+            }
+        }
+    }
+
+    /// Resolve the program versions visible to a transaction batch,
+    /// populating its entries.
+    ///
+    /// Every program found is removed from `search_for`, leaving only the
+    /// programs which must be loaded. At most one of the missing programs not
+    /// already being loaded by another batch is claimed and returned, along
+    /// with its usage count. The caller must load it and pass the result to
+    /// `finish_cooperative_loading_task`.
+    ///
+    /// Hits and misses are only counted on the first round of a batch, as
+    /// later rounds retry the same programs.
+    pub fn extract(
+        &mut self,
+        search_for: &mut Vec<(Pubkey, (LoadedProgramMatchCriteria, u64))>,
+        loaded_programs_for_tx_batch: &mut LoadedProgramsForTxBatch,
+        is_first_round: bool,
+    ) -> Option<(Pubkey, u64)> {
+        debug_assert!(self.fork_graph.is_some());
+        let locked_fork_graph = self.fork_graph.as_ref().unwrap().read().unwrap();
+        let batch_slot = loaded_programs_for_tx_batch.slot;
+        let mut cooperative_loading_task = None;
+        let num_searched = search_for.len();
+        search_for.retain(|(key, (match_criteria, usage_count))| {
+            let second_level = self.entries.entry(*key).or_default();
+            for entry in second_level.slot_versions.iter().rev() {
+                let is_visible = entry.deployment_slot <= self.latest_root_slot
+                    || matches!(
+                        locked_fork_graph.relationship(entry.deployment_slot, batch_slot),
+                        BlockRelation::Equal | BlockRelation::Ancestor
+                    );
+                if !is_visible {
+                    continue;
+                }
+                let entry_to_return = if batch_slot >= entry.effective_slot
+                    && Self::matches_environment(entry, &loaded_programs_for_tx_batch.environments)
+                {
+                    if !Self::matches_criteria(entry, match_criteria) {
+                        break;
+                    }
+                    if let LoadedProgramType::Unloaded(_) = &entry.program {
+                        break;
+                    }
+                    entry.clone()
+                } else if entry.is_implicit_delay_visibility_tombstone(batch_slot) {
+                    // Found a program entry on the current fork, but it's not
+                    // effective yet. It indicates that the program has delayed
+                    // visibility. Return the tombstone to reflect that.
+                    Arc::new(LoadedProgram::new_tombstone(
+                        entry.deployment_slot,
+                        LoadedProgramType::DelayVisibility,
+                    ))
+                } else {
+                    continue;
+                };
+                entry_to_return.update_access_slot(batch_slot);
+                entry_to_return
+                    .tx_usage_counter
+                    .fetch_add(*usage_count, Ordering::Relaxed);
+                loaded_programs_for_tx_batch
+                    .entries
+                    .insert(*key, entry_to_return);
+                if is_first_round {
+                    *self.stats.hits_by_program.entry(*key).or_insert(0) += 1;
+                }
+                return false;
+            }
+            if is_first_round {
+                *self.stats.misses_by_program.entry(*key).or_insert(0) += 1;
+            }
+            if cooperative_loading_task.is_none() && second_level.cooperative_loading_lock.is_none()
+            {
+                // Claim this missing program, as no other batch is loading it.
+                cooperative_loading_task = Some((*key, *usage_count));
+                second_level.cooperative_loading_lock = Some((batch_slot, thread::current().id()));
+            }
+            true
+        });
+        drop(locked_fork_graph);
+        if is_first_round {
+            self.stats
+                .misses
+                .fetch_add(search_for.len() as u64, Ordering::Relaxed);
+            self.stats.hits.fetch_add(
+                num_searched.saturating_sub(search_for.len()) as u64,
+                Ordering::Relaxed,
+            );
+        }
+        cooperative_loading_task
+    }
+
+    /// Release the claim on a program taken by `extract`, insert the loaded
+    /// program and wake any batches waiting on it.
+    ///
+    /// Returns `true` if an entry for the same deployment already existed.
+    pub fn finish_cooperative_loading_task(
+        &mut self,
+        slot: Slot,
+        key: Pubkey,
+        loaded_program: Arc<LoadedProgram>,
+    ) -> bool {
+        let second_level = self.entries.entry(key).or_default();
+        debug_assert_eq!(
+            second_level.cooperative_loading_lock,
+            Some((slot, thread::current().id()))
+        );
+        second_level.cooperative_loading_lock = None;
+        // The program must be visible to the fork which loaded it.
+        if loaded_program.deployment_slot > self.latest_root_slot
+            && !matches!(
+                self.fork_graph
+                    .as_ref()
+                    .unwrap()
+                    .read()
+                    .unwrap()
+                    .relationship(loaded_program.deployment_slot, slot),
+                BlockRelation::Equal | BlockRelation::Ancestor
+            )
+        {
+            self.stats.lost_insertions.fetch_add(1, Ordering::Relaxed);
+        }
+        let was_occupied = self.assign_program(key, loaded_program);
+        self.loading_task_waiter.notify();
+        was_occupied
+    }
+
+    /// Every compiled program version in the cache, which are the candidates
+    /// for eviction.
+    pub fn get_flattened_entries(&self) -> Vec<(Pubkey, Arc<LoadedProgram>)> {
+        self.entries
+            .iter()
+            .flat_map(|(key, second_level)| {
+                second_level
+                    .slot_versions
+                    .iter()
+                    .filter(|entry| entry.is_loaded())
+                    .map(|entry| (*key, entry.clone()))
+            })
+            .collect()
+    }
+
+    /// Evict loaded programs selected by `strategy` until at most
+    /// `shrink_to` of `MAX_LOADED_ENTRY_COUNT` remain.
+    pub fn evict<S: EvictionStrategy>(&mut self, strategy: &S, shrink_to: Percentage, now: Slot) {
+        let mut candidates = self.get_flattened_entries();
+        let num_to_evict = candidates
+            .len()
+            .saturating_sub(shrink_to.apply_to(MAX_LOADED_ENTRY_COUNT));
+        if num_to_evict == 0 {
+            return;
+        }
+        for (key, entry) in strategy.select_victims(&mut candidates, num_to_evict, now) {
+            self.unload_program_entry(&key, &entry);
+        }
+    }
+
+    pub fn evict_using_2s_random_selection(&mut self, shrink_to: Percentage, now: Slot) {
+        self.evict(&TwoRandomChoices, shrink_to, now);
+    }
+
+    /// Insert every program version modified by a transaction batch.
+    pub fn merge(&mut self, tx_batch_cache: &LoadedProgramsForTxBatch) {
+        for (key, entry) in tx_batch_cache.entries.iter() {
+            self.assign_program(*key, entry.clone());
+        }
+    }
+
+    fn unload_program_entry(&mut self, key: &Pubkey, entry: &Arc<LoadedProgram>) {
+        let Some(second_level) = self.entries.get_mut(key) else {
+            return;
+        };
+        let Some(candidate) = second_level
+            .slot_versions
+            .iter_mut()
+            .find(|at| Arc::ptr_eq(at, entry))
+        else {
+            return;
+        };
+        let Some(unloaded) = entry.to_unloaded() else {
+            return;
+        };
+        *candidate = Arc::new(unloaded);
+        if entry.tx_usage_counter.load(Ordering::Relaxed) <= 1 {
+            self.stats.one_hit_wonders.fetch_add(1, Ordering::Relaxed);
+        }
+        *self.stats.evictions.entry(*key).or_insert(0) += 1;
+    }
+
+    /// The environments programs are compiled against in `epoch`.
+    pub fn get_environments_for_epoch(&self, epoch: Epoch) -> &ProgramRuntimeEnvironments {
+        if epoch != self.latest_root_epoch {
+            if let Some(upcoming_environments) = self.upcoming_environments.as_ref() {
+                return upcoming_environments;
+            }
+        }
+        &self.environments
+    }
+
+    fn matches_environment(
+        entry: &Arc<LoadedProgram>,
+        environments: &ProgramRuntimeEnvironments,
+    ) -> bool {
+        let Some(environment) = entry.program.get_environment() else {
+            return true;
+        };
+        Arc::ptr_eq(environment, &environments.program_runtime_v1)
+            || Arc::ptr_eq(environment, &environments.program_runtime_v2)
+    }
+
+    fn matches_criteria(
+        program: &Arc<LoadedProgram>,
+        criteria: &LoadedProgramMatchCriteria,
+    ) -> bool {
+        match criteria {
+            LoadedProgramMatchCriteria::DeployedOnOrAfterSlot(slot) => {
+                program.deployment_slot >= *slot
+            }
+            LoadedProgramMatchCriteria::Tombstone => program.is_tombstone(),
+            LoadedProgramMatchCriteria::NoCriteria => true,
+        }
+    }
+}
+
 pub struct LoadedProgramsForTxBatch {
     pub entries: HashMap<Pubkey, Arc<LoadedProgram>>,
     pub slot: Slot,
@@ -86,3 +767,549 @@ pub struct LoadedProgramsForTxBatch {
     pub latest_root_epoch: Epoch,
     pub hit_max_limit: bool,
 }
+
+impl LoadedProgramsForTxBatch {
+    pub fn new(
+        slot: Slot,
+        environments: ProgramRuntimeEnvironments,
+        upcoming_environments: Option<ProgramRuntimeEnvironments>,
+        latest_root_epoch: Epoch,
+    ) -> Self {
+        Self {
+            entries: HashMap::new(),
+            slot,
+            environments,
+            upcoming_environments,
+            latest_root_epoch,
+            hit_max_limit: false,
+        }
+    }
+
+    /// Create an empty batch view for `slot` in `epoch`, using the cache's
+    /// environments.
+    pub fn new_from_cache<FG: ForkGraph>(
+        slot: Slot,
+        epoch: Epoch,
+        cache: &ProgramCache<FG>,
+    ) -> Self {
+        Self::new(
+            slot,
+            cache.get_environments_for_epoch(epoch).clone(),
+            cache.upcoming_environments.clone(),
+            cache.latest_root_epoch,
+        )
+    }
+
+    /// The environments programs are compiled against in `epoch`.
+    pub fn get_environments_for_epoch(&self, epoch: Epoch) -> &ProgramRuntimeEnvironments {
+        if epoch != self.latest_root_epoch {
+            if let Some(upcoming_environments) = self.upcoming_environments.as_ref() {
+                return upcoming_environments;
+            }
+        }
+        &self.environments
+    }
+
+    /// Insert a program version, returning whether an entry was replaced.
+    pub fn replenish(
+        &mut self,
+        key: Pubkey,
+        entry: Arc<LoadedProgram>,
+    ) -> (bool, Arc<LoadedProgram>) {
+        (self.entries.insert(key, entry.clone()).is_some(), entry)
+    }
+
+    /// Find the program version visible to the batch.
+    pub fn find(&self, key: &Pubkey) -> Option<Arc<LoadedProgram>> {
+        self.entries.get(key).map(|entry| {
+            if entry.is_implicit_delay_visibility_tombstone(self.slot) {
+                // Found a program entry on the current fork, but it's not
+                // effective yet. It indicates that the program has delayed
+                // visibility. Return the tombstone to reflect that.
+                Arc::new(LoadedProgram::new_tombstone(
+                    entry.deployment_slot,
+                    LoadedProgramType::DelayVisibility,
+                ))
+            } else {
+                entry.clone()
+            }
+        })
+    }
+
+    /// Apply the program versions modified by a transaction.
+    pub fn merge(&mut self, other: &Self) {
+        for (key, entry) in other.entries.iter() {
+            self.replenish(*key, entry.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{eviction::LeastRecentlyUsed, fork_graph::InMemoryForkGraph},
+        std::{any::Any, thread},
+    };
+
+    struct TestExecutable(ProgramRuntimeEnvironment);
+
+    impl Executable for TestExecutable {
+        fn get_loader(&self) -> &ProgramRuntimeEnvironment {
+            &self.0
+        }
+
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+    }
+
+    //     0
+    //     |
+    //     1
+    //    / \
+    //   2   3
+    //   |   |
+    //   4   5
+    fn new_cache() -> ProgramCache<InMemoryForkGraph> {
+        let mut fork_graph = InMemoryForkGraph::new(0, EpochSchedule::default());
+        for (slot, parent) in [(1, 0), (2, 1), (3, 1), (4, 2), (5, 3)] {
+            assert!(fork_graph.insert_slot(slot, parent));
+        }
+        let mut cache = ProgramCache::new(0, 0);
+        cache.set_fork_graph(Arc::new(RwLock::new(fork_graph)));
+        cache
+    }
+
+    fn new_program(
+        cache: &ProgramCache<InMemoryForkGraph>,
+        deployment_slot: Slot,
+        effective_slot: Slot,
+    ) -> Arc<LoadedProgram> {
+        let executable = Arc::new(TestExecutable(
+            cache.environments.program_runtime_v1.clone(),
+        ));
+        Arc::new(LoadedProgram {
+            program: LoadedProgramType::LegacyV1(executable),
+            account_size: 0,
+            deployment_slot,
+            effective_slot,
+            tx_usage_counter: AtomicU64::default(),
+            ix_usage_counter: AtomicU64::default(),
+            latest_access_slot: AtomicU64::default(),
+        })
+    }
+
+    fn deploy(cache: &mut ProgramCache<InMemoryForkGraph>, key: Pubkey, deployment_slot: Slot) {
+        let program = new_program(
+            cache,
+            deployment_slot,
+            deployment_slot + DELAY_VISIBILITY_SLOT_OFFSET,
+        );
+        cache.assign_program(key, program);
+    }
+
+    fn deployment_slots(cache: &ProgramCache<InMemoryForkGraph>, key: &Pubkey) -> Vec<Slot> {
+        cache
+            .entries
+            .get(key)
+            .map(|second_level| {
+                second_level
+                    .slot_versions
+                    .iter()
+                    .map(|entry| entry.deployment_slot)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Extract `keys` for a batch at `slot`, returning the batch view, the
+    /// keys left to load and the claimed loading task.
+    fn extract(
+        cache: &mut ProgramCache<InMemoryForkGraph>,
+        slot: Slot,
+        keys: &[(Pubkey, LoadedProgramMatchCriteria)],
+    ) -> (LoadedProgramsForTxBatch, Vec<Pubkey>, Option<(Pubkey, u64)>) {
+        let mut search_for = keys
+            .iter()
+            .map(|(key, match_criteria)| (*key, (match_criteria.clone(), 1)))
+            .collect();
+        let mut loaded_programs_for_tx_batch =
+            LoadedProgramsForTxBatch::new_from_cache(slot, 0, cache);
+        let task = cache.extract(&mut search_for, &mut loaded_programs_for_tx_batch, true);
+        let missing = search_for.into_iter().map(|(key, _)| key).collect();
+        (loaded_programs_for_tx_batch, missing, task)
+    }
+
+    #[test]
+    fn test_extract_fork_visibility() {
+        let mut cache = new_cache();
+        let key = Pubkey::new_unique();
+        deploy(&mut cache, key, 0);
+        deploy(&mut cache, key, 2);
+        deploy(&mut cache, key, 3);
+        let criteria = [(key, LoadedProgramMatchCriteria::NoCriteria)];
+
+        // Each batch sees the newest version effective on its own fork.
+        for (batch_slot, deployment_slot) in [(1, 0), (4, 2), (5, 3)] {
+            let (batch, missing, task) = extract(&mut cache, batch_slot, &criteria);
+            assert!(missing.is_empty());
+            assert!(task.is_none());
+            let entry = batch.find(&key).unwrap();
+            assert_eq!(entry.deployment_slot, deployment_slot);
+            assert!(entry.is_loaded());
+            assert_eq!(entry.latest_access_slot.load(Ordering::Relaxed), batch_slot);
+        }
+
+        // A version is invisible in its deployment slot.
+        let (batch, missing, _task) = extract(&mut cache, 2, &criteria);
+        assert!(missing.is_empty());
+        assert!(matches!(
+            batch.find(&key).unwrap().program,
+            LoadedProgramType::DelayVisibility
+        ));
+
+        assert_eq!(cache.stats.hits.load(Ordering::Relaxed), 4);
+        assert_eq!(cache.stats.misses.load(Ordering::Relaxed), 0);
+        assert_eq!(cache.stats.hits_by_program[&key], 4);
+        assert!(cache.stats.misses_by_program.is_empty());
+    }
+
+    #[test]
+    fn test_extract_match_criteria() {
+        let mut cache = new_cache();
+        let key = Pubkey::new_unique();
+        deploy(&mut cache, key, 2);
+
+        let (batch, missing, _task) = extract(
+            &mut cache,
+            4,
+            &[(key, LoadedProgramMatchCriteria::DeployedOnOrAfterSlot(2))],
+        );
+        assert!(batch.find(&key).is_some());
+        assert!(missing.is_empty());
+
+        // The program was redeployed since the cached version.
+        let (batch, missing, task) = extract(
+            &mut cache,
+            4,
+            &[(key, LoadedProgramMatchCriteria::DeployedOnOrAfterSlot(3))],
+        );
+        assert!(batch.find(&key).is_none());
+        assert_eq!(missing, vec![key]);
+        assert_eq!(task, Some((key, 1)));
+        cache.finish_cooperative_loading_task(4, key, new_program(&cache, 3, 3));
+
+        // The program was closed since the cached version.
+        let (batch, missing, _task) = extract(
+            &mut cache,
+            4,
+            &[(key, LoadedProgramMatchCriteria::Tombstone)],
+        );
+        assert!(batch.find(&key).is_none());
+        assert_eq!(missing, vec![key]);
+    }
+
+    #[test]
+    fn test_cooperative_loading() {
+        let mut cache = new_cache();
+        let key1 = Pubkey::new_unique();
+        let key2 = Pubkey::new_unique();
+        let criteria = [
+            (key1, LoadedProgramMatchCriteria::NoCriteria),
+            (key2, LoadedProgramMatchCriteria::NoCriteria),
+        ];
+
+        // The first batch claims one of the missing programs.
+        let (_batch, missing, task) = extract(&mut cache, 4, &criteria);
+        assert_eq!(missing.len(), 2);
+        let (claimed_key, _count) = task.unwrap();
+        // The second batch claims the other one.
+        let (_batch, missing, task) = extract(&mut cache, 5, &criteria);
+        assert_eq!(missing.len(), 2);
+        let (other_key, _count) = task.unwrap();
+        assert_ne!(claimed_key, other_key);
+        // Nothing is left to claim.
+        let (_batch, _missing, task) = extract(&mut cache, 4, &criteria);
+        assert!(task.is_none());
+        assert_eq!(cache.stats.misses.load(Ordering::Relaxed), 6);
+        assert_eq!(cache.stats.misses_by_program[&key1], 3);
+        assert_eq!(cache.stats.misses_by_program[&key2], 3);
+
+        let cookie = cache.loading_task_waiter.cookie();
+        assert!(!cache.finish_cooperative_loading_task(4, claimed_key, new_program(&cache, 0, 0)));
+        assert_ne!(cache.loading_task_waiter.cookie(), cookie);
+        assert!(cache.entries[&claimed_key]
+            .cooperative_loading_lock
+            .is_none());
+
+        let (batch, missing, task) = extract(&mut cache, 4, &criteria);
+        assert!(batch.find(&claimed_key).is_some());
+        assert_eq!(missing, vec![other_key]);
+        assert!(task.is_none());
+    }
+
+    #[test]
+    fn test_loading_task_waiter() {
+        let waiter = Arc::new(LoadingTaskWaiter::default());
+        let cookie = waiter.cookie();
+        let handle = thread::spawn({
+            let waiter = Arc::clone(&waiter);
+            move || waiter.wait(cookie)
+        });
+        waiter.notify();
+        assert_ne!(handle.join().unwrap(), cookie);
+    }
+
+    #[test]
+    fn test_finish_cooperative_loading_task_lost_insertion() {
+        let mut cache = new_cache();
+        let key = Pubkey::new_unique();
+        let (_batch, _missing, task) = extract(
+            &mut cache,
+            4,
+            &[(key, LoadedProgramMatchCriteria::NoCriteria)],
+        );
+        assert_eq!(task, Some((key, 1)));
+        // Deployed on a fork the loading batch is not on.
+        cache.finish_cooperative_loading_task(4, key, new_program(&cache, 3, 3));
+        assert_eq!(cache.stats.lost_insertions.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_evict() {
+        let mut cache = new_cache();
+        let recent_key = Pubkey::new_unique();
+        let old_key = Pubkey::new_unique();
+        let tombstone_key = Pubkey::new_unique();
+        deploy(&mut cache, recent_key, 0);
+        deploy(&mut cache, old_key, 0);
+        cache.assign_program(
+            tombstone_key,
+            Arc::new(LoadedProgram::new_tombstone(0, LoadedProgramType::Closed)),
+        );
+        cache.entries[&recent_key].slot_versions[0].update_access_slot(4);
+        assert_eq!(cache.get_flattened_entries().len(), 2);
+
+        // Nothing is evicted while the cache is below its target size.
+        cache.evict(&LeastRecentlyUsed, Percentage::from(90), 4);
+        assert_eq!(cache.get_flattened_entries().len(), 2);
+
+        // Evicted programs are unloaded, keeping their usage statistics.
+        cache.evict(&LeastRecentlyUsed, Percentage::from(0), 4);
+        assert!(cache.get_flattened_entries().is_empty());
+        for key in [recent_key, old_key] {
+            let entry = &cache.entries[&key].slot_versions[0];
+            assert!(matches!(entry.program, LoadedProgramType::Unloaded(_)));
+            assert_eq!(cache.stats.evictions[&key], 1);
+        }
+        assert_eq!(
+            cache.entries[&recent_key].slot_versions[0]
+                .latest_access_slot
+                .load(Ordering::Relaxed),
+            4
+        );
+        assert!(cache.entries[&tombstone_key].slot_versions[0].is_tombstone());
+        assert_eq!(cache.stats.one_hit_wonders.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn test_evict_using_2s_random_selection() {
+        let mut cache = new_cache();
+        let keys = (0..10).map(|_| Pubkey::new_unique()).collect::<Vec<_>>();
+        for key in &keys {
+            deploy(&mut cache, *key, 0);
+        }
+        cache.evict_using_2s_random_selection(Percentage::from(0), 0);
+        assert!(cache.get_flattened_entries().is_empty());
+        assert_eq!(cache.stats.evictions.len(), keys.len());
+    }
+
+    #[test]
+    fn test_prune() {
+        let mut cache = new_cache();
+        let key1 = Pubkey::new_unique();
+        let key2 = Pubkey::new_unique();
+        deploy(&mut cache, key1, 0);
+        deploy(&mut cache, key1, 2);
+        deploy(&mut cache, key1, 3);
+        deploy(&mut cache, key1, 4);
+        deploy(&mut cache, key2, 5);
+
+        cache.prune(2, 0);
+        assert_eq!(cache.latest_root_slot, 2);
+        // The versions on the other fork are dropped. The version below the
+        // root stays, since the version deployed in the root only becomes
+        // effective in the next slot.
+        assert_eq!(deployment_slots(&cache, &key1), vec![0, 2, 4]);
+        assert!(!cache.entries.contains_key(&key2));
+        assert_eq!(cache.stats.prunes_orphan.load(Ordering::Relaxed), 0);
+
+        // Once the next version is rooted, the superseded one is dropped.
+        cache.prune(4, 0);
+        assert_eq!(deployment_slots(&cache, &key1), vec![2, 4]);
+        assert_eq!(cache.stats.prunes_orphan.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_prune_before_set_root() {
+        let mut cache = new_cache();
+        let key = Pubkey::new_unique();
+        deploy(&mut cache, key, 2);
+
+        cache.prune(4, 0);
+        cache
+            .fork_graph
+            .as_ref()
+            .unwrap()
+            .write()
+            .unwrap()
+            .set_root(4);
+        // The version deployed between the previous and the new root
+        // survives both, and stays visible on the rooted fork.
+        assert_eq!(deployment_slots(&cache, &key), vec![2]);
+        let (batch, missing, _task) = extract(
+            &mut cache,
+            4,
+            &[(key, LoadedProgramMatchCriteria::NoCriteria)],
+        );
+        assert!(missing.is_empty());
+        assert_eq!(batch.find(&key).unwrap().deployment_slot, 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "the program cache must be pruned before the fork graph root moves")]
+    fn test_prune_after_set_root() {
+        let mut cache = new_cache();
+        deploy(&mut cache, Pubkey::new_unique(), 2);
+        cache
+            .fork_graph
+            .as_ref()
+            .unwrap()
+            .write()
+            .unwrap()
+            .set_root(4);
+        cache.prune(4, 0);
+    }
+
+    #[test]
+    fn test_recompile_for_upcoming_environments() {
+        let mut cache = new_cache();
+        {
+            let mut fork_graph = cache.fork_graph.as_ref().unwrap().write().unwrap();
+            let mut parent = 5;
+            for slot in 16..=32 {
+                assert!(fork_graph.insert_slot(slot, parent));
+                parent = slot;
+            }
+        }
+        let epoch_schedule = EpochSchedule::custom(32, 32, false);
+        let keys = (0..20).map(|_| Pubkey::new_unique()).collect::<Vec<_>>();
+        for (usage, key) in keys.iter().enumerate() {
+            deploy(&mut cache, *key, 0);
+            cache.entries[key].slot_versions[0]
+                .tx_usage_counter
+                .store(usage as u64, Ordering::Relaxed);
+        }
+        let upcoming_environments = ProgramRuntimeEnvironments::default();
+        cache.set_upcoming_environments(upcoming_environments.clone(), 0);
+        assert_eq!(cache.programs_to_recompile.len(), keys.len());
+
+        let mut recompiled_keys = Vec::new();
+        for slot in 0..32 {
+            let num_recompiled = cache.recompile_for_upcoming_environments(
+                slot,
+                &epoch_schedule,
+                |key, program, environments| {
+                    recompiled_keys.push(*key);
+                    let executable =
+                        Arc::new(TestExecutable(environments.program_runtime_v1.clone()));
+                    Some(LoadedProgram {
+                        program: LoadedProgramType::LegacyV1(executable),
+                        account_size: program.account_size,
+                        deployment_slot: program.deployment_slot,
+                        effective_slot: program.effective_slot,
+                        tx_usage_counter: AtomicU64::default(),
+                        ix_usage_counter: AtomicU64::default(),
+                        latest_access_slot: AtomicU64::default(),
+                    })
+                },
+            );
+            // Recompilation is spread across the last 16 slots of the epoch.
+            assert_eq!(
+                num_recompiled > 0,
+                ProgramCache::<InMemoryForkGraph>::is_in_recompilation_phase(slot, &epoch_schedule)
+            );
+            assert_eq!(slot < 16, num_recompiled == 0);
+        }
+        // The queue is drained by the epoch boundary, most used first.
+        assert!(cache.programs_to_recompile.is_empty());
+        assert_eq!(recompiled_keys.len(), keys.len());
+        assert_eq!(recompiled_keys[0], keys[19]);
+        assert_eq!(recompiled_keys[19], keys[0]);
+
+        for (usage, key) in keys.iter().enumerate() {
+            let slot_versions = &cache.entries[key].slot_versions;
+            assert_eq!(slot_versions.len(), 2);
+            assert_eq!(slot_versions[1].effective_slot, 32);
+            assert_eq!(
+                slot_versions[1].tx_usage_counter.load(Ordering::Relaxed),
+                usage as u64
+            );
+        }
+
+        // The recompiled versions become effective in the first slot of the
+        // new epoch.
+        let criteria = keys
+            .iter()
+            .map(|key| (*key, LoadedProgramMatchCriteria::NoCriteria))
+            .collect::<Vec<_>>();
+        let mut search_for = criteria
+            .iter()
+            .map(|(key, match_criteria)| (*key, (match_criteria.clone(), 1)))
+            .collect();
+        let mut batch = LoadedProgramsForTxBatch::new_from_cache(31, 0, &cache);
+        cache.extract(&mut search_for, &mut batch, true);
+        assert!(search_for.is_empty());
+        for key in &keys {
+            assert_eq!(batch.find(key).unwrap().effective_slot, 1);
+        }
+        let mut search_for = criteria
+            .iter()
+            .map(|(key, match_criteria)| (*key, (match_criteria.clone(), 1)))
+            .collect();
+        let mut batch = LoadedProgramsForTxBatch::new_from_cache(32, 1, &cache);
+        cache.extract(&mut search_for, &mut batch, true);
+        assert!(search_for.is_empty());
+        for key in &keys {
+            let entry = batch.find(key).unwrap();
+            assert_eq!(entry.effective_slot, 32);
+            assert!(Arc::ptr_eq(
+                entry.program.get_environment().unwrap(),
+                &upcoming_environments.program_runtime_v1
+            ));
+        }
+    }
+
+    #[test]
+    fn test_prune_rotates_environments() {
+        let mut cache = new_cache();
+        let key = Pubkey::new_unique();
+        deploy(&mut cache, key, 0);
+        let upcoming_environments = ProgramRuntimeEnvironments::default();
+        cache.set_upcoming_environments(upcoming_environments.clone(), 1);
+        assert_eq!(cache.programs_to_recompile.len(), 1);
+
+        cache.prune(1, 1);
+        assert_eq!(cache.latest_root_epoch, 1);
+        assert!(cache.upcoming_environments.is_none());
+        assert!(cache.programs_to_recompile.is_empty());
+        assert!(Arc::ptr_eq(
+            &cache.environments.program_runtime_v1,
+            &upcoming_environments.program_runtime_v1
+        ));
+        // The version compiled for the previous environments is dropped.
+        assert!(!cache.entries.contains_key(&key));
+        assert_eq!(cache.stats.prunes_environment.load(Ordering::Relaxed), 1);
+    }
+}
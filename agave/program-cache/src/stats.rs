@@ -0,0 +1,138 @@
+//! Program cache statistics.
+
+use {
+    log::{debug, log_enabled, trace, Level},
+    solana_sdk::{clock::Slot, pubkey::Pubkey},
+    std::{
+        collections::HashMap,
+        sync::atomic::{AtomicU64, Ordering},
+    },
+};
+
+/// Counters describing the behavior of a `ProgramCache`.
+#[derive(Debug, Default)]
+pub struct Stats {
+    /// A program was found in the cache.
+    pub hits: AtomicU64,
+    /// A program was not found in the cache and had to be loaded.
+    pub misses: AtomicU64,
+    /// Hits, per program.
+    pub hits_by_program: HashMap<Pubkey, u64>,
+    /// Misses, per program.
+    pub misses_by_program: HashMap<Pubkey, u64>,
+    /// Evictions, per program.
+    pub evictions: HashMap<Pubkey, u64>,
+    /// A program was reloaded after having been evicted.
+    pub reloads: AtomicU64,
+    /// A program version was inserted.
+    pub insertions: AtomicU64,
+    /// A program was loaded by another thread's cooperative-loading task
+    /// after this one had already started, so the version this one loaded is
+    /// not visible to the fork which requested it.
+    pub lost_insertions: AtomicU64,
+    /// A program version was inserted where one already existed.
+    pub replacements: AtomicU64,
+    /// An evicted program had been used by at most one transaction.
+    pub one_hit_wonders: AtomicU64,
+    /// A program version was pruned because it became unreachable.
+    pub prunes_orphan: AtomicU64,
+    /// A program version was pruned because it was compiled for the
+    /// environments of a previous epoch.
+    pub prunes_environment: AtomicU64,
+}
+
+/// A point-in-time copy of `Stats`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StatsSnapshot {
+    pub hits: u64,
+    pub misses: u64,
+    pub hits_by_program: HashMap<Pubkey, u64>,
+    pub misses_by_program: HashMap<Pubkey, u64>,
+    pub evictions: HashMap<Pubkey, u64>,
+    pub reloads: u64,
+    pub insertions: u64,
+    pub lost_insertions: u64,
+    pub replacements: u64,
+    pub one_hit_wonders: u64,
+    pub prunes_orphan: u64,
+    pub prunes_environment: u64,
+}
+
+impl StatsSnapshot {
+    /// Evictions across every program.
+    pub fn total_evictions(&self) -> u64 {
+        self.evictions.values().sum()
+    }
+}
+
+impl Stats {
+    pub fn snapshot(&self) -> StatsSnapshot {
+        StatsSnapshot {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            hits_by_program: self.hits_by_program.clone(),
+            misses_by_program: self.misses_by_program.clone(),
+            evictions: self.evictions.clone(),
+            reloads: self.reloads.load(Ordering::Relaxed),
+            insertions: self.insertions.load(Ordering::Relaxed),
+            lost_insertions: self.lost_insertions.load(Ordering::Relaxed),
+            replacements: self.replacements.load(Ordering::Relaxed),
+            one_hit_wonders: self.one_hit_wonders.load(Ordering::Relaxed),
+            prunes_orphan: self.prunes_orphan.load(Ordering::Relaxed),
+            prunes_environment: self.prunes_environment.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Log the statistics gathered up to `slot`, including the per-program
+    /// hits, misses and evictions at trace level.
+    pub fn log(&self, slot: Slot) {
+        let snapshot = self.snapshot();
+        debug!(
+            "Loaded Programs Cache Stats -- Slot: {} Hits: {}, Misses: {}, Evictions: {}, \
+             Reloads: {}, Insertions: {} Lost-Insertions: {}, Replacements: {}, \
+             One-Hit-Wonders: {}, Prunes-Orphan: {}, Prunes-Environment: {}",
+            slot,
+            snapshot.hits,
+            snapshot.misses,
+            snapshot.total_evictions(),
+            snapshot.reloads,
+            snapshot.insertions,
+            snapshot.lost_insertions,
+            snapshot.replacements,
+            snapshot.one_hit_wonders,
+            snapshot.prunes_orphan,
+            snapshot.prunes_environment,
+        );
+        if log_enabled!(Level::Trace) {
+            log_per_program("Hit", snapshot.hits_by_program);
+            log_per_program("Miss", snapshot.misses_by_program);
+            log_per_program("Eviction", snapshot.evictions);
+        }
+    }
+
+    pub fn reset(&mut self) {
+        *self = Stats::default();
+    }
+}
+
+/// Log `counts` at trace level, most frequent program first.
+fn log_per_program(kind: &str, counts: HashMap<Pubkey, u64>) {
+    if counts.is_empty() {
+        return;
+    }
+    let mut counts = counts.into_iter().collect::<Vec<_>>();
+    counts.sort_by_key(|(_key, count)| *count);
+    let details = counts
+        .into_iter()
+        .rev()
+        .map(|(key, count)| format!("  {:<44}  {}", key.to_string(), count))
+        .collect::<Vec<_>>()
+        .join("\n");
+    trace!(
+        "{} Details:\n  {:<44}  {}\n{}",
+        kind,
+        "Program",
+        "Count",
+        details
+    );
+}
@@ -4,8 +4,15 @@ mod batch;
 
 use {
     crate::batch::AgaveTransactionBatch,
-    solana_runtime::specification::{LoadAndExecuteTransactionsOutput, ValidatorRuntime},
-    solana_svm::specification::TransactionBatchProcessor,
+    solana_runtime::specification::{
+        LoadAndExecuteTransactionsOutput, TransactionBatch, ValidatorRuntime,
+    },
+    solana_sdk::transaction::TransactionError,
+    solana_svm::specification::{
+        LoadAndExecuteSanitizedTransactionsOutput, TransactionBatchProcessor,
+        TransactionExecutionResult,
+    },
+    std::borrow::Cow,
 };
 
 /// The Agave Validator Runtime.
@@ -25,21 +32,85 @@ impl<'a, BP: TransactionBatchProcessor> ValidatorRuntime<AgaveTransactionBatch<'
     /// Load and execute a batch of transactions.
     fn load_and_execute_transactions(
         &self,
-        _batch: &AgaveTransactionBatch,
+        batch: &AgaveTransactionBatch,
+        max_age: usize,
     ) -> LoadAndExecuteTransactionsOutput {
-        /*
-         * MOCK.
-         */
-        let _batch_processor = self.batch_processor();
-        //
+        let sanitized_txs = batch.sanitized_txs();
+        // Only the transactions whose accounts were locked are executed.
+        let locked_txs = if batch.lock_results.iter().all(Result::is_ok) {
+            Cow::Borrowed(sanitized_txs)
+        } else {
+            Cow::Owned(
+                sanitized_txs
+                    .iter()
+                    .zip(batch.lock_results.iter())
+                    .filter(|(_tx, lock_result)| lock_result.is_ok())
+                    .map(|(tx, _lock_result)| tx.clone())
+                    .collect::<Vec<_>>(),
+            )
+        };
+        let LoadAndExecuteSanitizedTransactionsOutput {
+            loaded_transactions,
+            execution_results,
+        } = self
+            .batch_processor()
+            .load_and_execute_sanitized_transactions(&locked_txs, max_age);
+        let mut loaded_transactions = loaded_transactions.into_iter();
+        let mut execution_results = execution_results.into_iter();
+        let (loaded_transactions, execution_results): (Vec<_>, Vec<_>) = batch
+            .lock_results
+            .iter()
+            .map(|lock_result| match lock_result {
+                Ok(()) => (
+                    loaded_transactions.next().unwrap(),
+                    execution_results.next().unwrap(),
+                ),
+                Err(err) => (
+                    (Err(err.clone()), None),
+                    TransactionExecutionResult::NotExecuted(err.clone()),
+                ),
+            })
+            .unzip();
+
+        let retryable_transaction_indexes = execution_results
+            .iter()
+            .enumerate()
+            .filter_map(|(index, execution_result)| {
+                let is_retryable = matches!(
+                    execution_result,
+                    TransactionExecutionResult::NotExecuted(
+                        TransactionError::AccountInUse | TransactionError::ProgramCacheHitMaxLimit
+                    )
+                );
+                is_retryable.then_some(index)
+            })
+            .collect();
+
+        let mut executed_transactions_count = 0;
+        let mut executed_non_vote_transactions_count = 0;
+        let mut executed_with_successful_result_count = 0;
+        let mut signature_count = 0;
+        for (tx, execution_result) in sanitized_txs.iter().zip(execution_results.iter()) {
+            if let TransactionExecutionResult::Executed { details } = execution_result {
+                executed_transactions_count += 1;
+                if !tx.is_simple_vote_transaction() {
+                    executed_non_vote_transactions_count += 1;
+                }
+                if details.status.is_ok() {
+                    executed_with_successful_result_count += 1;
+                }
+                signature_count += u64::from(tx.message().header().num_required_signatures);
+            }
+        }
+
         LoadAndExecuteTransactionsOutput {
-            loaded_transactions: vec![],
-            execution_results: vec![],
-            retryable_transaction_indexes: vec![],
-            executed_transactions_count: 0,
-            executed_non_vote_transactions_count: 0,
-            executed_with_successful_result_count: 0,
-            signature_count: 0,
+            loaded_transactions,
+            execution_results,
+            retryable_transaction_indexes,
+            executed_transactions_count,
+            executed_non_vote_transactions_count,
+            executed_with_successful_result_count,
+            signature_count,
         }
     }
 }
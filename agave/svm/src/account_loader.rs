@@ -0,0 +1,534 @@
+use {
+    crate::{
+        account_rent_state::{check_rent_state_with_account, RentState},
+        callbacks::TransactionProcessingCallback,
+        AccountOverrides,
+    },
+    agave_sysvar_cache::SysvarOverrides,
+    solana_compute_budget::{
+        compute_budget_processor::process_compute_budget_instructions,
+        fee_calculator::calculate_fee_details,
+    },
+    solana_sdk::{
+        account::{Account, AccountSharedData, ReadableAccount, WritableAccount},
+        account_utils::StateMut,
+        feature_set::{self, FeatureSet},
+        fee::FeeStructure,
+        message::SanitizedMessage,
+        native_loader,
+        nonce::{state::Versions as NonceVersions, State as NonceState},
+        nonce_info::NonceFull,
+        pubkey::Pubkey,
+        rent::RentDue,
+        rent_collector::{RentCollector, RENT_EXEMPT_RENT_EPOCH},
+        rent_debits::RentDebits,
+        system_program,
+        sysvar::{self, instructions::construct_instructions_data},
+        transaction::{self, SanitizedTransaction, TransactionError},
+        transaction_context::{IndexOfAccount, TransactionAccount},
+    },
+    solana_svm::specification::{LoadedTransaction, TransactionCheckResult, TransactionLoadResult},
+    std::{collections::HashSet, num::NonZeroUsize},
+};
+
+/// The account locks held by the transactions of a single batch.
+#[derive(Default)]
+struct AccountLocks {
+    write_locks: HashSet<Pubkey>,
+    readonly_locks: HashSet<Pubkey>,
+}
+
+impl AccountLocks {
+    /// Lock the accounts of a message, failing if any of them conflict with
+    /// locks held by an earlier transaction in the batch.
+    fn try_lock(&mut self, message: &SanitizedMessage) -> transaction::Result<()> {
+        let account_keys = message.account_keys();
+        let in_use = account_keys.iter().enumerate().any(|(i, key)| {
+            self.write_locks.contains(key)
+                || (message.is_writable(i) && self.readonly_locks.contains(key))
+        });
+        if in_use {
+            return Err(TransactionError::AccountInUse);
+        }
+        for (i, key) in account_keys.iter().enumerate() {
+            if message.is_writable(i) {
+                self.write_locks.insert(*key);
+            } else {
+                self.readonly_locks.insert(*key);
+            }
+        }
+        Ok(())
+    }
+}
+
+enum SystemAccountKind {
+    System,
+    Nonce,
+}
+
+pub(crate) fn load_accounts<CB: TransactionProcessingCallback>(
+    callbacks: &CB,
+    txs: &[SanitizedTransaction],
+    check_results: Vec<TransactionCheckResult>,
+    fee_structure: &FeeStructure,
+    account_overrides: Option<&AccountOverrides>,
+    sysvar_overrides: Option<&SysvarOverrides>,
+) -> Vec<TransactionLoadResult> {
+    let feature_set = callbacks.get_feature_set();
+    let mut account_locks = AccountLocks::default();
+    txs.iter()
+        .zip(check_results)
+        .map(|(tx, (check_result, nonce, lamports_per_signature))| {
+            if let Err(err) = check_result {
+                return (Err(err), None);
+            }
+            let message = tx.message();
+            if let Err(err) = account_locks.try_lock(message) {
+                return (Err(err), None);
+            }
+
+            // Durable nonce transactions are charged the lamports per
+            // signature stored in their nonce account.
+            let Some(lamports_per_signature) = lamports_per_signature else {
+                return (Err(TransactionError::BlockhashNotFound), None);
+            };
+
+            let fee = calculate_fee_details(
+                message,
+                fee_structure,
+                lamports_per_signature,
+                &process_compute_budget_instructions(message.program_instructions_iter())
+                    .unwrap_or_default()
+                    .into(),
+                feature_set.is_active(
+                    &feature_set::include_loaded_accounts_data_size_in_fee_calculation::id(),
+                ),
+            )
+            .total_fee();
+
+            match load_transaction_accounts(
+                callbacks,
+                tx,
+                fee,
+                &feature_set,
+                account_overrides,
+                sysvar_overrides,
+            ) {
+                Ok(loaded_transaction) => {
+                    let nonce = if let Some(nonce) = nonce {
+                        match NonceFull::from_partial(
+                            nonce,
+                            message,
+                            &loaded_transaction.accounts,
+                            &loaded_transaction.rent_debits,
+                        ) {
+                            Ok(nonce) => Some(nonce),
+                            Err(err) => return (Err(err), None),
+                        }
+                    } else {
+                        None
+                    };
+                    (Ok(loaded_transaction), nonce)
+                }
+                Err(err) => (Err(err), None),
+            }
+        })
+        .collect()
+}
+
+fn load_transaction_accounts<CB: TransactionProcessingCallback>(
+    callbacks: &CB,
+    tx: &SanitizedTransaction,
+    fee: u64,
+    feature_set: &FeatureSet,
+    account_overrides: Option<&AccountOverrides>,
+    sysvar_overrides: Option<&SysvarOverrides>,
+) -> transaction::Result<LoadedTransaction> {
+    // NOTE: this check will never fail because `tx` is sanitized
+    if tx.signatures().is_empty() && fee != 0 {
+        return Err(TransactionError::MissingSignatureForFee);
+    }
+
+    // There is no way to predict what program will execute without an error
+    // If a fee can pay for execution then the program will be scheduled
+    let mut validated_fee_payer = false;
+    let mut tx_rent = 0;
+    let message = tx.message();
+    let account_keys = message.account_keys();
+    let mut accounts_found = Vec::with_capacity(account_keys.len());
+    let mut rent_debits = RentDebits::default();
+    let rent_collector = callbacks.get_rent_collector();
+
+    let set_exempt_rent_epoch_max =
+        feature_set.is_active(&feature_set::set_exempt_rent_epoch_max::id());
+    let collect_rent = !feature_set.is_active(&feature_set::disable_rent_fees_collection::id());
+
+    let requested_loaded_accounts_data_size_limit =
+        get_requested_loaded_accounts_data_size_limit(tx)?;
+    let mut accumulated_accounts_data_size: usize = 0;
+
+    let mut accounts = account_keys
+        .iter()
+        .enumerate()
+        .map(|(i, key)| {
+            let mut account_found = true;
+            let account = if sysvar::instructions::check_id(key) {
+                construct_instructions_account(message)
+            } else {
+                let (mut account, rent) = if let Some(account_override) = account_overrides
+                    .and_then(|overrides| overrides.accounts.get(key))
+                    .or_else(|| sysvar_overrides.and_then(|overrides| overrides.get(key)))
+                {
+                    (account_override.clone(), 0)
+                } else if let Some(mut account) = callbacks.get_account_shared_data(key) {
+                    let rent_due = if !message.is_writable(i) {
+                        0
+                    } else if collect_rent {
+                        rent_collector
+                            .collect_from_existing_account(
+                                key,
+                                &mut account,
+                                set_exempt_rent_epoch_max,
+                            )
+                            .rent_amount
+                    } else {
+                        // When rent fee collection is disabled, rent-exempt
+                        // accounts are still marked as such.
+                        if set_exempt_rent_epoch_max
+                            && account.rent_epoch() != RENT_EXEMPT_RENT_EPOCH
+                            && rent_collector.get_rent_due(&account) == RentDue::Exempt
+                        {
+                            account.set_rent_epoch(RENT_EXEMPT_RENT_EPOCH);
+                        }
+                        0
+                    };
+                    (account, rent_due)
+                } else {
+                    account_found = false;
+                    let mut default_account = AccountSharedData::default();
+                    if set_exempt_rent_epoch_max {
+                        // All new accounts must be rent-exempt, so they can
+                        // skip rent collection entirely.
+                        default_account.set_rent_epoch(RENT_EXEMPT_RENT_EPOCH);
+                    }
+                    (default_account, 0)
+                };
+                accumulate_and_check_loaded_account_data_size(
+                    &mut accumulated_accounts_data_size,
+                    account.data().len(),
+                    requested_loaded_accounts_data_size_limit,
+                )?;
+
+                if !validated_fee_payer && message.is_non_loader_key(i) {
+                    validate_fee_payer(
+                        key,
+                        &mut account,
+                        i as IndexOfAccount,
+                        rent_collector,
+                        fee,
+                    )?;
+                    validated_fee_payer = true;
+                }
+
+                tx_rent += rent;
+                rent_debits.insert(key, rent, account.lamports());
+
+                account
+            };
+
+            callbacks.check_account_access(message, i, &account)?;
+
+            accounts_found.push(account_found);
+            Ok((*key, account))
+        })
+        .collect::<transaction::Result<Vec<TransactionAccount>>>()?;
+
+    if !validated_fee_payer {
+        return Err(TransactionError::AccountNotFound);
+    }
+
+    let builtins_start_index = accounts.len();
+    let program_indices = message
+        .instructions()
+        .iter()
+        .map(|instruction| {
+            let mut account_indices = Vec::new();
+            let program_index = instruction.program_id_index as usize;
+            // This command may never return error, because the transaction is sanitized
+            let (program_id, program_account) = accounts
+                .get(program_index)
+                .ok_or(TransactionError::ProgramAccountNotFound)?;
+            if native_loader::check_id(program_id) {
+                return Ok(account_indices);
+            }
+
+            let account_found = accounts_found.get(program_index).unwrap_or(&true);
+            if !account_found {
+                return Err(TransactionError::ProgramAccountNotFound);
+            }
+
+            if !program_account.executable() {
+                return Err(TransactionError::InvalidProgramForExecution);
+            }
+            account_indices.insert(0, program_index as IndexOfAccount);
+            let owner_id = *program_account.owner();
+            if native_loader::check_id(&owner_id) {
+                return Ok(account_indices);
+            }
+
+            // Load the program's loader, if not already loaded for a previous
+            // instruction.
+            let owner_index = if let Some(owner_index) = accounts[builtins_start_index..]
+                .iter()
+                .position(|(key, _)| *key == owner_id)
+            {
+                builtins_start_index.saturating_add(owner_index)
+            } else {
+                let owner_account = callbacks
+                    .get_account_shared_data(&owner_id)
+                    .ok_or(TransactionError::ProgramAccountNotFound)?;
+                if !native_loader::check_id(owner_account.owner()) || !owner_account.executable() {
+                    return Err(TransactionError::InvalidProgramForExecution);
+                }
+                accumulate_and_check_loaded_account_data_size(
+                    &mut accumulated_accounts_data_size,
+                    owner_account.data().len(),
+                    requested_loaded_accounts_data_size_limit,
+                )?;
+                accounts.push((owner_id, owner_account));
+                accounts.len().saturating_sub(1)
+            };
+            account_indices.insert(0, owner_index as IndexOfAccount);
+            Ok(account_indices)
+        })
+        .collect::<transaction::Result<Vec<Vec<IndexOfAccount>>>>()?;
+
+    Ok(LoadedTransaction {
+        accounts,
+        program_indices,
+        rent: tx_rent,
+        rent_debits,
+    })
+}
+
+/// Total accounts data a transaction can load is limited to
+///   if `set_tx_loaded_accounts_data_size` instruction is not activated or not used, then
+///     default value of 64MiB to not break anyone in Mainnet-beta today
+///   else
+///     user requested loaded accounts size.
+///     Note, requesting zero bytes will result transaction error
+fn get_requested_loaded_accounts_data_size_limit(
+    tx: &SanitizedTransaction,
+) -> transaction::Result<Option<NonZeroUsize>> {
+    let compute_budget_limits =
+        process_compute_budget_instructions(tx.message().program_instructions_iter())
+            .unwrap_or_default();
+    // sanitize against setting size limit to zero
+    NonZeroUsize::new(
+        usize::try_from(compute_budget_limits.loaded_accounts_bytes).unwrap_or_default(),
+    )
+    .map_or(
+        Err(TransactionError::InvalidLoadedAccountsDataSizeLimit),
+        |v| Ok(Some(v)),
+    )
+}
+
+/// Accumulate loaded account data size into `accumulated_accounts_data_size`.
+/// Returns TransactionErr::MaxLoadedAccountsDataSizeExceeded if
+/// `requested_loaded_accounts_data_size_limit` is specified and
+/// `accumulated_accounts_data_size` exceeds it.
+fn accumulate_and_check_loaded_account_data_size(
+    accumulated_loaded_accounts_data_size: &mut usize,
+    account_data_size: usize,
+    requested_loaded_accounts_data_size_limit: Option<NonZeroUsize>,
+) -> transaction::Result<()> {
+    if let Some(requested_loaded_accounts_data_size) = requested_loaded_accounts_data_size_limit {
+        *accumulated_loaded_accounts_data_size =
+            accumulated_loaded_accounts_data_size.saturating_add(account_data_size);
+        if *accumulated_loaded_accounts_data_size > requested_loaded_accounts_data_size.get() {
+            return Err(TransactionError::MaxLoadedAccountsDataSizeExceeded);
+        }
+    }
+    Ok(())
+}
+
+fn validate_fee_payer(
+    payer_address: &Pubkey,
+    payer_account: &mut AccountSharedData,
+    payer_index: IndexOfAccount,
+    rent_collector: &RentCollector,
+    fee: u64,
+) -> transaction::Result<()> {
+    if payer_account.lamports() == 0 {
+        return Err(TransactionError::AccountNotFound);
+    }
+    let system_account_kind =
+        get_system_account_kind(payer_account).ok_or(TransactionError::InvalidAccountForFee)?;
+    let min_balance = match system_account_kind {
+        SystemAccountKind::System => 0,
+        SystemAccountKind::Nonce => {
+            // Should we ever allow a fees charge to zero a nonce account's
+            // balance. The state MUST be set to uninitialized in that case
+            rent_collector.rent.minimum_balance(NonceState::size())
+        }
+    };
+
+    payer_account
+        .lamports()
+        .checked_sub(min_balance)
+        .and_then(|v| v.checked_sub(fee))
+        .ok_or(TransactionError::InsufficientFundsForFee)?;
+
+    let payer_pre_rent_state = RentState::from_account(payer_account, &rent_collector.rent);
+    payer_account
+        .checked_sub_lamports(fee)
+        .map_err(|_| TransactionError::InsufficientFundsForFee)?;
+
+    let payer_post_rent_state = RentState::from_account(payer_account, &rent_collector.rent);
+    check_rent_state_with_account(
+        &payer_pre_rent_state,
+        &payer_post_rent_state,
+        payer_address,
+        payer_index,
+    )
+}
+
+fn get_system_account_kind(account: &AccountSharedData) -> Option<SystemAccountKind> {
+    if !system_program::check_id(account.owner()) {
+        return None;
+    }
+    if account.data().is_empty() {
+        Some(SystemAccountKind::System)
+    } else if account.data().len() == NonceState::size() {
+        let nonce_versions: NonceVersions = account.state().ok()?;
+        match nonce_versions.state() {
+            NonceState::Uninitialized => None,
+            NonceState::Initialized(_) => Some(SystemAccountKind::Nonce),
+        }
+    } else {
+        None
+    }
+}
+
+fn construct_instructions_account(message: &SanitizedMessage) -> AccountSharedData {
+    AccountSharedData::from(Account {
+        data: construct_instructions_data(&message.decompile_instructions()),
+        owner: sysvar::id(),
+        ..Account::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        solana_sdk::{
+            account::{create_account_shared_data_for_test, from_account},
+            clock::Clock,
+            hash::Hash,
+            instruction::{AccountMeta, Instruction},
+            message::Message,
+            transaction::Transaction,
+        },
+        std::{collections::HashMap, sync::Arc},
+    };
+
+    #[derive(Default)]
+    struct TestCallbacks {
+        accounts: HashMap<Pubkey, AccountSharedData>,
+        rent_collector: RentCollector,
+    }
+
+    impl TransactionProcessingCallback for TestCallbacks {
+        fn account_matches_owners(&self, _account: &Pubkey, _owners: &[Pubkey]) -> Option<usize> {
+            None
+        }
+
+        fn get_account_shared_data(&self, pubkey: &Pubkey) -> Option<AccountSharedData> {
+            self.accounts.get(pubkey).cloned()
+        }
+
+        fn get_last_blockhash_and_lamports_per_signature(&self) -> (Hash, u64) {
+            (Hash::default(), 0)
+        }
+
+        fn get_lamports_per_signature_for_blockhash(
+            &self,
+            _blockhash: &Hash,
+            _max_age: usize,
+        ) -> Option<u64> {
+            None
+        }
+
+        fn get_rent_collector(&self) -> &RentCollector {
+            &self.rent_collector
+        }
+
+        fn get_feature_set(&self) -> Arc<FeatureSet> {
+            Arc::new(FeatureSet::all_enabled())
+        }
+    }
+
+    #[test]
+    fn test_load_accounts_sysvar_overrides() {
+        let payer = Pubkey::new_unique();
+        let mut callbacks = TestCallbacks::default();
+        callbacks.accounts.insert(
+            payer,
+            AccountSharedData::new(1_000_000, 0, &system_program::id()),
+        );
+        callbacks.accounts.insert(
+            system_program::id(),
+            native_loader::create_loadable_account_for_test("system_program"),
+        );
+        callbacks.accounts.insert(
+            sysvar::clock::id(),
+            create_account_shared_data_for_test(&Clock {
+                slot: 1,
+                ..Clock::default()
+            }),
+        );
+        let overridden_clock = Clock {
+            slot: 9,
+            ..Clock::default()
+        };
+        let mut sysvar_overrides = SysvarOverrides::default();
+        sysvar_overrides.set_sysvar(&overridden_clock);
+
+        let message = Message::new_with_blockhash(
+            &[Instruction::new_with_bytes(
+                system_program::id(),
+                &[],
+                vec![AccountMeta::new_readonly(sysvar::clock::id(), false)],
+            )],
+            Some(&payer),
+            &Hash::new_unique(),
+        );
+        let tx =
+            SanitizedTransaction::from_transaction_for_tests(Transaction::new_unsigned(message));
+        let mut load_results = load_accounts(
+            &callbacks,
+            &[tx],
+            vec![(Ok(()), None, Some(5000))],
+            &FeeStructure::default(),
+            None,
+            Some(&sysvar_overrides),
+        );
+
+        let (loaded_transaction, _nonce) = load_results.pop().unwrap();
+        let loaded_transaction = loaded_transaction.unwrap();
+        let (_key, clock_account) = loaded_transaction
+            .accounts
+            .iter()
+            .find(|(key, _account)| sysvar::clock::check_id(key))
+            .unwrap();
+        assert_eq!(
+            clock_account,
+            sysvar_overrides.get(&sysvar::clock::id()).unwrap()
+        );
+        assert_eq!(
+            from_account::<Clock, _>(clock_account),
+            Some(overridden_clock)
+        );
+    }
+}
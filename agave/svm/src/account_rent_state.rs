@@ -0,0 +1,75 @@
+use solana_sdk::{
+    account::{AccountSharedData, ReadableAccount},
+    pubkey::Pubkey,
+    rent::Rent,
+    transaction::{self, TransactionError},
+    transaction_context::IndexOfAccount,
+};
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum RentState {
+    /// account.lamports == 0
+    Uninitialized,
+    /// 0 < account.lamports < rent-exempt-minimum
+    RentPaying {
+        lamports: u64,    // account.lamports()
+        data_size: usize, // account.data().len()
+    },
+    /// account.lamports >= rent-exempt-minimum
+    RentExempt,
+}
+
+impl RentState {
+    /// Return a new RentState instance for a given account and rent.
+    pub(crate) fn from_account(account: &AccountSharedData, rent: &Rent) -> Self {
+        if account.lamports() == 0 {
+            Self::Uninitialized
+        } else if rent.is_exempt(account.lamports(), account.data().len()) {
+            Self::RentExempt
+        } else {
+            Self::RentPaying {
+                data_size: account.data().len(),
+                lamports: account.lamports(),
+            }
+        }
+    }
+
+    /// Check whether a transition from the pre_rent_state to this
+    /// state is valid.
+    pub(crate) fn transition_allowed_from(&self, pre_rent_state: &RentState) -> bool {
+        match self {
+            Self::Uninitialized | Self::RentExempt => true,
+            Self::RentPaying {
+                data_size: post_data_size,
+                lamports: post_lamports,
+            } => {
+                match pre_rent_state {
+                    Self::Uninitialized | Self::RentExempt => false,
+                    Self::RentPaying {
+                        data_size: pre_data_size,
+                        lamports: pre_lamports,
+                    } => {
+                        // Cannot remain RentPaying if resized or credited.
+                        post_data_size == pre_data_size && post_lamports <= pre_lamports
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub(crate) fn check_rent_state_with_account(
+    pre_rent_state: &RentState,
+    post_rent_state: &RentState,
+    address: &Pubkey,
+    account_index: IndexOfAccount,
+) -> transaction::Result<()> {
+    if !solana_sdk::incinerator::check_id(address)
+        && !post_rent_state.transition_allowed_from(pre_rent_state)
+    {
+        let account_index = account_index as u8;
+        Err(TransactionError::InsufficientFundsForRent { account_index })
+    } else {
+        Ok(())
+    }
+}
@@ -0,0 +1,30 @@
+//! Programs implemented natively by the SVM.
+
+mod compute_budget;
+mod system;
+
+use {crate::invoke_context::BuiltinFunction, solana_sdk::pubkey::Pubkey};
+
+/// Compute units consumed by each builtin instruction.
+pub const DEFAULT_COMPUTE_UNITS: u64 = 150;
+
+/// A builtin program, to be added to the program cache under `program_id`.
+pub struct BuiltinPrototype {
+    pub program_id: Pubkey,
+    pub name: &'static str,
+    pub entrypoint: BuiltinFunction,
+}
+
+/// The builtin programs provided by the SVM.
+pub static BUILTINS: &[BuiltinPrototype] = &[
+    BuiltinPrototype {
+        program_id: solana_sdk::system_program::id(),
+        name: "system_program",
+        entrypoint: system::process_instruction,
+    },
+    BuiltinPrototype {
+        program_id: solana_sdk::compute_budget::id(),
+        name: "compute_budget_program",
+        entrypoint: compute_budget::process_instruction,
+    },
+];
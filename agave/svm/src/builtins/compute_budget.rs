@@ -0,0 +1,15 @@
+//! The compute budget program.
+//!
+//! Its instructions are interpreted when the compute budget of a transaction
+//! is determined, so executing them does nothing.
+
+use {
+    crate::{builtins::DEFAULT_COMPUTE_UNITS, invoke_context::InvokeContext},
+    solana_sdk::instruction::InstructionError,
+};
+
+pub(crate) fn process_instruction(
+    invoke_context: &mut InvokeContext,
+) -> Result<(), InstructionError> {
+    invoke_context.consume_checked(DEFAULT_COMPUTE_UNITS)
+}
@@ -0,0 +1,1248 @@
+//! The system program, which creates accounts, assigns their owners,
+//! transfers lamports and manages durable nonce accounts.
+
+use {
+    crate::{builtins::DEFAULT_COMPUTE_UNITS, invoke_context::InvokeContext},
+    solana_sdk::{
+        instruction::InstructionError,
+        nonce::{
+            self,
+            state::{AuthorizeNonceError, DurableNonce, Versions},
+            State,
+        },
+        program_utils::limited_deserialize,
+        pubkey::Pubkey,
+        rent::Rent,
+        system_instruction::{SystemError, SystemInstruction, MAX_PERMITTED_DATA_LENGTH},
+        system_program,
+        sysvar::Sysvar,
+        transaction_context::{
+            BorrowedAccount, IndexOfAccount, InstructionContext, TransactionContext,
+        },
+    },
+    std::collections::HashSet,
+};
+
+#[allow(deprecated)]
+use solana_sdk::sysvar::recent_blockhashes::RecentBlockhashes;
+
+/// An account address, which may have been derived from a base address and a
+/// seed.
+struct Address {
+    address: Pubkey,
+    base: Option<Pubkey>,
+}
+
+impl Address {
+    /// Whether the account, or the base it was derived from, signed the
+    /// instruction.
+    fn is_signer(&self, signers: &HashSet<Pubkey>) -> bool {
+        signers.contains(self.base.as_ref().unwrap_or(&self.address))
+    }
+
+    /// Create an address, checking it matches the address derived from
+    /// `with_seed` if given.
+    fn create(
+        address: &Pubkey,
+        with_seed: Option<(&Pubkey, &str, &Pubkey)>,
+    ) -> Result<Self, InstructionError> {
+        let base = if let Some((base, seed, owner)) = with_seed {
+            let address_with_seed = Pubkey::create_with_seed(base, seed, owner)?;
+            // Re-derive the address, which must match the supplied address.
+            if *address != address_with_seed {
+                return Err(SystemError::AddressWithSeedMismatch.into());
+            }
+            Some(*base)
+        } else {
+            None
+        };
+        Ok(Self {
+            address: *address,
+            base,
+        })
+    }
+}
+
+pub(crate) fn process_instruction(
+    invoke_context: &mut InvokeContext,
+) -> Result<(), InstructionError> {
+    invoke_context.consume_checked(DEFAULT_COMPUTE_UNITS)?;
+    let transaction_context = &*invoke_context.transaction_context;
+    let instruction_context = transaction_context.get_current_instruction_context()?;
+    let instruction = limited_deserialize(instruction_context.get_instruction_data())?;
+    let signers = instruction_context.get_signers(transaction_context)?;
+    match instruction {
+        SystemInstruction::CreateAccount {
+            lamports,
+            space,
+            owner,
+        } => {
+            instruction_context.check_number_of_instruction_accounts(2)?;
+            let to_address = Address::create(
+                instruction_account_key(transaction_context, instruction_context, 1)?,
+                None,
+            )?;
+            create_account(
+                0,
+                1,
+                &to_address,
+                lamports,
+                space,
+                &owner,
+                &signers,
+                transaction_context,
+                instruction_context,
+            )
+        }
+        SystemInstruction::CreateAccountWithSeed {
+            base,
+            seed,
+            lamports,
+            space,
+            owner,
+        } => {
+            instruction_context.check_number_of_instruction_accounts(2)?;
+            let to_address = Address::create(
+                instruction_account_key(transaction_context, instruction_context, 1)?,
+                Some((&base, &seed, &owner)),
+            )?;
+            create_account(
+                0,
+                1,
+                &to_address,
+                lamports,
+                space,
+                &owner,
+                &signers,
+                transaction_context,
+                instruction_context,
+            )
+        }
+        SystemInstruction::Assign { owner } => {
+            instruction_context.check_number_of_instruction_accounts(1)?;
+            let mut account =
+                instruction_context.try_borrow_instruction_account(transaction_context, 0)?;
+            let address = Address::create(account.get_key(), None)?;
+            assign(&mut account, &address, &owner, &signers)
+        }
+        SystemInstruction::Transfer { lamports } => {
+            instruction_context.check_number_of_instruction_accounts(2)?;
+            transfer(0, 1, lamports, transaction_context, instruction_context)
+        }
+        SystemInstruction::TransferWithSeed {
+            lamports,
+            from_seed,
+            from_owner,
+        } => {
+            instruction_context.check_number_of_instruction_accounts(3)?;
+            transfer_with_seed(
+                0,
+                1,
+                &from_seed,
+                &from_owner,
+                2,
+                lamports,
+                transaction_context,
+                instruction_context,
+            )
+        }
+        SystemInstruction::AdvanceNonceAccount => {
+            instruction_context.check_number_of_instruction_accounts(1)?;
+            let mut account =
+                instruction_context.try_borrow_instruction_account(transaction_context, 0)?;
+            #[allow(deprecated)]
+            {
+                check_sysvar_account::<RecentBlockhashes>(
+                    transaction_context,
+                    instruction_context,
+                    1,
+                )?;
+                let recent_blockhashes =
+                    invoke_context.get_sysvar_cache().get_recent_blockhashes()?;
+                if recent_blockhashes.is_empty() {
+                    return Err(SystemError::NonceNoRecentBlockhashes.into());
+                }
+            }
+            advance_nonce_account(&mut account, &signers, invoke_context)
+        }
+        SystemInstruction::WithdrawNonceAccount(lamports) => {
+            instruction_context.check_number_of_instruction_accounts(2)?;
+            #[allow(deprecated)]
+            {
+                check_sysvar_account::<RecentBlockhashes>(
+                    transaction_context,
+                    instruction_context,
+                    2,
+                )?;
+                invoke_context.get_sysvar_cache().get_recent_blockhashes()?;
+            }
+            check_sysvar_account::<Rent>(transaction_context, instruction_context, 3)?;
+            let rent = invoke_context.get_sysvar_cache().get_rent()?;
+            withdraw_nonce_account(
+                0,
+                lamports,
+                1,
+                &rent,
+                &signers,
+                invoke_context,
+                instruction_context,
+            )
+        }
+        SystemInstruction::InitializeNonceAccount(authority) => {
+            instruction_context.check_number_of_instruction_accounts(1)?;
+            let mut account =
+                instruction_context.try_borrow_instruction_account(transaction_context, 0)?;
+            #[allow(deprecated)]
+            {
+                check_sysvar_account::<RecentBlockhashes>(
+                    transaction_context,
+                    instruction_context,
+                    1,
+                )?;
+                let recent_blockhashes =
+                    invoke_context.get_sysvar_cache().get_recent_blockhashes()?;
+                if recent_blockhashes.is_empty() {
+                    return Err(SystemError::NonceNoRecentBlockhashes.into());
+                }
+            }
+            check_sysvar_account::<Rent>(transaction_context, instruction_context, 2)?;
+            let rent = invoke_context.get_sysvar_cache().get_rent()?;
+            initialize_nonce_account(&mut account, &authority, &rent, invoke_context)
+        }
+        SystemInstruction::AuthorizeNonceAccount(authority) => {
+            instruction_context.check_number_of_instruction_accounts(1)?;
+            let mut account =
+                instruction_context.try_borrow_instruction_account(transaction_context, 0)?;
+            authorize_nonce_account(&mut account, &authority, &signers)
+        }
+        SystemInstruction::UpgradeNonceAccount => {
+            instruction_context.check_number_of_instruction_accounts(1)?;
+            let mut account =
+                instruction_context.try_borrow_instruction_account(transaction_context, 0)?;
+            if !system_program::check_id(account.get_owner()) {
+                return Err(InstructionError::InvalidAccountOwner);
+            }
+            if !account.is_writable() {
+                return Err(InstructionError::InvalidArgument);
+            }
+            let versions: Versions = account.get_state()?;
+            match versions.upgrade() {
+                None => Err(InstructionError::InvalidArgument),
+                Some(versions) => account.set_state(&versions),
+            }
+        }
+        SystemInstruction::Allocate { space } => {
+            instruction_context.check_number_of_instruction_accounts(1)?;
+            let mut account =
+                instruction_context.try_borrow_instruction_account(transaction_context, 0)?;
+            let address = Address::create(account.get_key(), None)?;
+            allocate(&mut account, &address, space, &signers)
+        }
+        SystemInstruction::AllocateWithSeed {
+            base,
+            seed,
+            space,
+            owner,
+        } => {
+            instruction_context.check_number_of_instruction_accounts(1)?;
+            let mut account =
+                instruction_context.try_borrow_instruction_account(transaction_context, 0)?;
+            let address = Address::create(account.get_key(), Some((&base, &seed, &owner)))?;
+            allocate(&mut account, &address, space, &signers)?;
+            assign(&mut account, &address, &owner, &signers)
+        }
+        SystemInstruction::AssignWithSeed { base, seed, owner } => {
+            instruction_context.check_number_of_instruction_accounts(1)?;
+            let mut account =
+                instruction_context.try_borrow_instruction_account(transaction_context, 0)?;
+            let address = Address::create(account.get_key(), Some((&base, &seed, &owner)))?;
+            assign(&mut account, &address, &owner, &signers)
+        }
+    }
+}
+
+/// The key of an instruction account.
+fn instruction_account_key<'a>(
+    transaction_context: &'a TransactionContext,
+    instruction_context: &InstructionContext,
+    instruction_account_index: IndexOfAccount,
+) -> Result<&'a Pubkey, InstructionError> {
+    transaction_context.get_key_of_account_at_index(
+        instruction_context
+            .get_index_of_instruction_account_in_transaction(instruction_account_index)?,
+    )
+}
+
+/// Check an instruction account is the account of sysvar `S`.
+fn check_sysvar_account<S: Sysvar>(
+    transaction_context: &TransactionContext,
+    instruction_context: &InstructionContext,
+    instruction_account_index: IndexOfAccount,
+) -> Result<(), InstructionError> {
+    let key = instruction_account_key(
+        transaction_context,
+        instruction_context,
+        instruction_account_index,
+    )?;
+    if !S::check_id(key) {
+        return Err(InstructionError::InvalidArgument);
+    }
+    Ok(())
+}
+
+fn allocate(
+    account: &mut BorrowedAccount,
+    address: &Address,
+    space: u64,
+    signers: &HashSet<Pubkey>,
+) -> Result<(), InstructionError> {
+    if !address.is_signer(signers) {
+        return Err(InstructionError::MissingRequiredSignature);
+    }
+    // If it looks like the account is already in use, bail.
+    if !account.get_data().is_empty() || !system_program::check_id(account.get_owner()) {
+        return Err(SystemError::AccountAlreadyInUse.into());
+    }
+    if space > MAX_PERMITTED_DATA_LENGTH {
+        return Err(SystemError::InvalidAccountDataLength.into());
+    }
+    account.set_data_length(space as usize)
+}
+
+fn assign(
+    account: &mut BorrowedAccount,
+    address: &Address,
+    owner: &Pubkey,
+    signers: &HashSet<Pubkey>,
+) -> Result<(), InstructionError> {
+    // No work to do, just return.
+    if account.get_owner() == owner {
+        return Ok(());
+    }
+    if !address.is_signer(signers) {
+        return Err(InstructionError::MissingRequiredSignature);
+    }
+    account.set_owner(&owner.to_bytes())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn create_account(
+    from_account_index: IndexOfAccount,
+    to_account_index: IndexOfAccount,
+    to_address: &Address,
+    lamports: u64,
+    space: u64,
+    owner: &Pubkey,
+    signers: &HashSet<Pubkey>,
+    transaction_context: &TransactionContext,
+    instruction_context: &InstructionContext,
+) -> Result<(), InstructionError> {
+    {
+        let mut to = instruction_context
+            .try_borrow_instruction_account(transaction_context, to_account_index)?;
+        // If it looks like the `to` account is already in use, bail.
+        if to.get_lamports() > 0 {
+            return Err(SystemError::AccountAlreadyInUse.into());
+        }
+        allocate(&mut to, to_address, space, signers)?;
+        assign(&mut to, to_address, owner, signers)?;
+    }
+    transfer(
+        from_account_index,
+        to_account_index,
+        lamports,
+        transaction_context,
+        instruction_context,
+    )
+}
+
+/// Transfer lamports from an account whose signature has already been
+/// verified.
+fn transfer_verified(
+    from_account_index: IndexOfAccount,
+    to_account_index: IndexOfAccount,
+    lamports: u64,
+    transaction_context: &TransactionContext,
+    instruction_context: &InstructionContext,
+) -> Result<(), InstructionError> {
+    let mut from = instruction_context
+        .try_borrow_instruction_account(transaction_context, from_account_index)?;
+    if !from.get_data().is_empty() {
+        return Err(InstructionError::InvalidArgument);
+    }
+    if lamports > from.get_lamports() {
+        return Err(SystemError::ResultWithNegativeLamports.into());
+    }
+    from.checked_sub_lamports(lamports)?;
+    drop(from);
+    let mut to = instruction_context
+        .try_borrow_instruction_account(transaction_context, to_account_index)?;
+    to.checked_add_lamports(lamports)
+}
+
+fn transfer(
+    from_account_index: IndexOfAccount,
+    to_account_index: IndexOfAccount,
+    lamports: u64,
+    transaction_context: &TransactionContext,
+    instruction_context: &InstructionContext,
+) -> Result<(), InstructionError> {
+    if !instruction_context.is_instruction_account_signer(from_account_index)? {
+        return Err(InstructionError::MissingRequiredSignature);
+    }
+    transfer_verified(
+        from_account_index,
+        to_account_index,
+        lamports,
+        transaction_context,
+        instruction_context,
+    )
+}
+
+/// Transfer lamports from an account derived from a base account, which must
+/// sign instead.
+#[allow(clippy::too_many_arguments)]
+fn transfer_with_seed(
+    from_account_index: IndexOfAccount,
+    from_base_account_index: IndexOfAccount,
+    from_seed: &str,
+    from_owner: &Pubkey,
+    to_account_index: IndexOfAccount,
+    lamports: u64,
+    transaction_context: &TransactionContext,
+    instruction_context: &InstructionContext,
+) -> Result<(), InstructionError> {
+    if !instruction_context.is_instruction_account_signer(from_base_account_index)? {
+        return Err(InstructionError::MissingRequiredSignature);
+    }
+    let address_from_seed = Pubkey::create_with_seed(
+        instruction_account_key(
+            transaction_context,
+            instruction_context,
+            from_base_account_index,
+        )?,
+        from_seed,
+        from_owner,
+    )?;
+    let from_key =
+        instruction_account_key(transaction_context, instruction_context, from_account_index)?;
+    if *from_key != address_from_seed {
+        return Err(SystemError::AddressWithSeedMismatch.into());
+    }
+    transfer_verified(
+        from_account_index,
+        to_account_index,
+        lamports,
+        transaction_context,
+        instruction_context,
+    )
+}
+
+fn advance_nonce_account(
+    account: &mut BorrowedAccount,
+    signers: &HashSet<Pubkey>,
+    invoke_context: &InvokeContext,
+) -> Result<(), InstructionError> {
+    if !account.is_writable() {
+        return Err(InstructionError::InvalidArgument);
+    }
+    let state: Versions = account.get_state()?;
+    match state.state() {
+        State::Initialized(data) => {
+            if !signers.contains(&data.authority) {
+                return Err(InstructionError::MissingRequiredSignature);
+            }
+            let next_durable_nonce =
+                DurableNonce::from_blockhash(&invoke_context.environment_config.blockhash);
+            if data.durable_nonce == next_durable_nonce {
+                return Err(SystemError::NonceBlockhashNotExpired.into());
+            }
+            let new_data = nonce::state::Data::new(
+                data.authority,
+                next_durable_nonce,
+                invoke_context.environment_config.lamports_per_signature,
+            );
+            account.set_state(&Versions::new(State::Initialized(new_data)))
+        }
+        State::Uninitialized => Err(InstructionError::InvalidAccountData),
+    }
+}
+
+/// Withdraw lamports from a nonce account.
+///
+/// An initialized nonce account must either keep a rent exempt balance, or
+/// be withdrawn in full, which deinitializes it.
+fn withdraw_nonce_account(
+    from_account_index: IndexOfAccount,
+    lamports: u64,
+    to_account_index: IndexOfAccount,
+    rent: &Rent,
+    signers: &HashSet<Pubkey>,
+    invoke_context: &InvokeContext,
+    instruction_context: &InstructionContext,
+) -> Result<(), InstructionError> {
+    let transaction_context = &*invoke_context.transaction_context;
+    let mut from = instruction_context
+        .try_borrow_instruction_account(transaction_context, from_account_index)?;
+    if !from.is_writable() {
+        return Err(InstructionError::InvalidArgument);
+    }
+    let state: Versions = from.get_state()?;
+    let signer = match state.state() {
+        State::Uninitialized => {
+            if lamports > from.get_lamports() {
+                return Err(InstructionError::InsufficientFunds);
+            }
+            *from.get_key()
+        }
+        State::Initialized(data) => {
+            if lamports == from.get_lamports() {
+                let durable_nonce =
+                    DurableNonce::from_blockhash(&invoke_context.environment_config.blockhash);
+                if data.durable_nonce == durable_nonce {
+                    return Err(SystemError::NonceBlockhashNotExpired.into());
+                }
+                from.set_state(&Versions::new(State::Uninitialized))?;
+            } else {
+                let min_balance = rent.minimum_balance(from.get_data().len());
+                let amount = lamports
+                    .checked_add(min_balance)
+                    .ok_or(InstructionError::InsufficientFunds)?;
+                if amount > from.get_lamports() {
+                    return Err(InstructionError::InsufficientFunds);
+                }
+            }
+            data.authority
+        }
+    };
+    if !signers.contains(&signer) {
+        return Err(InstructionError::MissingRequiredSignature);
+    }
+    from.checked_sub_lamports(lamports)?;
+    drop(from);
+    let mut to = instruction_context
+        .try_borrow_instruction_account(transaction_context, to_account_index)?;
+    to.checked_add_lamports(lamports)
+}
+
+fn initialize_nonce_account(
+    account: &mut BorrowedAccount,
+    authority: &Pubkey,
+    rent: &Rent,
+    invoke_context: &InvokeContext,
+) -> Result<(), InstructionError> {
+    if !account.is_writable() {
+        return Err(InstructionError::InvalidArgument);
+    }
+    let state: Versions = account.get_state()?;
+    match state.state() {
+        State::Uninitialized => {
+            let min_balance = rent.minimum_balance(account.get_data().len());
+            if account.get_lamports() < min_balance {
+                return Err(InstructionError::InsufficientFunds);
+            }
+            let durable_nonce =
+                DurableNonce::from_blockhash(&invoke_context.environment_config.blockhash);
+            let data = nonce::state::Data::new(
+                *authority,
+                durable_nonce,
+                invoke_context.environment_config.lamports_per_signature,
+            );
+            account.set_state(&Versions::new(State::Initialized(data)))
+        }
+        State::Initialized(_) => Err(InstructionError::InvalidAccountData),
+    }
+}
+
+fn authorize_nonce_account(
+    account: &mut BorrowedAccount,
+    authority: &Pubkey,
+    signers: &HashSet<Pubkey>,
+) -> Result<(), InstructionError> {
+    if !account.is_writable() {
+        return Err(InstructionError::InvalidArgument);
+    }
+    let state: Versions = account.get_state()?;
+    match state.authorize(signers, *authority) {
+        Ok(versions) => account.set_state(&versions),
+        Err(AuthorizeNonceError::Uninitialized) => Err(InstructionError::InvalidAccountData),
+        Err(AuthorizeNonceError::MissingRequiredSignature(_account_authority)) => {
+            Err(InstructionError::MissingRequiredSignature)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[allow(deprecated)]
+    use solana_sdk::sysvar::recent_blockhashes::IterItem;
+    use {
+        super::*,
+        crate::invoke_context::tests::mock_process_instruction,
+        solana_sdk::{
+            account::{
+                create_account_shared_data_for_test, AccountSharedData, ReadableAccount,
+                WritableAccount,
+            },
+            hash::Hash,
+            instruction::AccountMeta,
+            nonce::state::Data as NonceData,
+            system_instruction,
+            sysvar::SysvarId,
+            transaction_context::TransactionAccount,
+        },
+    };
+
+    fn process_instruction(
+        instruction: SystemInstruction,
+        transaction_accounts: Vec<TransactionAccount>,
+        instruction_accounts: Vec<AccountMeta>,
+        expected_result: Result<(), InstructionError>,
+    ) -> Vec<AccountSharedData> {
+        process_instruction_with_blockhash(
+            instruction,
+            transaction_accounts,
+            instruction_accounts,
+            Hash::default(),
+            expected_result,
+        )
+    }
+
+    fn process_instruction_with_blockhash(
+        instruction: SystemInstruction,
+        transaction_accounts: Vec<TransactionAccount>,
+        instruction_accounts: Vec<AccountMeta>,
+        blockhash: Hash,
+        expected_result: Result<(), InstructionError>,
+    ) -> Vec<AccountSharedData> {
+        mock_process_instruction(
+            &system_program::id(),
+            &bincode::serialize(&instruction).unwrap(),
+            transaction_accounts,
+            instruction_accounts,
+            expected_result,
+            |invoke_context| invoke_context.environment_config.blockhash = blockhash,
+        )
+    }
+
+    fn system_account(lamports: u64) -> AccountSharedData {
+        AccountSharedData::new(lamports, 0, &system_program::id())
+    }
+
+    fn system_error(err: SystemError) -> Result<(), InstructionError> {
+        Err(InstructionError::Custom(err as u32))
+    }
+
+    #[test]
+    fn test_create_account() {
+        let from = Pubkey::new_unique();
+        let to = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let instruction = |lamports, space| SystemInstruction::CreateAccount {
+            lamports,
+            space,
+            owner,
+        };
+        let transaction_accounts = vec![(from, system_account(100)), (to, system_account(0))];
+        let instruction_accounts = vec![AccountMeta::new(from, true), AccountMeta::new(to, true)];
+
+        let accounts = process_instruction(
+            instruction(50, 2),
+            transaction_accounts.clone(),
+            instruction_accounts.clone(),
+            Ok(()),
+        );
+        assert_eq!(accounts[0].lamports(), 50);
+        assert_eq!(accounts[1].lamports(), 50);
+        assert_eq!(accounts[1].data(), &[0, 0]);
+        assert_eq!(accounts[1].owner(), &owner);
+
+        // The new account must sign.
+        process_instruction(
+            instruction(50, 2),
+            transaction_accounts.clone(),
+            vec![AccountMeta::new(from, true), AccountMeta::new(to, false)],
+            Err(InstructionError::MissingRequiredSignature),
+        );
+        // As must the funding account.
+        process_instruction(
+            instruction(50, 2),
+            transaction_accounts.clone(),
+            vec![AccountMeta::new(from, false), AccountMeta::new(to, true)],
+            Err(InstructionError::MissingRequiredSignature),
+        );
+        process_instruction(
+            instruction(101, 2),
+            transaction_accounts.clone(),
+            instruction_accounts.clone(),
+            system_error(SystemError::ResultWithNegativeLamports),
+        );
+        process_instruction(
+            instruction(50, MAX_PERMITTED_DATA_LENGTH + 1),
+            transaction_accounts.clone(),
+            instruction_accounts.clone(),
+            system_error(SystemError::InvalidAccountDataLength),
+        );
+        process_instruction(
+            instruction(50, 2),
+            vec![(from, system_account(100)), (to, system_account(1))],
+            instruction_accounts.clone(),
+            system_error(SystemError::AccountAlreadyInUse),
+        );
+        process_instruction(
+            instruction(50, 2),
+            transaction_accounts,
+            vec![AccountMeta::new(from, true)],
+            Err(InstructionError::NotEnoughAccountKeys),
+        );
+    }
+
+    #[test]
+    fn test_create_account_with_seed() {
+        let from = Pubkey::new_unique();
+        let base = Pubkey::new_unique();
+        let seed = String::from("seed");
+        let owner = Pubkey::new_unique();
+        let to = Pubkey::create_with_seed(&base, &seed, &owner).unwrap();
+        let instruction = SystemInstruction::CreateAccountWithSeed {
+            base,
+            seed: seed.clone(),
+            lamports: 50,
+            space: 2,
+            owner,
+        };
+        let transaction_accounts = vec![
+            (from, system_account(100)),
+            (to, system_account(0)),
+            (base, system_account(0)),
+        ];
+
+        // The base signs on behalf of the derived account.
+        let accounts = process_instruction(
+            instruction.clone(),
+            transaction_accounts.clone(),
+            vec![
+                AccountMeta::new(from, true),
+                AccountMeta::new(to, false),
+                AccountMeta::new_readonly(base, true),
+            ],
+            Ok(()),
+        );
+        assert_eq!(accounts[1].lamports(), 50);
+        assert_eq!(accounts[1].data().len(), 2);
+        assert_eq!(accounts[1].owner(), &owner);
+
+        process_instruction(
+            instruction.clone(),
+            transaction_accounts,
+            vec![
+                AccountMeta::new(from, true),
+                AccountMeta::new(to, false),
+                AccountMeta::new_readonly(base, false),
+            ],
+            Err(InstructionError::MissingRequiredSignature),
+        );
+        let other = Pubkey::new_unique();
+        process_instruction(
+            instruction,
+            vec![
+                (from, system_account(100)),
+                (other, system_account(0)),
+                (base, system_account(0)),
+            ],
+            vec![
+                AccountMeta::new(from, true),
+                AccountMeta::new(other, true),
+                AccountMeta::new_readonly(base, true),
+            ],
+            system_error(SystemError::AddressWithSeedMismatch),
+        );
+    }
+
+    #[test]
+    fn test_allocate_and_assign() {
+        let address = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let transaction_accounts = vec![(address, system_account(100))];
+
+        let accounts = process_instruction(
+            SystemInstruction::Allocate { space: 10 },
+            transaction_accounts.clone(),
+            vec![AccountMeta::new(address, true)],
+            Ok(()),
+        );
+        assert_eq!(accounts[0].data().len(), 10);
+        process_instruction(
+            SystemInstruction::Allocate { space: 10 },
+            transaction_accounts.clone(),
+            vec![AccountMeta::new(address, false)],
+            Err(InstructionError::MissingRequiredSignature),
+        );
+        process_instruction(
+            SystemInstruction::Allocate { space: 10 },
+            vec![(address, accounts[0].clone())],
+            vec![AccountMeta::new(address, true)],
+            system_error(SystemError::AccountAlreadyInUse),
+        );
+        process_instruction(
+            SystemInstruction::Allocate {
+                space: MAX_PERMITTED_DATA_LENGTH + 1,
+            },
+            transaction_accounts.clone(),
+            vec![AccountMeta::new(address, true)],
+            system_error(SystemError::InvalidAccountDataLength),
+        );
+
+        let accounts = process_instruction(
+            SystemInstruction::Assign { owner },
+            transaction_accounts.clone(),
+            vec![AccountMeta::new(address, true)],
+            Ok(()),
+        );
+        assert_eq!(accounts[0].owner(), &owner);
+        process_instruction(
+            SystemInstruction::Assign { owner },
+            transaction_accounts.clone(),
+            vec![AccountMeta::new(address, false)],
+            Err(InstructionError::MissingRequiredSignature),
+        );
+        // Assigning the current owner needs no signature.
+        process_instruction(
+            SystemInstruction::Assign { owner },
+            vec![(address, AccountSharedData::new(100, 0, &owner))],
+            vec![AccountMeta::new(address, false)],
+            Ok(()),
+        );
+    }
+
+    #[test]
+    fn test_allocate_and_assign_with_seed() {
+        let base = Pubkey::new_unique();
+        let seed = String::from("seed");
+        let owner = Pubkey::new_unique();
+        let address = Pubkey::create_with_seed(&base, &seed, &owner).unwrap();
+        let transaction_accounts = vec![(address, system_account(100)), (base, system_account(0))];
+        let instruction_accounts = vec![
+            AccountMeta::new(address, false),
+            AccountMeta::new_readonly(base, true),
+        ];
+
+        let accounts = process_instruction(
+            SystemInstruction::AllocateWithSeed {
+                base,
+                seed: seed.clone(),
+                space: 10,
+                owner,
+            },
+            transaction_accounts.clone(),
+            instruction_accounts.clone(),
+            Ok(()),
+        );
+        assert_eq!(accounts[0].data().len(), 10);
+        assert_eq!(accounts[0].owner(), &owner);
+        process_instruction(
+            SystemInstruction::AllocateWithSeed {
+                base,
+                seed: String::from("other seed"),
+                space: 10,
+                owner,
+            },
+            transaction_accounts.clone(),
+            instruction_accounts.clone(),
+            system_error(SystemError::AddressWithSeedMismatch),
+        );
+
+        let accounts = process_instruction(
+            SystemInstruction::AssignWithSeed {
+                base,
+                seed: seed.clone(),
+                owner,
+            },
+            transaction_accounts.clone(),
+            instruction_accounts,
+            Ok(()),
+        );
+        assert_eq!(accounts[0].owner(), &owner);
+        process_instruction(
+            SystemInstruction::AssignWithSeed { base, seed, owner },
+            transaction_accounts,
+            vec![
+                AccountMeta::new(address, false),
+                AccountMeta::new_readonly(base, false),
+            ],
+            Err(InstructionError::MissingRequiredSignature),
+        );
+    }
+
+    #[test]
+    fn test_transfer_with_seed() {
+        let base = Pubkey::new_unique();
+        let from_seed = String::from("seed");
+        let from_owner = system_program::id();
+        let from = Pubkey::create_with_seed(&base, &from_seed, &from_owner).unwrap();
+        let to = Pubkey::new_unique();
+        let instruction = |lamports, from_seed: &str| SystemInstruction::TransferWithSeed {
+            lamports,
+            from_seed: from_seed.to_string(),
+            from_owner,
+        };
+        let transaction_accounts = vec![
+            (from, system_account(100)),
+            (base, system_account(0)),
+            (to, system_account(0)),
+        ];
+        let instruction_accounts = vec![
+            AccountMeta::new(from, false),
+            AccountMeta::new_readonly(base, true),
+            AccountMeta::new(to, false),
+        ];
+
+        let accounts = process_instruction(
+            instruction(40, &from_seed),
+            transaction_accounts.clone(),
+            instruction_accounts.clone(),
+            Ok(()),
+        );
+        assert_eq!(accounts[0].lamports(), 60);
+        assert_eq!(accounts[2].lamports(), 40);
+
+        process_instruction(
+            instruction(40, &from_seed),
+            transaction_accounts.clone(),
+            vec![
+                AccountMeta::new(from, false),
+                AccountMeta::new_readonly(base, false),
+                AccountMeta::new(to, false),
+            ],
+            Err(InstructionError::MissingRequiredSignature),
+        );
+        process_instruction(
+            instruction(40, "other seed"),
+            transaction_accounts.clone(),
+            instruction_accounts.clone(),
+            system_error(SystemError::AddressWithSeedMismatch),
+        );
+        process_instruction(
+            instruction(101, &from_seed),
+            transaction_accounts,
+            instruction_accounts,
+            system_error(SystemError::ResultWithNegativeLamports),
+        );
+    }
+
+    #[allow(deprecated)]
+    fn recent_blockhashes_account(blockhashes: &[Hash]) -> AccountSharedData {
+        let recent_blockhashes = RecentBlockhashes::from_iter(
+            blockhashes
+                .iter()
+                .enumerate()
+                .map(|(i, blockhash)| IterItem(i as u64, blockhash, 0)),
+        );
+        create_account_shared_data_for_test(&recent_blockhashes)
+    }
+
+    fn nonce_account(lamports: u64, versions: &Versions) -> AccountSharedData {
+        AccountSharedData::new_data_with_space(
+            lamports,
+            versions,
+            State::size(),
+            &system_program::id(),
+        )
+        .unwrap()
+    }
+
+    fn nonce_state(account: &AccountSharedData) -> State {
+        let versions: Versions = bincode::deserialize(account.data()).unwrap();
+        versions.state().clone()
+    }
+
+    /// The accounts of a nonce instruction, along with the sysvars it reads.
+    #[allow(deprecated)]
+    fn nonce_transaction_accounts(
+        accounts: Vec<TransactionAccount>,
+        recent_blockhashes: &[Hash],
+    ) -> Vec<TransactionAccount> {
+        let mut transaction_accounts = accounts;
+        transaction_accounts.push((
+            RecentBlockhashes::id(),
+            recent_blockhashes_account(recent_blockhashes),
+        ));
+        transaction_accounts.push((
+            Rent::id(),
+            create_account_shared_data_for_test(&Rent::default()),
+        ));
+        transaction_accounts
+    }
+
+    #[test]
+    fn test_initialize_nonce_account() {
+        let nonce = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let blockhash = Hash::new_unique();
+        let min_balance = Rent::default().minimum_balance(State::size());
+        let uninitialized = nonce_account(min_balance, &Versions::new(State::Uninitialized));
+        let instruction_accounts = system_instruction::create_nonce_account(
+            &Pubkey::new_unique(),
+            &nonce,
+            &authority,
+            min_balance,
+        )[1]
+        .accounts
+        .clone();
+        let instruction = SystemInstruction::InitializeNonceAccount(authority);
+
+        let accounts = process_instruction_with_blockhash(
+            instruction.clone(),
+            nonce_transaction_accounts(vec![(nonce, uninitialized.clone())], &[blockhash]),
+            instruction_accounts.clone(),
+            blockhash,
+            Ok(()),
+        );
+        assert_eq!(
+            nonce_state(&accounts[0]),
+            State::Initialized(NonceData::new(
+                authority,
+                DurableNonce::from_blockhash(&blockhash),
+                0,
+            ))
+        );
+
+        // An initialized nonce account cannot be initialized again.
+        process_instruction(
+            instruction.clone(),
+            nonce_transaction_accounts(vec![(nonce, accounts[0].clone())], &[blockhash]),
+            instruction_accounts.clone(),
+            Err(InstructionError::InvalidAccountData),
+        );
+        process_instruction(
+            instruction.clone(),
+            nonce_transaction_accounts(
+                vec![(
+                    nonce,
+                    nonce_account(min_balance - 1, &Versions::new(State::Uninitialized)),
+                )],
+                &[blockhash],
+            ),
+            instruction_accounts.clone(),
+            Err(InstructionError::InsufficientFunds),
+        );
+        process_instruction(
+            instruction.clone(),
+            nonce_transaction_accounts(vec![(nonce, uninitialized.clone())], &[]),
+            instruction_accounts.clone(),
+            system_error(SystemError::NonceNoRecentBlockhashes),
+        );
+        // The sysvars must be passed at their expected positions.
+        let mut swapped_sysvars = instruction_accounts.clone();
+        swapped_sysvars.swap(1, 2);
+        process_instruction(
+            instruction.clone(),
+            nonce_transaction_accounts(vec![(nonce, uninitialized.clone())], &[blockhash]),
+            swapped_sysvars,
+            Err(InstructionError::InvalidArgument),
+        );
+        let mut readonly = instruction_accounts;
+        readonly[0].is_writable = false;
+        process_instruction(
+            instruction,
+            nonce_transaction_accounts(vec![(nonce, uninitialized)], &[blockhash]),
+            readonly,
+            Err(InstructionError::InvalidArgument),
+        );
+    }
+
+    #[test]
+    fn test_withdraw_nonce_account() {
+        let nonce = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let to = Pubkey::new_unique();
+        let blockhash = Hash::new_unique();
+        let min_balance = Rent::default().minimum_balance(State::size());
+        let initialized = nonce_account(
+            min_balance + 100,
+            &Versions::new(State::Initialized(NonceData::new(
+                authority,
+                DurableNonce::from_blockhash(&blockhash),
+                0,
+            ))),
+        );
+        let transaction_accounts = nonce_transaction_accounts(
+            vec![
+                (nonce, initialized),
+                (to, system_account(0)),
+                (authority, system_account(0)),
+            ],
+            &[blockhash],
+        );
+        let instruction_accounts = |authority_is_signer| {
+            let mut instruction_accounts =
+                system_instruction::withdraw_nonce_account(&nonce, &authority, &to, 0).accounts;
+            instruction_accounts[4].is_signer = authority_is_signer;
+            instruction_accounts
+        };
+
+        let accounts = process_instruction(
+            SystemInstruction::WithdrawNonceAccount(100),
+            transaction_accounts.clone(),
+            instruction_accounts(true),
+            Ok(()),
+        );
+        assert_eq!(accounts[0].lamports(), min_balance);
+        assert_eq!(accounts[1].lamports(), 100);
+        assert!(matches!(nonce_state(&accounts[0]), State::Initialized(_)));
+
+        process_instruction(
+            SystemInstruction::WithdrawNonceAccount(100),
+            transaction_accounts.clone(),
+            instruction_accounts(false),
+            Err(InstructionError::MissingRequiredSignature),
+        );
+        // A partial withdrawal must leave the account rent exempt.
+        process_instruction(
+            SystemInstruction::WithdrawNonceAccount(101),
+            transaction_accounts.clone(),
+            instruction_accounts(true),
+            Err(InstructionError::InsufficientFunds),
+        );
+        // A full withdrawal must wait for the nonce to expire.
+        process_instruction_with_blockhash(
+            SystemInstruction::WithdrawNonceAccount(min_balance + 100),
+            transaction_accounts.clone(),
+            instruction_accounts(true),
+            blockhash,
+            system_error(SystemError::NonceBlockhashNotExpired),
+        );
+        let accounts = process_instruction_with_blockhash(
+            SystemInstruction::WithdrawNonceAccount(min_balance + 100),
+            transaction_accounts,
+            instruction_accounts(true),
+            Hash::new_unique(),
+            Ok(()),
+        );
+        assert_eq!(accounts[0].lamports(), 0);
+        assert_eq!(nonce_state(&accounts[0]), State::Uninitialized);
+
+        // An uninitialized account is withdrawn from by its own signature.
+        let accounts = process_instruction(
+            SystemInstruction::WithdrawNonceAccount(100),
+            nonce_transaction_accounts(
+                vec![
+                    (
+                        nonce,
+                        nonce_account(min_balance + 100, &Versions::new(State::Uninitialized)),
+                    ),
+                    (to, system_account(0)),
+                ],
+                &[blockhash],
+            ),
+            system_instruction::withdraw_nonce_account(&nonce, &nonce, &to, 0).accounts,
+            Ok(()),
+        );
+        assert_eq!(accounts[0].lamports(), min_balance);
+    }
+
+    #[test]
+    fn test_authorize_nonce_account() {
+        let nonce = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let new_authority = Pubkey::new_unique();
+        let data = NonceData::new(
+            authority,
+            DurableNonce::from_blockhash(&Hash::new_unique()),
+            0,
+        );
+        let initialized = nonce_account(1, &Versions::new(State::Initialized(data.clone())));
+        let transaction_accounts = vec![(nonce, initialized), (authority, system_account(0))];
+        let instruction_accounts =
+            system_instruction::authorize_nonce_account(&nonce, &authority, &new_authority)
+                .accounts;
+        let instruction = SystemInstruction::AuthorizeNonceAccount(new_authority);
+
+        let accounts = process_instruction(
+            instruction.clone(),
+            transaction_accounts.clone(),
+            instruction_accounts.clone(),
+            Ok(()),
+        );
+        assert_eq!(
+            nonce_state(&accounts[0]),
+            State::Initialized(NonceData {
+                authority: new_authority,
+                ..data
+            })
+        );
+
+        let mut unsigned = instruction_accounts.clone();
+        unsigned[1].is_signer = false;
+        process_instruction(
+            instruction.clone(),
+            transaction_accounts,
+            unsigned,
+            Err(InstructionError::MissingRequiredSignature),
+        );
+        process_instruction(
+            instruction,
+            vec![
+                (
+                    nonce,
+                    nonce_account(1, &Versions::new(State::Uninitialized)),
+                ),
+                (authority, system_account(0)),
+            ],
+            instruction_accounts,
+            Err(InstructionError::InvalidAccountData),
+        );
+    }
+
+    #[test]
+    fn test_upgrade_nonce_account() {
+        let nonce = Pubkey::new_unique();
+        let data = NonceData::new(
+            Pubkey::new_unique(),
+            DurableNonce::from_blockhash(&Hash::new_unique()),
+            0,
+        );
+        let legacy = Versions::Legacy(Box::new(State::Initialized(data)));
+        let instruction_accounts = system_instruction::upgrade_nonce_account(nonce).accounts;
+
+        let accounts = process_instruction(
+            SystemInstruction::UpgradeNonceAccount,
+            vec![(nonce, nonce_account(1, &legacy))],
+            instruction_accounts.clone(),
+            Ok(()),
+        );
+        let upgraded = legacy.clone().upgrade().unwrap();
+        assert_eq!(accounts[0].data(), nonce_account(1, &upgraded).data());
+
+        // Only legacy, initialized nonce accounts can be upgraded.
+        process_instruction(
+            SystemInstruction::UpgradeNonceAccount,
+            vec![(nonce, nonce_account(1, &upgraded))],
+            instruction_accounts.clone(),
+            Err(InstructionError::InvalidArgument),
+        );
+        process_instruction(
+            SystemInstruction::UpgradeNonceAccount,
+            vec![(
+                nonce,
+                nonce_account(1, &Versions::Legacy(Box::new(State::Uninitialized))),
+            )],
+            instruction_accounts.clone(),
+            Err(InstructionError::InvalidArgument),
+        );
+        let mut not_system_owned = nonce_account(1, &legacy);
+        not_system_owned.set_owner(Pubkey::new_unique());
+        process_instruction(
+            SystemInstruction::UpgradeNonceAccount,
+            vec![(nonce, not_system_owned)],
+            instruction_accounts,
+            Err(InstructionError::InvalidAccountOwner),
+        );
+        process_instruction(
+            SystemInstruction::UpgradeNonceAccount,
+            vec![(nonce, nonce_account(1, &legacy))],
+            vec![AccountMeta::new_readonly(nonce, false)],
+            Err(InstructionError::InvalidArgument),
+        );
+    }
+}
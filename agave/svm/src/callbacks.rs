@@ -14,6 +14,14 @@ pub trait TransactionProcessingCallback {
 
     fn get_last_blockhash_and_lamports_per_signature(&self) -> (Hash, u64);
 
+    /// The lamports per signature associated with `blockhash`, if it is one
+    /// of the last `max_age` blockhashes.
+    fn get_lamports_per_signature_for_blockhash(
+        &self,
+        blockhash: &Hash,
+        max_age: usize,
+    ) -> Option<u64>;
+
     fn get_rent_collector(&self) -> &RentCollector;
 
     fn get_feature_set(&self) -> Arc<FeatureSet>;
@@ -0,0 +1,307 @@
+use {
+    crate::{
+        callbacks::TransactionProcessingCallback,
+        nonce::check_load_and_advance_message_nonce_account, status_cache::StatusCache,
+        AccountOverrides,
+    },
+    solana_sdk::{
+        nonce::state::DurableNonce,
+        transaction::{SanitizedTransaction, TransactionError},
+    },
+    solana_svm::specification::TransactionCheckResult,
+};
+
+/// Check the age of each transaction, then whether it was already processed.
+pub(crate) fn check_transactions<CB: TransactionProcessingCallback>(
+    callbacks: &CB,
+    account_overrides: Option<&AccountOverrides>,
+    status_cache: Option<&dyn StatusCache>,
+    sanitized_txs: &[SanitizedTransaction],
+    max_age: usize,
+) -> Vec<TransactionCheckResult> {
+    let (last_blockhash, next_lamports_per_signature) =
+        callbacks.get_last_blockhash_and_lamports_per_signature();
+    let next_durable_nonce = DurableNonce::from_blockhash(&last_blockhash);
+    sanitized_txs
+        .iter()
+        .map(|tx| {
+            let check_result = check_age(
+                callbacks,
+                account_overrides,
+                tx,
+                max_age,
+                &next_durable_nonce,
+                next_lamports_per_signature,
+            );
+            if check_result.0.is_ok()
+                && status_cache.is_some_and(|status_cache| {
+                    status_cache.is_processed(tx.signature(), tx.message().recent_blockhash())
+                })
+            {
+                return (Err(TransactionError::AlreadyProcessed), None, None);
+            }
+            check_result
+        })
+        .collect()
+}
+
+/// A transaction may be processed if its recent blockhash is within `max_age`
+/// blockhashes, or if it is a valid durable nonce transaction.
+fn check_age<CB: TransactionProcessingCallback>(
+    callbacks: &CB,
+    account_overrides: Option<&AccountOverrides>,
+    tx: &SanitizedTransaction,
+    max_age: usize,
+    next_durable_nonce: &DurableNonce,
+    next_lamports_per_signature: u64,
+) -> TransactionCheckResult {
+    let recent_blockhash = tx.message().recent_blockhash();
+    if let Some(lamports_per_signature) =
+        callbacks.get_lamports_per_signature_for_blockhash(recent_blockhash, max_age)
+    {
+        (Ok(()), None, Some(lamports_per_signature))
+    } else if let Some((nonce, previous_lamports_per_signature)) =
+        check_load_and_advance_message_nonce_account(
+            callbacks,
+            account_overrides,
+            tx.message(),
+            next_durable_nonce,
+            next_lamports_per_signature,
+        )
+    {
+        (Ok(()), Some(nonce), Some(previous_lamports_per_signature))
+    } else {
+        (Err(TransactionError::BlockhashNotFound), None, None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        solana_sdk::{
+            account::AccountSharedData,
+            account_utils::StateMut,
+            feature_set::FeatureSet,
+            hash::Hash,
+            message::Message,
+            nonce::{
+                state::{Data as NonceData, Versions as NonceVersions},
+                State as NonceState,
+            },
+            nonce_info::NonceInfo,
+            pubkey::Pubkey,
+            rent_collector::RentCollector,
+            signature::Signature,
+            system_instruction, system_program,
+            transaction::Transaction,
+        },
+        std::{
+            collections::{HashMap, HashSet},
+            sync::Arc,
+        },
+    };
+
+    const MAX_AGE: usize = 4;
+
+    /// Serves the blockhashes of a queue, each with its lamports per
+    /// signature and age.
+    #[derive(Default)]
+    struct TestCallbacks {
+        accounts: HashMap<Pubkey, AccountSharedData>,
+        blockhashes: HashMap<Hash, (u64, usize)>,
+        rent_collector: RentCollector,
+    }
+
+    impl TransactionProcessingCallback for TestCallbacks {
+        fn account_matches_owners(&self, _account: &Pubkey, _owners: &[Pubkey]) -> Option<usize> {
+            None
+        }
+
+        fn get_account_shared_data(&self, pubkey: &Pubkey) -> Option<AccountSharedData> {
+            self.accounts.get(pubkey).cloned()
+        }
+
+        fn get_last_blockhash_and_lamports_per_signature(&self) -> (Hash, u64) {
+            self.blockhashes
+                .iter()
+                .find(|(_blockhash, (_lamports_per_signature, age))| *age == 0)
+                .map(|(blockhash, (lamports_per_signature, _age))| {
+                    (*blockhash, *lamports_per_signature)
+                })
+                .unwrap_or_default()
+        }
+
+        fn get_lamports_per_signature_for_blockhash(
+            &self,
+            blockhash: &Hash,
+            max_age: usize,
+        ) -> Option<u64> {
+            self.blockhashes
+                .get(blockhash)
+                .filter(|(_lamports_per_signature, age)| *age <= max_age)
+                .map(|(lamports_per_signature, _age)| *lamports_per_signature)
+        }
+
+        fn get_rent_collector(&self) -> &RentCollector {
+            &self.rent_collector
+        }
+
+        fn get_feature_set(&self) -> Arc<FeatureSet> {
+            Arc::new(FeatureSet::all_enabled())
+        }
+    }
+
+    #[derive(Default)]
+    struct TestStatusCache(HashSet<(Signature, Hash)>);
+
+    impl StatusCache for TestStatusCache {
+        fn is_processed(&self, signature: &Signature, recent_blockhash: &Hash) -> bool {
+            self.0.contains(&(*signature, *recent_blockhash))
+        }
+    }
+
+    fn transfer_transaction(recent_blockhash: Hash) -> SanitizedTransaction {
+        let payer = Pubkey::new_unique();
+        let message = Message::new_with_blockhash(
+            &[system_instruction::transfer(
+                &payer,
+                &Pubkey::new_unique(),
+                1,
+            )],
+            Some(&payer),
+            &recent_blockhash,
+        );
+        let mut tx = Transaction::new_unsigned(message);
+        tx.signatures = vec![Signature::new_unique()];
+        SanitizedTransaction::from_transaction_for_tests(tx)
+    }
+
+    fn new_callbacks() -> (TestCallbacks, Hash, Hash) {
+        let last_blockhash = Hash::new_unique();
+        let old_blockhash = Hash::new_unique();
+        let callbacks = TestCallbacks {
+            blockhashes: HashMap::from([(last_blockhash, (10_000, 0)), (old_blockhash, (5000, 5))]),
+            ..TestCallbacks::default()
+        };
+        (callbacks, last_blockhash, old_blockhash)
+    }
+
+    #[test]
+    fn test_check_transactions_age() {
+        let (callbacks, last_blockhash, old_blockhash) = new_callbacks();
+        let txs = [
+            transfer_transaction(last_blockhash),
+            transfer_transaction(old_blockhash),
+            transfer_transaction(Hash::new_unique()),
+        ];
+
+        assert_eq!(
+            check_transactions(&callbacks, None, None, &txs, MAX_AGE),
+            vec![
+                (Ok(()), None, Some(10_000)),
+                (Err(TransactionError::BlockhashNotFound), None, None),
+                (Err(TransactionError::BlockhashNotFound), None, None),
+            ]
+        );
+        assert_eq!(
+            check_transactions(&callbacks, None, None, &txs, MAX_AGE + 1),
+            vec![
+                (Ok(()), None, Some(10_000)),
+                (Ok(()), None, Some(5000)),
+                (Err(TransactionError::BlockhashNotFound), None, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_transactions_durable_nonce() {
+        let (callbacks, last_blockhash, _old_blockhash) = new_callbacks();
+        let nonce_address = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let durable_nonce = DurableNonce::from_blockhash(&Hash::new_unique());
+        let nonce_account = AccountSharedData::new_data(
+            1_000_000,
+            &NonceVersions::new(NonceState::Initialized(NonceData::new(
+                authority,
+                durable_nonce,
+                5000,
+            ))),
+            &system_program::id(),
+        )
+        .unwrap();
+        let account_overrides = AccountOverrides {
+            accounts: HashMap::from([(nonce_address, nonce_account)]),
+        };
+        let message = Message::new_with_blockhash(
+            &[
+                system_instruction::advance_nonce_account(&nonce_address, &authority),
+                system_instruction::transfer(&authority, &Pubkey::new_unique(), 1),
+            ],
+            Some(&authority),
+            durable_nonce.as_hash(),
+        );
+        let txs = [SanitizedTransaction::from_transaction_for_tests(
+            Transaction::new_unsigned(message),
+        )];
+
+        // The nonce account is only found among the overrides.
+        assert_eq!(
+            check_transactions(&callbacks, None, None, &txs, MAX_AGE),
+            vec![(Err(TransactionError::BlockhashNotFound), None, None)]
+        );
+        let check_results =
+            check_transactions(&callbacks, Some(&account_overrides), None, &txs, MAX_AGE);
+        let (Ok(()), Some(nonce), Some(5000)) = &check_results[0] else {
+            panic!("unexpected check result: {check_results:?}");
+        };
+        assert_eq!(nonce.address(), &nonce_address);
+        let versions: NonceVersions = nonce.account().state().unwrap();
+        let NonceState::Initialized(data) = versions.state() else {
+            panic!("nonce account is not initialized");
+        };
+        assert_eq!(
+            data.durable_nonce,
+            DurableNonce::from_blockhash(&last_blockhash)
+        );
+        assert_eq!(data.get_lamports_per_signature(), 10_000);
+    }
+
+    #[test]
+    fn test_check_transactions_already_processed() {
+        let (callbacks, last_blockhash, old_blockhash) = new_callbacks();
+        let processed_tx = transfer_transaction(last_blockhash);
+        let expired_tx = transfer_transaction(old_blockhash);
+        let status_cache = TestStatusCache(HashSet::from([
+            (*processed_tx.signature(), last_blockhash),
+            (*expired_tx.signature(), old_blockhash),
+        ]));
+
+        // The same message under another fee payer signature is a different
+        // transaction.
+        let mut resigned_tx = processed_tx
+            .to_versioned_transaction()
+            .into_legacy_transaction()
+            .unwrap();
+        resigned_tx.signatures = vec![Signature::new_unique()];
+        let resigned_tx = SanitizedTransaction::from_transaction_for_tests(resigned_tx);
+        assert_eq!(resigned_tx.message_hash(), processed_tx.message_hash());
+
+        let txs = [
+            processed_tx,
+            resigned_tx,
+            expired_tx,
+            transfer_transaction(last_blockhash),
+        ];
+        assert_eq!(
+            check_transactions(&callbacks, None, Some(&status_cache), &txs, MAX_AGE),
+            vec![
+                (Err(TransactionError::AlreadyProcessed), None, None),
+                (Ok(()), None, Some(10_000)),
+                // The age is checked before the processing status.
+                (Err(TransactionError::BlockhashNotFound), None, None),
+                (Ok(()), None, Some(10_000)),
+            ]
+        );
+    }
+}
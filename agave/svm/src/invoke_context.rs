@@ -0,0 +1,488 @@
+//! The execution context of a single transaction's instructions.
+
+use {
+    crate::{log_collector::LogCollector, stable_log},
+    agave_program_cache::{
+        executable::Executable, LoadedProgram, LoadedProgramType, LoadedProgramsForTxBatch,
+    },
+    agave_sysvar_cache::SysvarCache,
+    solana_compute_budget::compute_budget::ComputeBudget,
+    solana_sdk::{
+        feature_set::FeatureSet,
+        hash::Hash,
+        instruction::InstructionError,
+        pubkey::Pubkey,
+        transaction_context::{IndexOfAccount, InstructionAccount, TransactionContext},
+    },
+    std::{
+        cell::RefCell,
+        collections::HashMap,
+        rc::Rc,
+        sync::{atomic::Ordering, Arc},
+    },
+};
+
+/// The entrypoint of a builtin program.
+pub type BuiltinFunction = fn(&mut InvokeContext) -> Result<(), InstructionError>;
+
+/// A program implemented natively by the SVM.
+///
+/// Builtins are marked in the program cache as `LoadedProgramType::Builtin`
+/// and dispatched by program id to the builtins of the environment.
+pub struct BuiltinProgram {
+    pub entrypoint: BuiltinFunction,
+}
+
+impl BuiltinProgram {
+    pub fn new(entrypoint: BuiltinFunction) -> Self {
+        Self { entrypoint }
+    }
+}
+
+/// A VM able to execute the programs compiled by its runtime environments.
+pub trait VmBackend: Send + Sync {
+    /// Execute the program of the current instruction context.
+    fn execute(
+        &self,
+        executable: &dyn Executable,
+        invoke_context: &mut InvokeContext,
+    ) -> Result<(), InstructionError>;
+}
+
+/// The environment a transaction's instructions are executed in.
+pub struct EnvironmentConfig<'a> {
+    pub blockhash: Hash,
+    pub builtins: &'a HashMap<Pubkey, BuiltinProgram>,
+    pub feature_set: Arc<FeatureSet>,
+    pub lamports_per_signature: u64,
+    pub sysvar_cache: &'a SysvarCache,
+    pub vm_backend: Option<&'a dyn VmBackend>,
+}
+
+/// Main pipeline from the runtime to a program's execution.
+pub struct InvokeContext<'a> {
+    pub transaction_context: &'a mut TransactionContext,
+    pub environment_config: EnvironmentConfig<'a>,
+    compute_budget: ComputeBudget,
+    compute_meter: RefCell<u64>,
+    log_collector: Option<Rc<RefCell<LogCollector>>>,
+    programs_loaded_for_tx_batch: &'a LoadedProgramsForTxBatch,
+    pub programs_modified_by_tx: &'a mut LoadedProgramsForTxBatch,
+}
+
+impl<'a> InvokeContext<'a> {
+    pub fn new(
+        transaction_context: &'a mut TransactionContext,
+        environment_config: EnvironmentConfig<'a>,
+        compute_budget: ComputeBudget,
+        log_collector: Option<Rc<RefCell<LogCollector>>>,
+        programs_loaded_for_tx_batch: &'a LoadedProgramsForTxBatch,
+        programs_modified_by_tx: &'a mut LoadedProgramsForTxBatch,
+    ) -> Self {
+        Self {
+            transaction_context,
+            environment_config,
+            compute_budget,
+            compute_meter: RefCell::new(compute_budget.compute_unit_limit),
+            log_collector,
+            programs_loaded_for_tx_batch,
+            programs_modified_by_tx,
+        }
+    }
+
+    /// Push a stack frame onto the invocation stack.
+    pub fn push(&mut self) -> Result<(), InstructionError> {
+        let instruction_context = self
+            .transaction_context
+            .get_instruction_context_at_index_in_trace(
+                self.transaction_context.get_instruction_trace_length(),
+            )?;
+        let program_id = instruction_context
+            .get_last_program_key(self.transaction_context)
+            .map_err(|_| InstructionError::UnsupportedProgramId)?;
+        if self
+            .transaction_context
+            .get_instruction_context_stack_height()
+            != 0
+        {
+            let contains = (0..self
+                .transaction_context
+                .get_instruction_context_stack_height())
+                .any(|level| {
+                    self.transaction_context
+                        .get_instruction_context_at_nesting_level(level)
+                        .and_then(|instruction_context| {
+                            instruction_context
+                                .try_borrow_last_program_account(self.transaction_context)
+                        })
+                        .map(|program_account| program_account.get_key() == program_id)
+                        .unwrap_or(false)
+                });
+            let is_last = self
+                .transaction_context
+                .get_current_instruction_context()
+                .and_then(|instruction_context| {
+                    instruction_context.try_borrow_last_program_account(self.transaction_context)
+                })
+                .map(|program_account| program_account.get_key() == program_id)
+                .unwrap_or(false);
+            if contains && !is_last {
+                // Reentrancy not allowed unless caller is calling itself.
+                return Err(InstructionError::ReentrancyNotAllowed);
+            }
+        }
+        self.transaction_context.push()
+    }
+
+    /// Pop a stack frame from the invocation stack.
+    pub fn pop(&mut self) -> Result<(), InstructionError> {
+        self.transaction_context.pop()
+    }
+
+    /// Current height of the invocation stack, top level instructions are
+    /// height 1.
+    pub fn get_stack_height(&self) -> usize {
+        self.transaction_context
+            .get_instruction_context_stack_height()
+    }
+
+    /// Configure, push and execute an instruction.
+    pub fn process_instruction(
+        &mut self,
+        instruction_data: &[u8],
+        instruction_accounts: &[InstructionAccount],
+        program_indices: &[IndexOfAccount],
+    ) -> Result<(), InstructionError> {
+        self.transaction_context
+            .get_next_instruction_context()?
+            .configure(program_indices, instruction_accounts, instruction_data);
+        self.push()?;
+        self.process_executable_chain()
+            // MUST pop if and only if `push` succeeded, independent of `result`.
+            // Thus, the `.and()` instead of an `.and_then()`.
+            .and(self.pop())
+    }
+
+    /// Dispatch the current instruction to the program it invokes.
+    fn process_executable_chain(&mut self) -> Result<(), InstructionError> {
+        let instruction_context = self.transaction_context.get_current_instruction_context()?;
+        let program_id = *instruction_context.get_last_program_key(self.transaction_context)?;
+        let entry = self
+            .find_program_in_cache(&program_id)
+            .ok_or(InstructionError::UnsupportedProgramId)?;
+
+        self.transaction_context
+            .set_return_data(program_id, Vec::new())?;
+        let logger = self.get_log_collector();
+        stable_log::program_invoke(&logger, &program_id, self.get_stack_height());
+        let result = self.execute_program(&program_id, &entry);
+        match &result {
+            Ok(()) => stable_log::program_success(&logger, &program_id),
+            Err(err) => stable_log::program_failure(&logger, &program_id, err),
+        }
+        result
+    }
+
+    fn execute_program(
+        &mut self,
+        program_id: &Pubkey,
+        entry: &LoadedProgram,
+    ) -> Result<(), InstructionError> {
+        match &entry.program {
+            LoadedProgramType::Builtin => {
+                let entrypoint = self
+                    .environment_config
+                    .builtins
+                    .get(program_id)
+                    .ok_or(InstructionError::UnsupportedProgramId)?
+                    .entrypoint;
+                entry.ix_usage_counter.fetch_add(1, Ordering::Relaxed);
+                entrypoint(self)
+            }
+            LoadedProgramType::LegacyV0(executable)
+            | LoadedProgramType::LegacyV1(executable)
+            | LoadedProgramType::Typed(executable) => {
+                let vm_backend = self
+                    .environment_config
+                    .vm_backend
+                    .ok_or(InstructionError::UnsupportedProgramId)?;
+                entry.ix_usage_counter.fetch_add(1, Ordering::Relaxed);
+                vm_backend.execute(executable.as_ref(), self)
+            }
+            _ => Err(InstructionError::UnsupportedProgramId),
+        }
+    }
+
+    /// Find a program, preferring the versions deployed by the current
+    /// transaction over those loaded for the batch.
+    pub fn find_program_in_cache(&self, pubkey: &Pubkey) -> Option<Arc<LoadedProgram>> {
+        self.programs_modified_by_tx
+            .find(pubkey)
+            .or_else(|| self.programs_loaded_for_tx_batch.find(pubkey))
+    }
+
+    /// Consume compute units, failing if the remaining units are exceeded.
+    pub fn consume_checked(&self, amount: u64) -> Result<(), InstructionError> {
+        let mut compute_meter = self.compute_meter.borrow_mut();
+        let exceeded = *compute_meter < amount;
+        *compute_meter = compute_meter.saturating_sub(amount);
+        if exceeded {
+            return Err(InstructionError::ComputationalBudgetExceeded);
+        }
+        Ok(())
+    }
+
+    /// Compute units remaining for the transaction.
+    pub fn get_remaining(&self) -> u64 {
+        *self.compute_meter.borrow()
+    }
+
+    pub fn get_compute_budget(&self) -> &ComputeBudget {
+        &self.compute_budget
+    }
+
+    pub fn get_log_collector(&self) -> Option<Rc<RefCell<LogCollector>>> {
+        self.log_collector.clone()
+    }
+
+    pub fn get_feature_set(&self) -> &FeatureSet {
+        &self.environment_config.feature_set
+    }
+
+    pub fn get_sysvar_cache(&self) -> &SysvarCache {
+        self.environment_config.sysvar_cache
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use {
+        super::*,
+        crate::builtins::BUILTINS,
+        agave_program_cache::ProgramRuntimeEnvironments,
+        solana_sdk::{
+            account::AccountSharedData, instruction::AccountMeta, native_loader, rent::Rent,
+            transaction_context::TransactionAccount,
+        },
+    };
+
+    /// Process a single instruction of `program_id`, one of the SVM's builtins,
+    /// asserting its result and returning the resulting accounts.
+    ///
+    /// The sysvar cache is filled from the sysvar accounts among
+    /// `transaction_accounts`. `pre_adjustments` may amend the invoke context,
+    /// e.g. its blockhash, before the instruction is processed.
+    pub(crate) fn mock_process_instruction<F: FnMut(&mut InvokeContext)>(
+        program_id: &Pubkey,
+        instruction_data: &[u8],
+        mut transaction_accounts: Vec<TransactionAccount>,
+        instruction_account_metas: Vec<AccountMeta>,
+        expected_result: Result<(), InstructionError>,
+        mut pre_adjustments: F,
+    ) -> Vec<AccountSharedData> {
+        let instruction_accounts = instruction_account_metas
+            .iter()
+            .enumerate()
+            .map(|(instruction_account_index, account_meta)| {
+                let index_in_transaction = transaction_accounts
+                    .iter()
+                    .position(|(key, _account)| *key == account_meta.pubkey)
+                    .unwrap_or(transaction_accounts.len())
+                    as IndexOfAccount;
+                let index_in_callee = instruction_account_metas[..instruction_account_index]
+                    .iter()
+                    .position(|other| other.pubkey == account_meta.pubkey)
+                    .unwrap_or(instruction_account_index)
+                    as IndexOfAccount;
+                InstructionAccount {
+                    index_in_transaction,
+                    index_in_caller: index_in_transaction,
+                    index_in_callee,
+                    is_signer: account_meta.is_signer,
+                    is_writable: account_meta.is_writable,
+                }
+            })
+            .collect::<Vec<_>>();
+        let mut sysvar_cache = SysvarCache::default();
+        sysvar_cache.fill_missing_entries(0, None, |pubkey| {
+            transaction_accounts
+                .iter()
+                .find(|(key, _account)| key == pubkey)
+                .map(|(_key, account)| account.clone())
+        });
+        let program_index = transaction_accounts.len() as IndexOfAccount;
+        transaction_accounts.push((
+            *program_id,
+            native_loader::create_loadable_account_for_test("mock_program"),
+        ));
+        let compute_budget = ComputeBudget::default();
+        let mut transaction_context = TransactionContext::new(
+            transaction_accounts,
+            Rent::default(),
+            compute_budget.max_invoke_stack_height,
+            compute_budget.max_instruction_trace_length,
+        );
+        with_mock_invoke_context(
+            &mut transaction_context,
+            &sysvar_cache,
+            compute_budget,
+            |invoke_context| {
+                pre_adjustments(invoke_context);
+                let result = invoke_context.process_instruction(
+                    instruction_data,
+                    &instruction_accounts,
+                    &[program_index],
+                );
+                assert_eq!(result, expected_result);
+            },
+        );
+        let mut accounts = transaction_context.deconstruct_without_keys().unwrap();
+        accounts.pop();
+        accounts
+    }
+
+    /// Run `f` with an invoke context over `transaction_context`, in which every
+    /// program account is one of the SVM's builtins.
+    pub(crate) fn with_mock_invoke_context<R>(
+        transaction_context: &mut TransactionContext,
+        sysvar_cache: &SysvarCache,
+        compute_budget: ComputeBudget,
+        f: impl FnOnce(&mut InvokeContext) -> R,
+    ) -> R {
+        let mut builtins = HashMap::new();
+        for builtin in BUILTINS {
+            builtins.insert(builtin.program_id, BuiltinProgram::new(builtin.entrypoint));
+        }
+        let mut programs_loaded_for_tx_batch =
+            LoadedProgramsForTxBatch::new(0, ProgramRuntimeEnvironments::default(), None, 0);
+        for builtin in BUILTINS {
+            programs_loaded_for_tx_batch.replenish(
+                builtin.program_id,
+                Arc::new(LoadedProgram::new_builtin(0, builtin.name.len())),
+            );
+        }
+        let mut programs_modified_by_tx =
+            LoadedProgramsForTxBatch::new(0, ProgramRuntimeEnvironments::default(), None, 0);
+        let mut invoke_context = InvokeContext::new(
+            transaction_context,
+            EnvironmentConfig {
+                blockhash: Hash::default(),
+                builtins: &builtins,
+                feature_set: Arc::new(FeatureSet::all_enabled()),
+                lamports_per_signature: 0,
+                sysvar_cache,
+                vm_backend: None,
+            },
+            compute_budget,
+            None,
+            &programs_loaded_for_tx_batch,
+            &mut programs_modified_by_tx,
+        );
+        f(&mut invoke_context)
+    }
+
+    fn new_transaction_context(
+        program_ids: &[Pubkey],
+        max_invoke_stack_height: usize,
+        max_instruction_trace_length: usize,
+    ) -> TransactionContext {
+        let transaction_accounts = program_ids
+            .iter()
+            .map(|program_id| {
+                (
+                    *program_id,
+                    native_loader::create_loadable_account_for_test("program"),
+                )
+            })
+            .collect();
+        TransactionContext::new(
+            transaction_accounts,
+            Rent::default(),
+            max_invoke_stack_height,
+            max_instruction_trace_length,
+        )
+    }
+
+    /// Push a frame invoking the program at `program_index`.
+    fn push(
+        invoke_context: &mut InvokeContext,
+        program_index: IndexOfAccount,
+    ) -> Result<(), InstructionError> {
+        invoke_context
+            .transaction_context
+            .get_next_instruction_context()?
+            .configure(&[program_index], &[], &[]);
+        invoke_context.push()
+    }
+
+    #[test]
+    fn test_push_reentrancy() {
+        let mut transaction_context =
+            new_transaction_context(&[Pubkey::new_unique(), Pubkey::new_unique()], 5, 64);
+        with_mock_invoke_context(
+            &mut transaction_context,
+            &SysvarCache::default(),
+            ComputeBudget::default(),
+            |invoke_context| {
+                assert_eq!(push(invoke_context, 0), Ok(()));
+                assert_eq!(push(invoke_context, 1), Ok(()));
+                // The first program is already on the stack, below the caller.
+                assert_eq!(
+                    push(invoke_context, 0),
+                    Err(InstructionError::ReentrancyNotAllowed)
+                );
+                // A program may call itself.
+                assert_eq!(push(invoke_context, 1), Ok(()));
+                assert_eq!(invoke_context.get_stack_height(), 3);
+                for _ in 0..3 {
+                    assert_eq!(invoke_context.pop(), Ok(()));
+                }
+                assert_eq!(invoke_context.pop(), Err(InstructionError::CallDepth));
+            },
+        );
+    }
+
+    #[test]
+    fn test_push_limits() {
+        let mut transaction_context = new_transaction_context(&[Pubkey::new_unique()], 2, 4);
+        with_mock_invoke_context(
+            &mut transaction_context,
+            &SysvarCache::default(),
+            ComputeBudget::default(),
+            |invoke_context| {
+                assert_eq!(push(invoke_context, 0), Ok(()));
+                assert_eq!(push(invoke_context, 0), Ok(()));
+                assert_eq!(push(invoke_context, 0), Err(InstructionError::CallDepth));
+                assert_eq!(invoke_context.get_stack_height(), 2);
+                assert_eq!(invoke_context.pop(), Ok(()));
+                assert_eq!(invoke_context.pop(), Ok(()));
+
+                // The failed push was still recorded in the instruction trace.
+                assert_eq!(push(invoke_context, 0), Ok(()));
+                assert_eq!(invoke_context.pop(), Ok(()));
+                assert_eq!(
+                    push(invoke_context, 0),
+                    Err(InstructionError::MaxInstructionTraceLengthExceeded)
+                );
+            },
+        );
+    }
+
+    #[test]
+    fn test_process_instruction_unsupported_program() {
+        let program_id = Pubkey::new_unique();
+        let mut transaction_context = new_transaction_context(&[program_id], 5, 64);
+        with_mock_invoke_context(
+            &mut transaction_context,
+            &SysvarCache::default(),
+            ComputeBudget::default(),
+            |invoke_context| {
+                assert_eq!(
+                    invoke_context.process_instruction(&[], &[], &[0]),
+                    Err(InstructionError::UnsupportedProgramId)
+                );
+                assert_eq!(invoke_context.get_stack_height(), 0);
+            },
+        );
+    }
+}
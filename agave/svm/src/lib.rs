@@ -1,30 +1,63 @@
 //! Agave Solana SVM Implementation.
 
+mod account_loader;
+mod account_rent_state;
+pub mod builtins;
 pub mod callbacks;
+mod check_transactions;
+pub mod invoke_context;
+pub mod log_collector;
+mod message_processor;
+mod nonce;
+mod program_loader;
+pub mod stable_log;
+pub mod status_cache;
+mod transaction_account_state_info;
 
 use {
-    crate::callbacks::TransactionProcessingCallback,
-    agave_program_cache::{ForkGraph, ProgramCache},
-    agave_sysvar_cache::SysvarCache,
+    crate::{
+        account_loader::load_accounts,
+        builtins::BUILTINS,
+        callbacks::TransactionProcessingCallback,
+        check_transactions::check_transactions,
+        invoke_context::{BuiltinProgram, EnvironmentConfig, InvokeContext, VmBackend},
+        log_collector::LogCollector,
+        message_processor::process_message,
+        program_loader::{load_program_with_pubkey, program_modification_slot},
+        status_cache::StatusCache,
+        transaction_account_state_info::TransactionAccountStateInfo,
+    },
+    agave_program_cache::{
+        eviction::Percentage, ForkGraph, LoadedProgram, LoadedProgramMatchCriteria,
+        LoadedProgramsForTxBatch, ProgramCache, ProgramRuntimeEnvironments, MAX_LOADED_ENTRY_COUNT,
+    },
+    agave_sysvar_cache::{SysvarCache, SysvarOverrides},
     solana_compute_budget::compute_budget::ComputeBudget,
     solana_sdk::{
-        account::AccountSharedData,
+        account::{AccountSharedData, ReadableAccount},
+        bpf_loader, bpf_loader_deprecated, bpf_loader_upgradeable,
         clock::{Epoch, Slot},
         epoch_schedule::EpochSchedule,
         fee::FeeStructure,
+        inner_instruction::{InnerInstruction, InnerInstructionsList},
+        instruction::{CompiledInstruction, TRANSACTION_LEVEL_STACK_HEIGHT},
+        loader_v4,
+        message::SanitizedMessage,
         native_loader,
         pubkey::Pubkey,
         transaction::{SanitizedTransaction, TransactionError},
+        transaction_context::{ExecutionRecord, TransactionAccount, TransactionContext},
     },
     solana_svm::specification::{
         DurableNonceFee, LoadAndExecuteSanitizedTransactionsOutput, LoadedTransaction,
-        TransactionBatchProcessor, TransactionExecutionResult, TransactionLoadResult,
+        TransactionBatchProcessor, TransactionCheckResult, TransactionExecutionDetails,
+        TransactionExecutionResult,
     },
     std::{
         cell::RefCell,
-        collections::HashMap,
+        collections::{hash_map::Entry, HashMap},
         rc::Rc,
-        sync::{Arc, RwLock},
+        sync::{atomic::Ordering, Arc, RwLock},
     },
 };
 
@@ -40,10 +73,6 @@ pub struct ExecutionRecordingConfig {
     pub log_messages_bytes_limit: Option<usize>,
 }
 
-// ============== EVICT ME ==============
-pub struct LoadedProgramsForTxBatch;
-// ======================================
-
 pub struct RuntimeConfig {
     pub compute_budget: Option<ComputeBudget>,
     pub log_messages_bytes_limit: Option<usize>,
@@ -51,7 +80,6 @@ pub struct RuntimeConfig {
 }
 
 pub struct RuntimeEnvironment {
-    pub builtin_programs: Vec<Pubkey>,
     pub epoch: Epoch,
     pub epoch_schedule: EpochSchedule,
     pub fee_structure: FeeStructure,
@@ -67,26 +95,50 @@ pub struct AgaveTransactionBatchProcessor<CB: TransactionProcessingCallback, FG:
     pub runtime_environment: Arc<RuntimeEnvironment>,
     pub sysvar_cache: RwLock<SysvarCache>,
     pub program_cache: Arc<RwLock<ProgramCache<FG>>>,
+    pub status_cache: Option<Arc<dyn StatusCache>>,
+    pub vm_backend: Option<Arc<dyn VmBackend>>,
+    /// The builtin programs, by program id.
+    builtins: HashMap<Pubkey, BuiltinProgram>,
+    /// Sysvar values which take precedence over their accounts.
+    sysvar_overrides: Option<SysvarOverrides>,
 }
 
 /// Agave SVM Transaction Batch Processor Implementation.
 impl<CB: TransactionProcessingCallback, FG: ForkGraph> TransactionBatchProcessor
     for AgaveTransactionBatchProcessor<CB, FG>
 {
+    fn check_transactions(
+        &self,
+        sanitized_txs: &[SanitizedTransaction],
+        max_age: usize,
+    ) -> Vec<TransactionCheckResult> {
+        check_transactions(
+            &self.callbacks,
+            self.account_overrides.as_ref(),
+            self.status_cache.as_deref(),
+            sanitized_txs,
+            max_age,
+        )
+    }
+
     /// The entrypoint to the Agave SVM Implementation.
     /// Load and execute a batch of sanitized transactions.
     fn load_and_execute_sanitized_transactions(
         &self,
         sanitized_txs: &[SanitizedTransaction],
+        max_age: usize,
     ) -> LoadAndExecuteSanitizedTransactionsOutput {
-        /*
-         * Mock implementation to demonstrate driving other modular components.
-         */
+        // [METRICS]: [START]: check_time
+        let check_results = self.check_transactions(sanitized_txs, max_age);
+        // [METRICS]: [STOP]: check_time
+
+        self.fill_missing_sysvar_cache_entries();
+
         // [METRICS]: [START]: program_cache_time
         let mut program_accounts_map =
-            filter_executable_program_accounts(&self.callbacks, sanitized_txs);
+            filter_executable_program_accounts(&self.callbacks, sanitized_txs, &check_results);
         let native_loader = native_loader::id();
-        for builtin_program in &self.runtime_environment.builtin_programs {
+        for builtin_program in self.builtins.keys() {
             program_accounts_map.insert(*builtin_program, (&native_loader, 0));
         }
         let programs_loaded_for_tx_batch = Rc::new(RefCell::new(
@@ -94,14 +146,36 @@ impl<CB: TransactionProcessingCallback, FG: ForkGraph> TransactionBatchProcessor
         ));
         // [METRICS]: [STOP]: program_cache_time
 
+        if programs_loaded_for_tx_batch.borrow().hit_max_limit {
+            // The batch cannot be executed without evicting its own programs,
+            // so every transaction which passed its checks is returned to be
+            // retried.
+            let (loaded_transactions, execution_results) = check_results
+                .into_iter()
+                .map(|(check_result, _nonce, _lamports_per_signature)| {
+                    let err = check_result
+                        .err()
+                        .unwrap_or(TransactionError::ProgramCacheHitMaxLimit);
+                    (
+                        (Err(err.clone()), None),
+                        TransactionExecutionResult::NotExecuted(err),
+                    )
+                })
+                .unzip();
+            return LoadAndExecuteSanitizedTransactionsOutput {
+                loaded_transactions,
+                execution_results,
+            };
+        }
+
         // [METRICS]: [START]: load_time
         let mut loaded_transactions = load_accounts(
             &self.callbacks,
             sanitized_txs,
+            check_results,
             &self.runtime_environment.fee_structure,
             self.account_overrides.as_ref(),
-            &program_accounts_map,
-            &programs_loaded_for_tx_batch.borrow(),
+            self.sysvar_overrides.as_ref(),
         );
         // [METRICS]: [STOP]: load_time
 
@@ -127,7 +201,7 @@ impl<CB: TransactionProcessingCallback, FG: ForkGraph> TransactionBatchProcessor
                             maybe_compute_budget.unwrap()
                         };
 
-                    let result = self.execute_loaded_transaction(
+                    let (result, programs_modified_by_tx) = self.execute_loaded_transaction(
                         tx,
                         loaded_transaction,
                         compute_budget,
@@ -135,19 +209,15 @@ impl<CB: TransactionProcessingCallback, FG: ForkGraph> TransactionBatchProcessor
                         &programs_loaded_for_tx_batch.borrow(),
                     );
 
-                    // if let TransactionExecutionResult::Executed {
-                    //     details,
-                    //     programs_modified_by_tx,
-                    // } = &result
-                    // {
-                    //     // Update batch specific cache of the loaded programs with the modifications
-                    //     // made by the transaction, if it executed successfully.
-                    //     if details.status.is_ok() {
-                    //         programs_loaded_for_tx_batch
-                    //             .borrow_mut()
-                    //             .merge(programs_modified_by_tx);
-                    //     }
-                    // }
+                    if let TransactionExecutionResult::Executed { details } = &result {
+                        // Update batch specific cache of the loaded programs with the modifications
+                        // made by the transaction, if it executed successfully.
+                        if details.status.is_ok() {
+                            programs_loaded_for_tx_batch
+                                .borrow_mut()
+                                .merge(&programs_modified_by_tx);
+                        }
+                    }
 
                     result
                 }
@@ -155,14 +225,14 @@ impl<CB: TransactionProcessingCallback, FG: ForkGraph> TransactionBatchProcessor
             .collect();
         // [METRICS]: [STOP]: execution_time
 
-        // const SHRINK_LOADED_PROGRAMS_TO_PERCENTAGE: u8 = 90;
-        // self.program_cache
-        //     .write()
-        //     .unwrap()
-        //     .evict_using_2s_random_selection(
-        //         Percentage::from(SHRINK_LOADED_PROGRAMS_TO_PERCENTAGE),
-        //         self.slot,
-        //     );
+        const SHRINK_LOADED_PROGRAMS_TO_PERCENTAGE: u8 = 90;
+        self.program_cache
+            .write()
+            .unwrap()
+            .evict_using_2s_random_selection(
+                Percentage::from(SHRINK_LOADED_PROGRAMS_TO_PERCENTAGE),
+                self.runtime_environment.slot,
+            );
 
         /* ... */
 
@@ -173,54 +243,826 @@ impl<CB: TransactionProcessingCallback, FG: ForkGraph> TransactionBatchProcessor
     }
 }
 
-// Mock helpers below.
-
 impl<CB: TransactionProcessingCallback, FG: ForkGraph> AgaveTransactionBatchProcessor<CB, FG> {
+    /// Create a batch processor with the SVM's builtin programs registered in
+    /// `program_cache`.
+    pub fn new(
+        callbacks: CB,
+        recording_config: ExecutionRecordingConfig,
+        runtime_config: Arc<RuntimeConfig>,
+        runtime_environment: Arc<RuntimeEnvironment>,
+        program_cache: Arc<RwLock<ProgramCache<FG>>>,
+    ) -> Self {
+        let mut processor = Self {
+            account_overrides: None,
+            callbacks,
+            recording_config,
+            runtime_config,
+            runtime_environment,
+            sysvar_cache: RwLock::default(),
+            program_cache,
+            status_cache: None,
+            vm_backend: None,
+            builtins: HashMap::new(),
+            sysvar_overrides: None,
+        };
+        for builtin in BUILTINS {
+            processor.add_builtin(
+                builtin.program_id,
+                builtin.name,
+                BuiltinProgram::new(builtin.entrypoint),
+            );
+        }
+        processor
+    }
+
+    /// Populate any sysvars missing from the sysvar cache from their accounts,
+    /// applying the sysvar overrides.
+    ///
+    /// The cache is filled once per slot. If it was filled for another slot,
+    /// it is reset first.
+    pub fn fill_missing_sysvar_cache_entries(&self) {
+        let slot = self.runtime_environment.slot;
+        if self.sysvar_cache.read().unwrap().filled_for_slot() == Some(slot) {
+            return;
+        }
+        let mut sysvar_cache = self.sysvar_cache.write().unwrap();
+        match sysvar_cache.filled_for_slot() {
+            // Filled by another thread while the lock was released.
+            Some(filled_for_slot) if filled_for_slot == slot => return,
+            Some(_) => sysvar_cache.reset(),
+            None => {}
+        }
+        sysvar_cache.fill_missing_entries(slot, self.sysvar_overrides.as_ref(), |pubkey| {
+            self.callbacks.get_account_shared_data(pubkey)
+        });
+    }
+
+    /// Clear the sysvar cache, so sysvars are read from their accounts again.
+    pub fn reset_sysvar_cache(&self) {
+        let mut sysvar_cache = self.sysvar_cache.write().unwrap();
+        sysvar_cache.reset();
+    }
+
+    /// Replace the sysvar overrides, clearing the sysvar cache so they take
+    /// effect.
+    pub fn set_sysvar_overrides(&mut self, sysvar_overrides: Option<SysvarOverrides>) {
+        self.sysvar_overrides = sysvar_overrides;
+        self.reset_sysvar_cache();
+    }
+
+    /// Prepare the program cache for the environments of the next epoch.
+    ///
+    /// To be called once per slot, after `runtime_environment` moves to the
+    /// slot. Within the recompilation phase at the end of an epoch,
+    /// `upcoming_environments` are staged and the most used programs are
+    /// recompiled against them a few per slot, so they are ready when the
+    /// next epoch begins. Pass `None` if the next epoch keeps the current
+    /// environments. Returns the number of programs recompiled.
+    pub fn prepare_program_cache_for_upcoming_epoch(
+        &self,
+        upcoming_environments: Option<ProgramRuntimeEnvironments>,
+    ) -> usize {
+        let slot = self.runtime_environment.slot;
+        let epoch_schedule = &self.runtime_environment.epoch_schedule;
+        let mut program_cache = self.program_cache.write().unwrap();
+        if let Some(upcoming_environments) = upcoming_environments {
+            if program_cache.upcoming_environments.is_none()
+                && ProgramCache::<FG>::is_in_recompilation_phase(slot, epoch_schedule)
+            {
+                program_cache.set_upcoming_environments(upcoming_environments, slot);
+            }
+        }
+        program_cache.recompile_for_upcoming_environments(
+            slot,
+            epoch_schedule,
+            |key, _program, environments| {
+                Arc::into_inner(load_program_with_pubkey(
+                    &self.callbacks,
+                    environments,
+                    key,
+                    slot,
+                ))
+            },
+        )
+    }
+
+    /// Add a builtin program, registering it in the program cache.
+    pub fn add_builtin(&mut self, program_id: Pubkey, name: &str, builtin: BuiltinProgram) {
+        self.builtins.insert(program_id, builtin);
+        self.program_cache.write().unwrap().assign_program(
+            program_id,
+            Arc::new(LoadedProgram::new_builtin(0, name.len())),
+        );
+    }
+
+    /// Load every program used by the batch into a batch-local view of the
+    /// program cache, cooperating with other threads loading the same
+    /// programs.
+    ///
+    /// If loading is limited and the batch uses more programs than the cache
+    /// can hold, the returned view is empty and marked `hit_max_limit`.
     fn replenish_program_cache(
         &self,
-        _program_accounts_map: &HashMap<Pubkey, (&Pubkey, u64)>,
+        program_accounts_map: &HashMap<Pubkey, (&Pubkey, u64)>,
     ) -> LoadedProgramsForTxBatch {
-        /*
-         * MOCK.
-         */
-        LoadedProgramsForTxBatch
+        let slot = self.runtime_environment.slot;
+        let epoch = self.runtime_environment.epoch;
+        if self.recording_config.limit_to_load_programs
+            && program_accounts_map.len() > MAX_LOADED_ENTRY_COUNT
+        {
+            let mut loaded_programs_for_txs = LoadedProgramsForTxBatch::new_from_cache(
+                slot,
+                epoch,
+                &self.program_cache.read().unwrap(),
+            );
+            loaded_programs_for_txs.hit_max_limit = true;
+            return loaded_programs_for_txs;
+        }
+        let mut missing_programs: Vec<(Pubkey, (LoadedProgramMatchCriteria, u64))> =
+            program_accounts_map
+                .iter()
+                .map(|(pubkey, (_owner, count))| {
+                    let match_criteria = program_modification_slot(&self.callbacks, pubkey).map_or(
+                        LoadedProgramMatchCriteria::Tombstone,
+                        LoadedProgramMatchCriteria::DeployedOnOrAfterSlot,
+                    );
+                    (*pubkey, (match_criteria, *count))
+                })
+                .collect();
+
+        let mut loaded_programs_for_txs = None;
+        let mut program_to_store = None;
+        loop {
+            let (program_to_load, task_cookie, task_waiter) = {
+                // Lock the global cache.
+                let mut program_cache = self.program_cache.write().unwrap();
+                // Initialize our local cache.
+                let is_first_round = loaded_programs_for_txs.is_none();
+                if is_first_round {
+                    loaded_programs_for_txs = Some(LoadedProgramsForTxBatch::new_from_cache(
+                        slot,
+                        epoch,
+                        &program_cache,
+                    ));
+                }
+                // Submit our last completed loading task.
+                if let Some((key, program)) = program_to_store.take() {
+                    program_cache.finish_cooperative_loading_task(slot, key, program);
+                }
+                // Figure out which program needs to be loaded next.
+                let program_to_load = program_cache.extract(
+                    &mut missing_programs,
+                    loaded_programs_for_txs.as_mut().unwrap(),
+                    is_first_round,
+                );
+                let task_waiter = Arc::clone(&program_cache.loading_task_waiter);
+                (program_to_load, task_waiter.cookie(), task_waiter)
+                // Unlock the global cache again.
+            };
+
+            if let Some((key, count)) = program_to_load {
+                // Load, verify and compile one program.
+                let program = load_program_with_pubkey(
+                    &self.callbacks,
+                    loaded_programs_for_txs
+                        .as_ref()
+                        .unwrap()
+                        .get_environments_for_epoch(epoch),
+                    &key,
+                    slot,
+                );
+                program.tx_usage_counter.store(count, Ordering::Relaxed);
+                program_to_store = Some((key, program));
+            } else if missing_programs.is_empty() {
+                break;
+            } else {
+                // Sleep until the next `finish_cooperative_loading_task` call.
+                // Once a task completes we'll wake up and try to load the
+                // missing programs inside the tx batch again.
+                let _new_cookie = task_waiter.wait(task_cookie);
+            }
+        }
+
+        loaded_programs_for_txs.unwrap()
     }
 
+    /// Execute the instructions of a loaded transaction, returning the
+    /// execution result along with the programs deployed by the transaction.
     fn execute_loaded_transaction(
         &self,
-        _tx: &SanitizedTransaction,
-        _loaded_transaction: &mut LoadedTransaction,
-        _compute_budget: ComputeBudget,
-        _durable_nonce_fee: Option<DurableNonceFee>,
-        _programs_loaded_for_tx_batch: &LoadedProgramsForTxBatch,
-    ) -> TransactionExecutionResult {
-        /*
-         * MOCK.
-         */
-        TransactionExecutionResult::NotExecuted(TransactionError::UnsupportedVersion)
+        tx: &SanitizedTransaction,
+        loaded_transaction: &mut LoadedTransaction,
+        compute_budget: ComputeBudget,
+        durable_nonce_fee: Option<DurableNonceFee>,
+        programs_loaded_for_tx_batch: &LoadedProgramsForTxBatch,
+    ) -> (TransactionExecutionResult, LoadedProgramsForTxBatch) {
+        let mut programs_modified_by_tx = LoadedProgramsForTxBatch::new(
+            programs_loaded_for_tx_batch.slot,
+            programs_loaded_for_tx_batch.environments.clone(),
+            programs_loaded_for_tx_batch.upcoming_environments.clone(),
+            programs_loaded_for_tx_batch.latest_root_epoch,
+        );
+
+        let transaction_accounts = std::mem::take(&mut loaded_transaction.accounts);
+        let lamports_before_tx =
+            transaction_accounts_lamports_sum(&transaction_accounts, tx.message()).unwrap_or(0);
+
+        let rent = self.callbacks.get_rent_collector().rent;
+        let mut transaction_context = TransactionContext::new(
+            transaction_accounts,
+            rent,
+            compute_budget.max_invoke_stack_height,
+            compute_budget.max_instruction_trace_length,
+        );
+        let pre_account_state_info =
+            TransactionAccountStateInfo::new(&rent, &transaction_context, tx.message());
+
+        let (blockhash, lamports_per_signature) = self
+            .callbacks
+            .get_last_blockhash_and_lamports_per_signature();
+        let log_collector = self.recording_config.enable_log_recording.then(|| {
+            LogCollector::new_ref_with_limit(self.recording_config.log_messages_bytes_limit)
+        });
+
+        let sysvar_cache = self.sysvar_cache.read().unwrap();
+        let mut invoke_context = InvokeContext::new(
+            &mut transaction_context,
+            EnvironmentConfig {
+                blockhash,
+                builtins: &self.builtins,
+                feature_set: self.callbacks.get_feature_set(),
+                lamports_per_signature,
+                sysvar_cache: &sysvar_cache,
+                vm_backend: self.vm_backend.as_deref(),
+            },
+            compute_budget,
+            log_collector.clone(),
+            programs_loaded_for_tx_batch,
+            &mut programs_modified_by_tx,
+        );
+
+        // [METRICS]: [START]: process_message_time
+        let mut status = process_message(
+            tx.message(),
+            &loaded_transaction.program_indices,
+            &mut invoke_context,
+        );
+        // [METRICS]: [STOP]: process_message_time
+
+        let executed_units = compute_budget
+            .compute_unit_limit
+            .saturating_sub(invoke_context.get_remaining());
+        drop(invoke_context);
+        drop(sysvar_cache);
+
+        if status.is_ok() {
+            let post_account_state_info =
+                TransactionAccountStateInfo::new(&rent, &transaction_context, tx.message());
+            status = TransactionAccountStateInfo::verify_changes(
+                &pre_account_state_info,
+                &post_account_state_info,
+                &transaction_context,
+            );
+        }
+
+        let log_messages = log_collector.and_then(|log_collector| {
+            Rc::try_unwrap(log_collector)
+                .map(|log_collector| log_collector.into_inner().into_messages())
+                .ok()
+        });
+
+        let inner_instructions = if self.recording_config.enable_cpi_recording {
+            Some(inner_instructions_list_from_instruction_trace(
+                &transaction_context,
+            ))
+        } else {
+            None
+        };
+
+        let ExecutionRecord {
+            accounts,
+            return_data,
+            accounts_resize_delta,
+            ..
+        } = transaction_context.into();
+
+        if status.is_ok()
+            && transaction_accounts_lamports_sum(&accounts, tx.message())
+                .filter(|lamports_after_tx| lamports_before_tx == *lamports_after_tx)
+                .is_none()
+        {
+            status = Err(TransactionError::UnbalancedTransaction);
+        }
+        let accounts_data_len_delta = status.as_ref().map_or(0, |_| accounts_resize_delta);
+        loaded_transaction.accounts = accounts;
+
+        let return_data =
+            if self.recording_config.enable_return_data_recording && !return_data.data.is_empty() {
+                Some(return_data)
+            } else {
+                None
+            };
+
+        let details = TransactionExecutionDetails {
+            status,
+            log_messages,
+            inner_instructions,
+            durable_nonce_fee,
+            return_data,
+            executed_units,
+            accounts_data_len_delta,
+        };
+        (
+            TransactionExecutionResult::Executed { details },
+            programs_modified_by_tx,
+        )
     }
 }
 
+/// The loaders which may own an executable program account.
+const PROGRAM_OWNERS: &[Pubkey] = &[
+    bpf_loader_upgradeable::id(),
+    bpf_loader::id(),
+    bpf_loader_deprecated::id(),
+    loader_v4::id(),
+];
+
+/// Find every program account referenced by the transactions which passed
+/// their checks, returning each program's owner along with the number of
+/// transactions referencing it.
 fn filter_executable_program_accounts<'a, CB: TransactionProcessingCallback>(
-    _callbacks: &CB,
-    _txs: &[SanitizedTransaction],
+    callbacks: &CB,
+    txs: &[SanitizedTransaction],
+    check_results: &[TransactionCheckResult],
 ) -> HashMap<Pubkey, (&'a Pubkey, u64)> {
-    /*
-     * MOCK.
-     */
-    HashMap::new()
+    let mut result: HashMap<Pubkey, (&'a Pubkey, u64)> = HashMap::new();
+    txs.iter()
+        .zip(check_results)
+        .filter(|(_tx, (check_result, _nonce, _lamports_per_signature))| check_result.is_ok())
+        .for_each(|(tx, _check_result)| {
+            tx.message()
+                .account_keys()
+                .iter()
+                .for_each(|key| match result.entry(*key) {
+                    Entry::Occupied(mut entry) => {
+                        let (_, count) = entry.get_mut();
+                        *count = count.saturating_add(1);
+                    }
+                    Entry::Vacant(entry) => {
+                        if let Some(index) = callbacks.account_matches_owners(key, PROGRAM_OWNERS) {
+                            if let Some(owner) = PROGRAM_OWNERS.get(index) {
+                                entry.insert((owner, 1));
+                            }
+                        }
+                    }
+                });
+        });
+    result
 }
 
-fn load_accounts<CB: TransactionProcessingCallback>(
-    _callbacks: &CB,
-    _txs: &[SanitizedTransaction],
-    _fee_structure: &FeeStructure,
-    _account_overrides: Option<&AccountOverrides>,
-    _program_accounts: &HashMap<Pubkey, (&Pubkey, u64)>,
-    _loaded_programs: &LoadedProgramsForTxBatch,
-) -> Vec<TransactionLoadResult> {
-    /*
-     * MOCK.
-     */
-    vec![]
+/// Collect the instructions invoked through CPI by each top-level
+/// instruction of a transaction.
+fn inner_instructions_list_from_instruction_trace(
+    transaction_context: &TransactionContext,
+) -> InnerInstructionsList {
+    let mut outer_instructions = Vec::new();
+    for index_in_trace in 0..transaction_context.get_instruction_trace_length() {
+        if let Ok(instruction_context) =
+            transaction_context.get_instruction_context_at_index_in_trace(index_in_trace)
+        {
+            let stack_height = instruction_context.get_stack_height();
+            if stack_height == TRANSACTION_LEVEL_STACK_HEIGHT {
+                outer_instructions.push(Vec::new());
+            } else if let Some(inner_instructions) = outer_instructions.last_mut() {
+                let stack_height = u8::try_from(stack_height).unwrap_or(u8::MAX);
+                let instruction = CompiledInstruction::new_from_raw_parts(
+                    instruction_context
+                        .get_index_of_program_account_in_transaction(
+                            instruction_context
+                                .get_number_of_program_accounts()
+                                .saturating_sub(1),
+                        )
+                        .unwrap_or_default() as u8,
+                    instruction_context.get_instruction_data().to_vec(),
+                    (0..instruction_context.get_number_of_instruction_accounts())
+                        .map(|instruction_account_index| {
+                            instruction_context
+                                .get_index_of_instruction_account_in_transaction(
+                                    instruction_account_index,
+                                )
+                                .unwrap_or_default() as u8
+                        })
+                        .collect(),
+                );
+                inner_instructions.push(InnerInstruction {
+                    instruction,
+                    stack_height,
+                });
+            } else {
+                debug_assert!(false);
+            }
+        }
+    }
+    outer_instructions
+}
+
+/// Sum the lamports of the message's accounts, or `None` on overflow.
+fn transaction_accounts_lamports_sum(
+    accounts: &[TransactionAccount],
+    message: &SanitizedMessage,
+) -> Option<u128> {
+    let mut lamports_sum = 0u128;
+    for i in 0..message.account_keys().len() {
+        let (_, account) = accounts.get(i)?;
+        lamports_sum = lamports_sum.checked_add(u128::from(account.lamports()))?;
+    }
+    Some(lamports_sum)
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        agave_program_cache::fork_graph::InMemoryForkGraph,
+        solana_sdk::{
+            account::{create_account_shared_data_for_test, WritableAccount},
+            clock::Clock,
+            feature_set::FeatureSet,
+            hash::Hash,
+            instruction::{Instruction, InstructionError},
+            message::Message,
+            rent_collector::RentCollector,
+            rent_debits::RentDebits,
+            system_instruction, system_program, sysvar,
+            transaction::Transaction,
+            transaction_context::{IndexOfAccount, TransactionReturnData},
+        },
+    };
+
+    #[derive(Default)]
+    struct TestCallbacks {
+        accounts: HashMap<Pubkey, AccountSharedData>,
+        rent_collector: RentCollector,
+    }
+
+    impl TransactionProcessingCallback for TestCallbacks {
+        fn account_matches_owners(&self, _account: &Pubkey, _owners: &[Pubkey]) -> Option<usize> {
+            None
+        }
+
+        fn get_account_shared_data(&self, pubkey: &Pubkey) -> Option<AccountSharedData> {
+            self.accounts.get(pubkey).cloned()
+        }
+
+        fn get_last_blockhash_and_lamports_per_signature(&self) -> (Hash, u64) {
+            (Hash::default(), 0)
+        }
+
+        fn get_lamports_per_signature_for_blockhash(
+            &self,
+            _blockhash: &Hash,
+            _max_age: usize,
+        ) -> Option<u64> {
+            None
+        }
+
+        fn get_rent_collector(&self) -> &RentCollector {
+            &self.rent_collector
+        }
+
+        fn get_feature_set(&self) -> Arc<FeatureSet> {
+            Arc::new(FeatureSet::all_enabled())
+        }
+    }
+
+    fn runtime_environment(slot: Slot) -> Arc<RuntimeEnvironment> {
+        Arc::new(RuntimeEnvironment {
+            epoch: 0,
+            epoch_schedule: EpochSchedule::default(),
+            fee_structure: FeeStructure::default(),
+            slot,
+        })
+    }
+
+    fn new_processor(
+        callbacks: TestCallbacks,
+    ) -> AgaveTransactionBatchProcessor<TestCallbacks, InMemoryForkGraph> {
+        let mut program_cache = ProgramCache::new(0, 0);
+        program_cache.set_fork_graph(Arc::new(RwLock::new(InMemoryForkGraph::new(
+            0,
+            EpochSchedule::default(),
+        ))));
+        AgaveTransactionBatchProcessor::new(
+            callbacks,
+            ExecutionRecordingConfig {
+                enable_cpi_recording: false,
+                enable_log_recording: false,
+                enable_return_data_recording: false,
+                limit_to_load_programs: false,
+                log_messages_bytes_limit: None,
+            },
+            Arc::new(RuntimeConfig {
+                compute_budget: None,
+                log_messages_bytes_limit: None,
+                transaction_account_lock_limit: None,
+            }),
+            runtime_environment(0),
+            Arc::new(RwLock::new(program_cache)),
+        )
+    }
+
+    fn clock_account(slot: Slot) -> AccountSharedData {
+        create_account_shared_data_for_test(&Clock {
+            slot,
+            ..Clock::default()
+        })
+    }
+
+    fn cached_clock_slot(
+        processor: &AgaveTransactionBatchProcessor<TestCallbacks, InMemoryForkGraph>,
+    ) -> Slot {
+        processor.fill_missing_sysvar_cache_entries();
+        let clock = processor.sysvar_cache.read().unwrap().get_clock();
+        clock.unwrap().slot
+    }
+
+    #[test]
+    fn test_fill_missing_sysvar_cache_entries_once_per_slot() {
+        let mut callbacks = TestCallbacks::default();
+        callbacks
+            .accounts
+            .insert(sysvar::clock::id(), clock_account(0));
+        let mut processor = new_processor(callbacks);
+        assert_eq!(cached_clock_slot(&processor), 0);
+
+        processor
+            .callbacks
+            .accounts
+            .insert(sysvar::clock::id(), clock_account(1));
+        assert_eq!(cached_clock_slot(&processor), 0);
+
+        processor.runtime_environment = runtime_environment(1);
+        assert_eq!(cached_clock_slot(&processor), 1);
+        assert_eq!(
+            processor.sysvar_cache.read().unwrap().filled_for_slot(),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn test_set_sysvar_overrides_clears_sysvar_cache() {
+        let mut callbacks = TestCallbacks::default();
+        callbacks
+            .accounts
+            .insert(sysvar::clock::id(), clock_account(0));
+        let mut processor = new_processor(callbacks);
+        assert_eq!(cached_clock_slot(&processor), 0);
+
+        let mut sysvar_overrides = SysvarOverrides::default();
+        sysvar_overrides.set_sysvar(&Clock {
+            slot: 9,
+            ..Clock::default()
+        });
+        processor.set_sysvar_overrides(Some(sysvar_overrides));
+        {
+            let sysvar_cache = processor.sysvar_cache.read().unwrap();
+            assert_eq!(sysvar_cache.filled_for_slot(), None);
+            assert!(sysvar_cache.get_clock().is_err());
+        }
+        assert_eq!(cached_clock_slot(&processor), 9);
+
+        processor.set_sysvar_overrides(None);
+        assert_eq!(cached_clock_slot(&processor), 0);
+    }
+
+    /// Execute `message` against `accounts`, as if its accounts had been
+    /// loaded by `load_accounts`.
+    fn execute_message(
+        processor: &AgaveTransactionBatchProcessor<TestCallbacks, InMemoryForkGraph>,
+        message: Message,
+        accounts: &HashMap<Pubkey, AccountSharedData>,
+        compute_budget: ComputeBudget,
+    ) -> TransactionExecutionDetails {
+        let tx =
+            SanitizedTransaction::from_transaction_for_tests(Transaction::new_unsigned(message));
+        let mut loaded_transaction = LoadedTransaction {
+            accounts: tx
+                .message()
+                .account_keys()
+                .iter()
+                .map(|key| (*key, accounts.get(key).cloned().unwrap_or_default()))
+                .collect(),
+            program_indices: tx
+                .message()
+                .instructions()
+                .iter()
+                .map(|instruction| vec![instruction.program_id_index as IndexOfAccount])
+                .collect(),
+            rent: 0,
+            rent_debits: RentDebits::default(),
+        };
+        let mut programs_loaded_for_tx_batch =
+            LoadedProgramsForTxBatch::new(0, ProgramRuntimeEnvironments::default(), None, 0);
+        for program_id in processor.builtins.keys() {
+            programs_loaded_for_tx_batch
+                .replenish(*program_id, Arc::new(LoadedProgram::new_builtin(0, 0)));
+        }
+        let (result, _programs_modified_by_tx) = processor.execute_loaded_transaction(
+            &tx,
+            &mut loaded_transaction,
+            compute_budget,
+            None,
+            &programs_loaded_for_tx_batch,
+        );
+        match result {
+            TransactionExecutionResult::Executed { details } => details,
+            TransactionExecutionResult::NotExecuted(err) => panic!("not executed: {err}"),
+        }
+    }
+
+    /// The accounts of a funded payer and the system program.
+    fn payer_accounts(payer: Pubkey) -> HashMap<Pubkey, AccountSharedData> {
+        HashMap::from([
+            (
+                payer,
+                AccountSharedData::new(10_000_000, 0, &system_program::id()),
+            ),
+            (
+                system_program::id(),
+                native_loader::create_loadable_account_for_test("system_program"),
+            ),
+        ])
+    }
+
+    #[test]
+    fn test_execute_loaded_transaction_rent_state_transition() {
+        let processor = new_processor(TestCallbacks::default());
+        let payer = Pubkey::new_unique();
+        let accounts = payer_accounts(payer);
+        let rent = processor.callbacks.rent_collector.rent;
+        let transfer = |lamports| {
+            Message::new(
+                &[system_instruction::transfer(
+                    &payer,
+                    &Pubkey::new_unique(),
+                    lamports,
+                )],
+                Some(&payer),
+            )
+        };
+
+        // A new account must be funded to be rent exempt.
+        let details = execute_message(&processor, transfer(1), &accounts, ComputeBudget::default());
+        assert_eq!(
+            details.status,
+            Err(TransactionError::InsufficientFundsForRent { account_index: 1 })
+        );
+        assert_eq!(details.accounts_data_len_delta, 0);
+
+        let details = execute_message(
+            &processor,
+            transfer(rent.minimum_balance(0)),
+            &accounts,
+            ComputeBudget::default(),
+        );
+        assert_eq!(details.status, Ok(()));
+    }
+
+    /// A builtin which mints a lamport into the first account of the
+    /// transaction, without passing it to the instruction.
+    fn mint_lamport(invoke_context: &mut InvokeContext) -> Result<(), InstructionError> {
+        let mut account = invoke_context
+            .transaction_context
+            .get_account_at_index(0)?
+            .borrow_mut();
+        let lamports = account.lamports();
+        account.set_lamports(lamports + 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_execute_loaded_transaction_unbalanced() {
+        let mut processor = new_processor(TestCallbacks::default());
+        let mint_program_id = Pubkey::new_unique();
+        processor.add_builtin(mint_program_id, "mint", BuiltinProgram::new(mint_lamport));
+        let payer = Pubkey::new_unique();
+        let mut accounts = payer_accounts(payer);
+        accounts.insert(
+            mint_program_id,
+            native_loader::create_loadable_account_for_test("mint"),
+        );
+
+        let message = Message::new(
+            &[Instruction::new_with_bytes(mint_program_id, &[], vec![])],
+            Some(&payer),
+        );
+        let details = execute_message(&processor, message, &accounts, ComputeBudget::default());
+        assert_eq!(details.status, Err(TransactionError::UnbalancedTransaction));
+    }
+
+    #[test]
+    fn test_execute_loaded_transaction_instruction_trace_limit() {
+        let processor = new_processor(TestCallbacks::default());
+        let payer = Pubkey::new_unique();
+        let accounts = payer_accounts(payer);
+        let to = Pubkey::new_unique();
+        let message = Message::new(
+            &[
+                system_instruction::transfer(&payer, &to, 1_000_000),
+                system_instruction::transfer(&payer, &to, 1_000_000),
+            ],
+            Some(&payer),
+        );
+
+        let details = execute_message(
+            &processor,
+            message.clone(),
+            &accounts,
+            ComputeBudget::default(),
+        );
+        assert_eq!(details.status, Ok(()));
+        let details = execute_message(
+            &processor,
+            message,
+            &accounts,
+            ComputeBudget {
+                max_instruction_trace_length: 1,
+                ..ComputeBudget::default()
+            },
+        );
+        assert_eq!(
+            details.status,
+            Err(TransactionError::InstructionError(
+                1,
+                InstructionError::MaxInstructionTraceLengthExceeded
+            ))
+        );
+    }
+
+    /// A builtin which returns its instruction data.
+    fn return_instruction_data(invoke_context: &mut InvokeContext) -> Result<(), InstructionError> {
+        let transaction_context = &mut invoke_context.transaction_context;
+        let instruction_context = transaction_context.get_current_instruction_context()?;
+        let program_id = *instruction_context.get_last_program_key(transaction_context)?;
+        let data = instruction_context.get_instruction_data().to_vec();
+        transaction_context.set_return_data(program_id, data)
+    }
+
+    #[test]
+    fn test_execute_loaded_transaction_recording_config() {
+        let mut processor = new_processor(TestCallbacks::default());
+        let program_id = Pubkey::new_unique();
+        processor.add_builtin(
+            program_id,
+            "return_data",
+            BuiltinProgram::new(return_instruction_data),
+        );
+        let payer = Pubkey::new_unique();
+        let mut accounts = payer_accounts(payer);
+        accounts.insert(
+            program_id,
+            native_loader::create_loadable_account_for_test("return_data"),
+        );
+        let message = Message::new(
+            &[Instruction::new_with_bytes(program_id, &[1, 2, 3], vec![])],
+            Some(&payer),
+        );
+
+        let details = execute_message(
+            &processor,
+            message.clone(),
+            &accounts,
+            ComputeBudget::default(),
+        );
+        assert_eq!(details.status, Ok(()));
+        assert_eq!(details.log_messages, None);
+        assert_eq!(details.inner_instructions, None);
+        assert_eq!(details.return_data, None);
+
+        processor.recording_config = ExecutionRecordingConfig {
+            enable_cpi_recording: true,
+            enable_log_recording: true,
+            enable_return_data_recording: true,
+            limit_to_load_programs: false,
+            log_messages_bytes_limit: None,
+        };
+        let details = execute_message(&processor, message, &accounts, ComputeBudget::default());
+        assert_eq!(details.status, Ok(()));
+        assert_eq!(
+            details.log_messages,
+            Some(vec![
+                format!("Program {program_id} invoke [1]"),
+                format!("Program {program_id} success"),
+            ])
+        );
+        assert_eq!(details.inner_instructions, Some(vec![vec![]]));
+        assert_eq!(
+            details.return_data,
+            Some(TransactionReturnData {
+                program_id,
+                data: vec![1, 2, 3],
+            })
+        );
+    }
 }
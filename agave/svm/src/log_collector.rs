@@ -0,0 +1,111 @@
+use std::{cell::RefCell, rc::Rc};
+
+/// The default byte limit of a transaction's log messages.
+pub const LOG_MESSAGES_BYTES_LIMIT: usize = 10 * 1000;
+
+/// Collects the log messages of a transaction, up to a byte limit.
+pub struct LogCollector {
+    messages: Vec<String>,
+    bytes_written: usize,
+    bytes_limit: Option<usize>,
+    limit_warning: bool,
+}
+
+impl Default for LogCollector {
+    fn default() -> Self {
+        Self {
+            messages: Vec::new(),
+            bytes_written: 0,
+            bytes_limit: Some(LOG_MESSAGES_BYTES_LIMIT),
+            limit_warning: false,
+        }
+    }
+}
+
+impl LogCollector {
+    /// Record a message, replacing every message past the byte limit with a
+    /// single "Log truncated" marker.
+    pub fn log(&mut self, message: &str) {
+        let Some(limit) = self.bytes_limit else {
+            self.messages.push(message.to_string());
+            return;
+        };
+
+        let bytes_written = self.bytes_written.saturating_add(message.len());
+        if bytes_written >= limit {
+            if !self.limit_warning {
+                self.limit_warning = true;
+                self.messages.push(String::from("Log truncated"));
+            }
+        } else {
+            self.bytes_written = bytes_written;
+            self.messages.push(message.to_string());
+        }
+    }
+
+    pub fn get_recorded_content(&self) -> &[String] {
+        self.messages.as_slice()
+    }
+
+    pub fn new_ref() -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(Self::default()))
+    }
+
+    /// Create a collector with the given byte limit, `None` being unlimited.
+    pub fn new_ref_with_limit(bytes_limit: Option<usize>) -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(Self {
+            bytes_limit,
+            ..Self::default()
+        }))
+    }
+
+    pub fn into_messages(self) -> Vec<String> {
+        self.messages
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_messages_bytes_limit() {
+        let log_collector = LogCollector::new_ref_with_limit(Some(10));
+        for message in ["12345", "1234", "1", "1"] {
+            log_collector.borrow_mut().log(message);
+        }
+        // The message reaching the limit and every later one are replaced by
+        // a single marker.
+        assert_eq!(
+            log_collector.borrow().get_recorded_content(),
+            ["12345", "1234", "Log truncated"]
+        );
+    }
+
+    #[test]
+    fn test_default_log_messages_bytes_limit() {
+        let log_collector = LogCollector::new_ref();
+        let message = "x".repeat(LOG_MESSAGES_BYTES_LIMIT / 10);
+        for _ in 0..20 {
+            log_collector.borrow_mut().log(&message);
+        }
+        let log_collector = log_collector.borrow();
+        let messages = log_collector.get_recorded_content();
+        assert_eq!(messages.len(), 10);
+        assert!(messages[..9].iter().all(|logged| *logged == message));
+        assert_eq!(messages[9], "Log truncated");
+    }
+
+    #[test]
+    fn test_unlimited_log_messages() {
+        let log_collector = LogCollector::new_ref_with_limit(None);
+        let message = "x".repeat(LOG_MESSAGES_BYTES_LIMIT);
+        for _ in 0..3 {
+            log_collector.borrow_mut().log(&message);
+        }
+        assert_eq!(
+            log_collector.borrow().get_recorded_content(),
+            [message.clone(), message.clone(), message]
+        );
+    }
+}
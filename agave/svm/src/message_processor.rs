@@ -0,0 +1,94 @@
+use {
+    crate::invoke_context::InvokeContext,
+    solana_sdk::{
+        account::WritableAccount,
+        message::SanitizedMessage,
+        precompiles::is_precompile,
+        sysvar::instructions,
+        transaction::TransactionError,
+        transaction_context::{IndexOfAccount, InstructionAccount},
+    },
+};
+
+/// Process the instructions of a message in order, stopping at the first
+/// failing instruction.
+pub(crate) fn process_message(
+    message: &SanitizedMessage,
+    program_indices: &[Vec<IndexOfAccount>],
+    invoke_context: &mut InvokeContext,
+) -> Result<(), TransactionError> {
+    debug_assert_eq!(program_indices.len(), message.instructions().len());
+    for (instruction_index, ((program_id, instruction), program_indices)) in message
+        .program_instructions_iter()
+        .zip(program_indices.iter())
+        .enumerate()
+    {
+        let is_precompile = is_precompile(program_id, |feature_id| {
+            invoke_context.get_feature_set().is_active(feature_id)
+        });
+
+        // Fixup the special instructions key if present before the
+        // instruction is executed.
+        if let Some(account_index) = invoke_context
+            .transaction_context
+            .find_index_of_account(&instructions::id())
+        {
+            let mut account = invoke_context
+                .transaction_context
+                .get_account_at_index(account_index)
+                .map_err(|_| TransactionError::InvalidAccountIndex)?
+                .borrow_mut();
+            instructions::store_current_index(
+                account.data_as_mut_slice(),
+                instruction_index as u16,
+            );
+        }
+
+        let instruction_accounts = instruction
+            .accounts
+            .iter()
+            .enumerate()
+            .map(|(instruction_account_index, index_in_transaction)| {
+                let index_in_callee = instruction.accounts[..instruction_account_index]
+                    .iter()
+                    .position(|account_index| account_index == index_in_transaction)
+                    .unwrap_or(instruction_account_index)
+                    as IndexOfAccount;
+                let index_in_transaction = *index_in_transaction as usize;
+                InstructionAccount {
+                    index_in_transaction: index_in_transaction as IndexOfAccount,
+                    index_in_caller: index_in_transaction as IndexOfAccount,
+                    index_in_callee,
+                    is_signer: message.is_signer(index_in_transaction),
+                    is_writable: message.is_writable(index_in_transaction),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let result = if is_precompile {
+            // Precompiles are verified along with the transaction's
+            // signatures, so only the instruction trace is recorded.
+            invoke_context
+                .transaction_context
+                .get_next_instruction_context()
+                .map(|instruction_context| {
+                    instruction_context.configure(
+                        program_indices,
+                        &instruction_accounts,
+                        &instruction.data,
+                    );
+                })
+                .and_then(|_| invoke_context.transaction_context.push())
+                .and_then(|_| invoke_context.transaction_context.pop())
+        } else {
+            invoke_context.process_instruction(
+                &instruction.data,
+                &instruction_accounts,
+                program_indices,
+            )
+        };
+
+        result.map_err(|err| TransactionError::InstructionError(instruction_index as u8, err))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,315 @@
+use {
+    crate::{callbacks::TransactionProcessingCallback, AccountOverrides},
+    solana_sdk::{
+        account::AccountSharedData,
+        account_utils::StateMut,
+        message::SanitizedMessage,
+        nonce::{
+            state::{Data as NonceData, DurableNonce, Versions as NonceVersions},
+            State as NonceState, NONCED_TX_MARKER_IX_INDEX,
+        },
+        nonce_account,
+        nonce_info::NoncePartial,
+        pubkey::Pubkey,
+    },
+};
+
+/// Load the nonce account of a durable nonce transaction and advance it to
+/// `next_durable_nonce`, returning it along with the lamports per signature
+/// stored in it before the advance.
+///
+/// Returns `None` if the message is not a valid durable nonce transaction.
+pub(crate) fn check_load_and_advance_message_nonce_account<CB: TransactionProcessingCallback>(
+    callbacks: &CB,
+    account_overrides: Option<&AccountOverrides>,
+    message: &SanitizedMessage,
+    next_durable_nonce: &DurableNonce,
+    next_lamports_per_signature: u64,
+) -> Option<(NoncePartial, u64)> {
+    let nonce_is_advanceable = message.recent_blockhash() != next_durable_nonce.as_hash();
+    if !nonce_is_advanceable {
+        return None;
+    }
+
+    let (nonce_address, mut nonce_account, nonce_data) =
+        load_message_nonce_account(callbacks, account_overrides, message)?;
+
+    let previous_lamports_per_signature = nonce_data.get_lamports_per_signature();
+    let next_nonce_state = NonceVersions::new(NonceState::new_initialized(
+        &nonce_data.authority,
+        *next_durable_nonce,
+        next_lamports_per_signature,
+    ));
+    nonce_account.set_state(&next_nonce_state).ok()?;
+
+    Some((
+        NoncePartial::new(nonce_address, nonce_account),
+        previous_lamports_per_signature,
+    ))
+}
+
+/// Load the nonce account named by the message's `AdvanceNonceAccount`
+/// instruction, checking its stored nonce matches the message's blockhash
+/// and its authority signed the instruction.
+fn load_message_nonce_account<CB: TransactionProcessingCallback>(
+    callbacks: &CB,
+    account_overrides: Option<&AccountOverrides>,
+    message: &SanitizedMessage,
+) -> Option<(Pubkey, AccountSharedData, NonceData)> {
+    let nonce_address = message.get_durable_nonce()?;
+    let nonce_account = account_overrides
+        .and_then(|overrides| overrides.accounts.get(nonce_address).cloned())
+        .or_else(|| callbacks.get_account_shared_data(nonce_address))?;
+    let nonce_data =
+        nonce_account::verify_nonce_account(&nonce_account, message.recent_blockhash())?;
+
+    let nonce_is_authorized = message
+        .get_ix_signers(NONCED_TX_MARKER_IX_INDEX as usize)
+        .any(|signer| signer == &nonce_data.authority);
+    if !nonce_is_authorized {
+        return None;
+    }
+
+    Some((*nonce_address, nonce_account, nonce_data))
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        solana_sdk::{
+            account::WritableAccount,
+            feature_set::FeatureSet,
+            hash::Hash,
+            message::Message,
+            nonce_info::NonceInfo,
+            rent_collector::RentCollector,
+            system_instruction, system_program,
+            transaction::{SanitizedTransaction, Transaction},
+        },
+        std::{collections::HashMap, sync::Arc},
+    };
+
+    #[derive(Default)]
+    struct TestCallbacks {
+        accounts: HashMap<Pubkey, AccountSharedData>,
+        rent_collector: RentCollector,
+    }
+
+    impl TransactionProcessingCallback for TestCallbacks {
+        fn account_matches_owners(&self, _account: &Pubkey, _owners: &[Pubkey]) -> Option<usize> {
+            None
+        }
+
+        fn get_account_shared_data(&self, pubkey: &Pubkey) -> Option<AccountSharedData> {
+            self.accounts.get(pubkey).cloned()
+        }
+
+        fn get_last_blockhash_and_lamports_per_signature(&self) -> (Hash, u64) {
+            (Hash::default(), 0)
+        }
+
+        fn get_lamports_per_signature_for_blockhash(
+            &self,
+            _blockhash: &Hash,
+            _max_age: usize,
+        ) -> Option<u64> {
+            None
+        }
+
+        fn get_rent_collector(&self) -> &RentCollector {
+            &self.rent_collector
+        }
+
+        fn get_feature_set(&self) -> Arc<FeatureSet> {
+            Arc::new(FeatureSet::all_enabled())
+        }
+    }
+
+    fn nonce_account(authority: &Pubkey, durable_nonce: DurableNonce) -> AccountSharedData {
+        let data = NonceData::new(*authority, durable_nonce, 5000);
+        AccountSharedData::new_data(
+            1_000_000,
+            &NonceVersions::new(NonceState::Initialized(data)),
+            &system_program::id(),
+        )
+        .unwrap()
+    }
+
+    fn nonce_transaction(
+        nonce_address: &Pubkey,
+        authority: &Pubkey,
+        durable_nonce: &DurableNonce,
+    ) -> SanitizedTransaction {
+        let payer = Pubkey::new_unique();
+        let instructions = [
+            system_instruction::advance_nonce_account(nonce_address, authority),
+            system_instruction::transfer(&payer, &Pubkey::new_unique(), 1),
+        ];
+        let message =
+            Message::new_with_blockhash(&instructions, Some(&payer), durable_nonce.as_hash());
+        SanitizedTransaction::from_transaction_for_tests(Transaction::new_unsigned(message))
+    }
+
+    #[test]
+    fn test_advance_nonce_account() {
+        let nonce_address = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let durable_nonce = DurableNonce::from_blockhash(&Hash::new_unique());
+        let next_durable_nonce = DurableNonce::from_blockhash(&Hash::new_unique());
+        let mut callbacks = TestCallbacks::default();
+        callbacks
+            .accounts
+            .insert(nonce_address, nonce_account(&authority, durable_nonce));
+        let tx = nonce_transaction(&nonce_address, &authority, &durable_nonce);
+
+        let (nonce, previous_lamports_per_signature) =
+            check_load_and_advance_message_nonce_account(
+                &callbacks,
+                None,
+                tx.message(),
+                &next_durable_nonce,
+                10_000,
+            )
+            .unwrap();
+        assert_eq!(previous_lamports_per_signature, 5000);
+        assert_eq!(nonce.address(), &nonce_address);
+        let versions: NonceVersions = nonce.account().state().unwrap();
+        let NonceState::Initialized(data) = versions.state() else {
+            panic!("nonce account is not initialized");
+        };
+        assert_eq!(data.authority, authority);
+        assert_eq!(data.durable_nonce, next_durable_nonce);
+        assert_eq!(data.get_lamports_per_signature(), 10_000);
+        // The stored account is left untouched.
+        assert_eq!(
+            callbacks.accounts[&nonce_address],
+            nonce_account(&authority, durable_nonce)
+        );
+    }
+
+    #[test]
+    fn test_advance_nonce_account_not_advanceable() {
+        let nonce_address = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let durable_nonce = DurableNonce::from_blockhash(&Hash::new_unique());
+        let mut callbacks = TestCallbacks::default();
+        callbacks
+            .accounts
+            .insert(nonce_address, nonce_account(&authority, durable_nonce));
+        let tx = nonce_transaction(&nonce_address, &authority, &durable_nonce);
+
+        // The nonce was already advanced in this slot.
+        assert!(check_load_and_advance_message_nonce_account(
+            &callbacks,
+            None,
+            tx.message(),
+            &durable_nonce,
+            5000,
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn test_advance_nonce_account_invalid() {
+        let nonce_address = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let durable_nonce = DurableNonce::from_blockhash(&Hash::new_unique());
+        let next_durable_nonce = DurableNonce::from_blockhash(&Hash::new_unique());
+        let check = |callbacks: &TestCallbacks, tx: &SanitizedTransaction| {
+            check_load_and_advance_message_nonce_account(
+                callbacks,
+                None,
+                tx.message(),
+                &next_durable_nonce,
+                5000,
+            )
+        };
+        let tx = nonce_transaction(&nonce_address, &authority, &durable_nonce);
+
+        // The nonce account does not exist.
+        let mut callbacks = TestCallbacks::default();
+        assert!(check(&callbacks, &tx).is_none());
+
+        // The stored nonce does not match the transaction's blockhash.
+        callbacks.accounts.insert(
+            nonce_address,
+            nonce_account(
+                &authority,
+                DurableNonce::from_blockhash(&Hash::new_unique()),
+            ),
+        );
+        assert!(check(&callbacks, &tx).is_none());
+
+        // The nonce authority did not sign the transaction.
+        callbacks.accounts.insert(
+            nonce_address,
+            nonce_account(&Pubkey::new_unique(), durable_nonce),
+        );
+        assert!(check(&callbacks, &tx).is_none());
+
+        // The nonce account is not owned by the system program.
+        let mut account = nonce_account(&authority, durable_nonce);
+        account.set_owner(Pubkey::new_unique());
+        callbacks.accounts.insert(nonce_address, account);
+        assert!(check(&callbacks, &tx).is_none());
+
+        callbacks
+            .accounts
+            .insert(nonce_address, nonce_account(&authority, durable_nonce));
+        assert!(check(&callbacks, &tx).is_some());
+
+        // The transaction does not advance a nonce first.
+        let payer = Pubkey::new_unique();
+        let message = Message::new_with_blockhash(
+            &[system_instruction::transfer(
+                &payer,
+                &Pubkey::new_unique(),
+                1,
+            )],
+            Some(&payer),
+            durable_nonce.as_hash(),
+        );
+        let tx =
+            SanitizedTransaction::from_transaction_for_tests(Transaction::new_unsigned(message));
+        assert!(check(&callbacks, &tx).is_none());
+    }
+
+    #[test]
+    fn test_advance_nonce_account_override() {
+        let nonce_address = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let durable_nonce = DurableNonce::from_blockhash(&Hash::new_unique());
+        let next_durable_nonce = DurableNonce::from_blockhash(&Hash::new_unique());
+        let mut callbacks = TestCallbacks::default();
+        callbacks.accounts.insert(
+            nonce_address,
+            nonce_account(
+                &authority,
+                DurableNonce::from_blockhash(&Hash::new_unique()),
+            ),
+        );
+        let account_overrides = AccountOverrides {
+            accounts: HashMap::from([(nonce_address, nonce_account(&authority, durable_nonce))]),
+        };
+        let tx = nonce_transaction(&nonce_address, &authority, &durable_nonce);
+
+        // The overriding account takes precedence over the stored one.
+        assert!(check_load_and_advance_message_nonce_account(
+            &callbacks,
+            None,
+            tx.message(),
+            &next_durable_nonce,
+            5000,
+        )
+        .is_none());
+        assert!(check_load_and_advance_message_nonce_account(
+            &callbacks,
+            Some(&account_overrides),
+            tx.message(),
+            &next_durable_nonce,
+            5000,
+        )
+        .is_some());
+    }
+}
@@ -0,0 +1,195 @@
+use {
+    crate::callbacks::TransactionProcessingCallback,
+    agave_program_cache::{
+        executable::ProgramRuntimeEnvironment, LoadedProgram, LoadedProgramType,
+        ProgramRuntimeEnvironments, DELAY_VISIBILITY_SLOT_OFFSET,
+    },
+    solana_sdk::{
+        account::{AccountSharedData, ReadableAccount},
+        account_utils::StateMut,
+        bpf_loader_upgradeable::{self, UpgradeableLoaderState},
+        clock::Slot,
+        loader_v4::{self, LoaderV4State, LoaderV4Status},
+        pubkey::Pubkey,
+        transaction::{self, TransactionError},
+    },
+    std::sync::Arc,
+};
+
+enum ProgramAccountLoadResult {
+    AccountNotFound,
+    InvalidAccountData,
+    ProgramOfLoaderV1orV2(AccountSharedData),
+    ProgramOfLoaderV3(AccountSharedData, AccountSharedData, Slot),
+    ProgramOfLoaderV4(AccountSharedData, Slot),
+}
+
+/// Load, verify and compile a program against `environments`.
+///
+/// Programs which cannot be loaded are returned as tombstones, so the cache
+/// does not attempt to load them again.
+pub(crate) fn load_program_with_pubkey<CB: TransactionProcessingCallback>(
+    callbacks: &CB,
+    environments: &ProgramRuntimeEnvironments,
+    pubkey: &Pubkey,
+    slot: Slot,
+) -> Arc<LoadedProgram> {
+    let loaded_program = match load_program_accounts(callbacks, pubkey) {
+        ProgramAccountLoadResult::AccountNotFound => {
+            LoadedProgram::new_tombstone(slot, LoadedProgramType::Closed)
+        }
+        ProgramAccountLoadResult::InvalidAccountData => LoadedProgram::new_tombstone(
+            slot,
+            LoadedProgramType::FailedVerification(environments.program_runtime_v1.clone()),
+        ),
+        ProgramAccountLoadResult::ProgramOfLoaderV1orV2(program_account) => {
+            load_program_from_bytes(
+                program_account.owner(),
+                &environments.program_runtime_v1,
+                0,
+                Some(program_account.data()),
+                program_account.data().len(),
+            )
+        }
+        ProgramAccountLoadResult::ProgramOfLoaderV3(program_account, programdata_account, slot) => {
+            load_program_from_bytes(
+                program_account.owner(),
+                &environments.program_runtime_v1,
+                slot,
+                programdata_account
+                    .data()
+                    .get(UpgradeableLoaderState::size_of_programdata_metadata()..),
+                program_account
+                    .data()
+                    .len()
+                    .saturating_add(programdata_account.data().len()),
+            )
+        }
+        ProgramAccountLoadResult::ProgramOfLoaderV4(program_account, slot) => {
+            load_program_from_bytes(
+                &loader_v4::id(),
+                &environments.program_runtime_v2,
+                slot,
+                program_account
+                    .data()
+                    .get(LoaderV4State::program_data_offset()..),
+                program_account.data().len(),
+            )
+        }
+    };
+    Arc::new(loaded_program)
+}
+
+fn load_program_from_bytes(
+    loader_key: &Pubkey,
+    program_runtime_environment: &ProgramRuntimeEnvironment,
+    deployment_slot: Slot,
+    elf_bytes: Option<&[u8]>,
+    account_size: usize,
+) -> LoadedProgram {
+    elf_bytes
+        .ok_or_else(|| "Invalid program data".into())
+        .and_then(|elf_bytes| {
+            LoadedProgram::new(
+                loader_key,
+                program_runtime_environment.clone(),
+                deployment_slot,
+                deployment_slot.saturating_add(DELAY_VISIBILITY_SLOT_OFFSET),
+                elf_bytes,
+                account_size,
+            )
+        })
+        .unwrap_or_else(|_| {
+            LoadedProgram::new_tombstone(
+                deployment_slot,
+                LoadedProgramType::FailedVerification(program_runtime_environment.clone()),
+            )
+        })
+}
+
+/// The slot a program was last deployed in, used to find a matching version
+/// in the cache.
+pub(crate) fn program_modification_slot<CB: TransactionProcessingCallback>(
+    callbacks: &CB,
+    pubkey: &Pubkey,
+) -> transaction::Result<Slot> {
+    let program = callbacks
+        .get_account_shared_data(pubkey)
+        .ok_or(TransactionError::ProgramAccountNotFound)?;
+    if bpf_loader_upgradeable::check_id(program.owner()) {
+        if let Ok(UpgradeableLoaderState::Program {
+            programdata_address,
+        }) = program.state()
+        {
+            let programdata = callbacks
+                .get_account_shared_data(&programdata_address)
+                .ok_or(TransactionError::ProgramAccountNotFound)?;
+            if let Ok(UpgradeableLoaderState::ProgramData { slot, .. }) = programdata.state() {
+                return Ok(slot);
+            }
+        }
+        Err(TransactionError::ProgramAccountNotFound)
+    } else if loader_v4::check_id(program.owner()) {
+        let state =
+            get_loader_v4_state(program.data()).ok_or(TransactionError::ProgramAccountNotFound)?;
+        Ok(state.slot)
+    } else {
+        Ok(0)
+    }
+}
+
+fn load_program_accounts<CB: TransactionProcessingCallback>(
+    callbacks: &CB,
+    pubkey: &Pubkey,
+) -> ProgramAccountLoadResult {
+    let Some(program_account) = callbacks.get_account_shared_data(pubkey) else {
+        return ProgramAccountLoadResult::AccountNotFound;
+    };
+
+    if loader_v4::check_id(program_account.owner()) {
+        return get_loader_v4_state(program_account.data())
+            .filter(|state| state.status != LoaderV4Status::Retracted)
+            .map(|state| ProgramAccountLoadResult::ProgramOfLoaderV4(program_account, state.slot))
+            .unwrap_or(ProgramAccountLoadResult::InvalidAccountData);
+    }
+
+    if !bpf_loader_upgradeable::check_id(program_account.owner()) {
+        return ProgramAccountLoadResult::ProgramOfLoaderV1orV2(program_account);
+    }
+
+    if let Ok(UpgradeableLoaderState::Program {
+        programdata_address,
+    }) = program_account.state()
+    {
+        if let Some(programdata_account) = callbacks.get_account_shared_data(&programdata_address) {
+            if let Ok(UpgradeableLoaderState::ProgramData { slot, .. }) =
+                programdata_account.state()
+            {
+                return ProgramAccountLoadResult::ProgramOfLoaderV3(
+                    program_account,
+                    programdata_account,
+                    slot,
+                );
+            }
+        }
+    }
+    ProgramAccountLoadResult::InvalidAccountData
+}
+
+/// Deserialize the `LoaderV4State` header of a loader-v4 program account.
+fn get_loader_v4_state(data: &[u8]) -> Option<LoaderV4State> {
+    let data = data.get(..LoaderV4State::program_data_offset())?;
+    let slot = u64::from_le_bytes(data[0..8].try_into().ok()?);
+    let authority_address = Pubkey::try_from(&data[8..40]).ok()?;
+    let status = match u64::from_le_bytes(data[40..48].try_into().ok()?) {
+        0 => LoaderV4Status::Retracted,
+        1 => LoaderV4Status::Deployed,
+        2 => LoaderV4Status::Finalized,
+        _ => return None,
+    };
+    Some(LoaderV4State {
+        slot,
+        authority_address,
+        status,
+    })
+}
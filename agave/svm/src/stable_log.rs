@@ -0,0 +1,74 @@
+//! Stable program log messages.
+//!
+//! The format of these log messages should not be modified to avoid breaking
+//! downstream consumers of program logging.
+
+use {
+    crate::log_collector::LogCollector,
+    solana_sdk::pubkey::Pubkey,
+    std::{cell::RefCell, fmt::Display, rc::Rc},
+};
+
+fn log(log_collector: &Option<Rc<RefCell<LogCollector>>>, message: &str) {
+    if let Some(log_collector) = log_collector {
+        log_collector.borrow_mut().log(message);
+    }
+}
+
+/// Log a program invoke.
+///
+/// The general form is:
+///
+/// ```notrust
+/// "Program <address> invoke [<depth>]"
+/// ```
+pub fn program_invoke(
+    log_collector: &Option<Rc<RefCell<LogCollector>>>,
+    program_id: &Pubkey,
+    invoke_depth: usize,
+) {
+    log(
+        log_collector,
+        &format!("Program {program_id} invoke [{invoke_depth}]"),
+    );
+}
+
+/// Log a message from the program itself.
+///
+/// The general form is:
+///
+/// ```notrust
+/// "Program log: <program-generated output>"
+/// ```
+pub fn program_log(log_collector: &Option<Rc<RefCell<LogCollector>>>, message: &str) {
+    log(log_collector, &format!("Program log: {message}"));
+}
+
+/// Log successful program execution.
+///
+/// The general form is:
+///
+/// ```notrust
+/// "Program <address> success"
+/// ```
+pub fn program_success(log_collector: &Option<Rc<RefCell<LogCollector>>>, program_id: &Pubkey) {
+    log(log_collector, &format!("Program {program_id} success"));
+}
+
+/// Log program execution failure.
+///
+/// The general form is:
+///
+/// ```notrust
+/// "Program <address> failed: <program error details>"
+/// ```
+pub fn program_failure<E: Display>(
+    log_collector: &Option<Rc<RefCell<LogCollector>>>,
+    program_id: &Pubkey,
+    err: &E,
+) {
+    log(
+        log_collector,
+        &format!("Program {program_id} failed: {err}"),
+    );
+}
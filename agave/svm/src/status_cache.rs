@@ -0,0 +1,9 @@
+use solana_sdk::{hash::Hash, signature::Signature};
+
+/// Records the transactions which were already processed, so they are not
+/// processed again.
+pub trait StatusCache: Send + Sync {
+    /// Whether the transaction whose fee payer signature is `signature`,
+    /// referencing `recent_blockhash`, was already processed.
+    fn is_processed(&self, signature: &Signature, recent_blockhash: &Hash) -> bool;
+}
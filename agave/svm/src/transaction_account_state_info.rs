@@ -0,0 +1,83 @@
+use {
+    crate::account_rent_state::{check_rent_state_with_account, RentState},
+    solana_sdk::{
+        account::ReadableAccount,
+        message::SanitizedMessage,
+        native_loader,
+        rent::Rent,
+        transaction::Result,
+        transaction_context::{IndexOfAccount, TransactionContext},
+    },
+};
+
+/// The rent state of each account of a transaction, recorded before and after
+/// execution to check the transitions are allowed.
+#[derive(PartialEq, Debug)]
+pub(crate) struct TransactionAccountStateInfo {
+    rent_state: Option<RentState>, // None: readonly account
+}
+
+impl TransactionAccountStateInfo {
+    pub(crate) fn new(
+        rent: &Rent,
+        transaction_context: &TransactionContext,
+        message: &SanitizedMessage,
+    ) -> Vec<Self> {
+        (0..message.account_keys().len())
+            .map(|i| {
+                let rent_state = if message.is_writable(i) {
+                    let state = if let Ok(account) =
+                        transaction_context.get_account_at_index(i as IndexOfAccount)
+                    {
+                        let account = account.borrow();
+
+                        // Native programs appear to be RentPaying because they carry low lamport
+                        // balances; however they will never be loaded as writable
+                        debug_assert!(!native_loader::check_id(account.owner()));
+
+                        Some(RentState::from_account(&account, rent))
+                    } else {
+                        None
+                    };
+                    debug_assert!(
+                        state.is_some(),
+                        "message and transaction context out of sync, fatal"
+                    );
+                    state
+                } else {
+                    None
+                };
+                Self { rent_state }
+            })
+            .collect()
+    }
+
+    pub(crate) fn verify_changes(
+        pre_state_infos: &[Self],
+        post_state_infos: &[Self],
+        transaction_context: &TransactionContext,
+    ) -> Result<()> {
+        for (i, (pre_state_info, post_state_info)) in
+            pre_state_infos.iter().zip(post_state_infos).enumerate()
+        {
+            if let Some((pre_rent_state, post_rent_state)) = pre_state_info
+                .rent_state
+                .as_ref()
+                .zip(post_state_info.rent_state.as_ref())
+            {
+                let address = transaction_context
+                    .get_key_of_account_at_index(i as IndexOfAccount)
+                    .expect(
+                        "account must exist at TransactionContext index if rent-states are Some",
+                    );
+                check_rent_state_with_account(
+                    pre_rent_state,
+                    post_rent_state,
+                    address,
+                    i as IndexOfAccount,
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,188 @@
+//! Runs the Solana SVM specification test harness against the Agave SVM.
+
+use {
+    agave_program_cache::{BlockRelation, ForkGraph, ProgramCache},
+    agave_svm::{
+        builtins::DEFAULT_COMPUTE_UNITS, callbacks::TransactionProcessingCallback,
+        AgaveTransactionBatchProcessor, ExecutionRecordingConfig, RuntimeConfig,
+        RuntimeEnvironment,
+    },
+    solana_sdk::{
+        account::{AccountSharedData, ReadableAccount},
+        clock::Slot,
+        epoch_schedule::EpochSchedule,
+        feature_set::FeatureSet,
+        fee::FeeStructure,
+        hash::Hash,
+        pubkey::Pubkey,
+        rent_collector::RentCollector,
+        transaction::SanitizedTransaction,
+    },
+    solana_svm::{
+        specification::{
+            LoadAndExecuteSanitizedTransactionsOutput, TransactionBatchProcessor,
+            TransactionCheckResult,
+        },
+        test_harness::{
+            fixture::SvmFixture, SolanaSvmTestHarness, SvmTestEnvironment,
+            TestTransactionBatchProcessor,
+        },
+    },
+    std::{
+        collections::HashMap,
+        path::{Path, PathBuf},
+        sync::{Arc, RwLock},
+    },
+};
+
+/// Callbacks serving the accounts of a test environment.
+struct TestCallbacks {
+    accounts: HashMap<Pubkey, AccountSharedData>,
+    blockhash: Hash,
+    lamports_per_signature: u64,
+    feature_set: Arc<FeatureSet>,
+    rent_collector: RentCollector,
+}
+
+impl TransactionProcessingCallback for TestCallbacks {
+    fn account_matches_owners(&self, account: &Pubkey, owners: &[Pubkey]) -> Option<usize> {
+        let account = self.accounts.get(account)?;
+        if account.lamports() == 0 {
+            return None;
+        }
+        owners.iter().position(|owner| account.owner() == owner)
+    }
+
+    fn get_account_shared_data(&self, pubkey: &Pubkey) -> Option<AccountSharedData> {
+        self.accounts.get(pubkey).cloned()
+    }
+
+    fn get_last_blockhash_and_lamports_per_signature(&self) -> (Hash, u64) {
+        (self.blockhash, self.lamports_per_signature)
+    }
+
+    fn get_lamports_per_signature_for_blockhash(
+        &self,
+        blockhash: &Hash,
+        _max_age: usize,
+    ) -> Option<u64> {
+        // The environment's blockhash is the only one, and is never too old.
+        (*blockhash == self.blockhash).then_some(self.lamports_per_signature)
+    }
+
+    fn get_rent_collector(&self) -> &RentCollector {
+        &self.rent_collector
+    }
+
+    fn get_feature_set(&self) -> Arc<FeatureSet> {
+        self.feature_set.clone()
+    }
+}
+
+/// Every test environment consists of a single slot.
+struct SingleSlotForkGraph;
+
+impl ForkGraph for SingleSlotForkGraph {
+    fn relationship(&self, _a: Slot, _b: Slot) -> BlockRelation {
+        BlockRelation::Equal
+    }
+}
+
+struct TestProcessor(AgaveTransactionBatchProcessor<TestCallbacks, SingleSlotForkGraph>);
+
+impl TransactionBatchProcessor for TestProcessor {
+    fn check_transactions(
+        &self,
+        sanitized_txs: &[SanitizedTransaction],
+        max_age: usize,
+    ) -> Vec<TransactionCheckResult> {
+        self.0.check_transactions(sanitized_txs, max_age)
+    }
+
+    fn load_and_execute_sanitized_transactions(
+        &self,
+        sanitized_txs: &[SanitizedTransaction],
+        max_age: usize,
+    ) -> LoadAndExecuteSanitizedTransactionsOutput {
+        self.0
+            .load_and_execute_sanitized_transactions(sanitized_txs, max_age)
+    }
+}
+
+impl TestTransactionBatchProcessor for TestProcessor {
+    const BUILTIN_COMPUTE_UNITS: u64 = DEFAULT_COMPUTE_UNITS;
+
+    fn from_test_environment(environment: &SvmTestEnvironment) -> Self {
+        let callbacks = TestCallbacks {
+            accounts: environment.accounts.iter().cloned().collect(),
+            blockhash: environment.blockhash,
+            lamports_per_signature: environment.lamports_per_signature,
+            feature_set: Arc::new(environment.feature_set.clone()),
+            rent_collector: RentCollector::default(),
+        };
+        let mut program_cache = ProgramCache::new(0, 0);
+        program_cache.set_fork_graph(Arc::new(RwLock::new(SingleSlotForkGraph)));
+        Self(AgaveTransactionBatchProcessor::new(
+            callbacks,
+            ExecutionRecordingConfig {
+                enable_cpi_recording: false,
+                enable_log_recording: false,
+                enable_return_data_recording: false,
+                limit_to_load_programs: false,
+                log_messages_bytes_limit: None,
+            },
+            Arc::new(RuntimeConfig {
+                compute_budget: environment.compute_budget,
+                log_messages_bytes_limit: None,
+                transaction_account_lock_limit: None,
+            }),
+            Arc::new(RuntimeEnvironment {
+                epoch: 0,
+                epoch_schedule: EpochSchedule::default(),
+                fee_structure: FeeStructure::default(),
+                slot: 0,
+            }),
+            Arc::new(RwLock::new(program_cache)),
+        ))
+    }
+}
+
+#[test]
+fn test_conformance() {
+    SolanaSvmTestHarness::new().run_all::<TestProcessor>();
+}
+
+fn fixtures_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("../../solana/svm/fixtures")
+}
+
+#[test]
+fn test_fixtures() {
+    let reports = SolanaSvmTestHarness::new()
+        .run_fixtures::<TestProcessor>(&fixtures_dir())
+        .unwrap();
+    assert!(!reports.is_empty());
+    for report in reports {
+        assert!(
+            report.passed(),
+            "{}: {}",
+            report.path.display(),
+            report.diffs.join("\n")
+        );
+    }
+}
+
+#[test]
+fn test_fixture_mismatch() {
+    let mut fixture =
+        SvmFixture::load(&fixtures_dir().join("system_transfer_failures.json")).unwrap();
+    fixture.expected.swap(0, 1);
+    let diffs = fixture.run::<TestProcessor>();
+    assert_eq!(diffs.len(), 4, "{}", diffs.join("\n"));
+    assert!(diffs[0].starts_with("transaction 0: load result"));
+    assert!(diffs[1].starts_with("transaction 0: execution result"));
+
+    fixture.expected.pop();
+    let diffs = fixture.run::<TestProcessor>();
+    assert!(diffs[0].starts_with("expected 1 results, got 2 load results"));
+}
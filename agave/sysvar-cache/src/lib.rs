@@ -1,20 +1,546 @@
 //! Agave Sysvar Cache.
 
+#[allow(deprecated)]
+use solana_sdk::sysvar::{fees::Fees, recent_blockhashes::RecentBlockhashes};
 use {
-    solana_sdk::sysvar::{
-        clock::Clock, epoch_rewards::EpochRewards, epoch_schedule::EpochSchedule,
-        last_restart_slot::LastRestartSlot, rent::Rent, slot_hashes::SlotHashes,
-        stake_history::StakeHistory,
+    solana_sdk::{
+        account::{create_account_shared_data_with_fields, from_account, AccountSharedData},
+        clock::Slot,
+        instruction::InstructionError,
+        pubkey::Pubkey,
+        rent_collector::RENT_EXEMPT_RENT_EPOCH,
+        sysvar::{
+            self, clock::Clock, epoch_rewards::EpochRewards, epoch_schedule::EpochSchedule,
+            last_restart_slot::LastRestartSlot, rent::Rent, slot_hashes::SlotHashes,
+            stake_history::StakeHistory, Sysvar,
+        },
     },
-    std::sync::Arc,
+    std::{collections::HashMap, sync::Arc},
 };
 
+/// Sysvar values which take precedence over their accounts, both when the
+/// cache is filled and when transaction accounts are loaded.
+#[derive(Default)]
+pub struct SysvarOverrides {
+    pub sysvars: HashMap<Pubkey, AccountSharedData>,
+}
+
+impl SysvarOverrides {
+    /// Pin the value of a sysvar.
+    ///
+    /// Like the sysvar accounts of a bank, the account is rent-exempt under
+    /// the default rent.
+    pub fn set_sysvar<S: Sysvar>(&mut self, sysvar: &S) {
+        let lamports = Rent::default().minimum_balance(S::size_of());
+        let account =
+            create_account_shared_data_with_fields(sysvar, (lamports, RENT_EXEMPT_RENT_EPOCH));
+        self.sysvars.insert(S::id(), account);
+    }
+
+    pub fn get(&self, sysvar_id: &Pubkey) -> Option<&AccountSharedData> {
+        self.sysvars.get(sysvar_id)
+    }
+}
+
+#[derive(Default)]
 pub struct SysvarCache {
     pub clock: Option<Arc<Clock>>,
     pub epoch_schedule: Option<Arc<EpochSchedule>>,
     pub epoch_rewards: Option<Arc<EpochRewards>>,
+    #[allow(deprecated)]
+    pub fees: Option<Arc<Fees>>,
     pub rent: Option<Arc<Rent>>,
     pub slot_hashes: Option<Arc<SlotHashes>>,
+    #[allow(deprecated)]
+    pub recent_blockhashes: Option<Arc<RecentBlockhashes>>,
     pub stake_history: Option<Arc<StakeHistory>>,
     pub last_restart_slot: Option<Arc<LastRestartSlot>>,
+    /// The slot the cache was last filled for.
+    filled_for_slot: Option<Slot>,
+}
+
+impl SysvarCache {
+    pub fn get_clock(&self) -> Result<Arc<Clock>, InstructionError> {
+        get_entry(&self.clock)
+    }
+
+    pub fn get_epoch_schedule(&self) -> Result<Arc<EpochSchedule>, InstructionError> {
+        get_entry(&self.epoch_schedule)
+    }
+
+    pub fn get_epoch_rewards(&self) -> Result<Arc<EpochRewards>, InstructionError> {
+        get_entry(&self.epoch_rewards)
+    }
+
+    #[deprecated]
+    #[allow(deprecated)]
+    pub fn get_fees(&self) -> Result<Arc<Fees>, InstructionError> {
+        get_entry(&self.fees)
+    }
+
+    pub fn get_rent(&self) -> Result<Arc<Rent>, InstructionError> {
+        get_entry(&self.rent)
+    }
+
+    pub fn get_slot_hashes(&self) -> Result<Arc<SlotHashes>, InstructionError> {
+        get_entry(&self.slot_hashes)
+    }
+
+    #[deprecated]
+    #[allow(deprecated)]
+    pub fn get_recent_blockhashes(&self) -> Result<Arc<RecentBlockhashes>, InstructionError> {
+        get_entry(&self.recent_blockhashes)
+    }
+
+    pub fn get_stake_history(&self) -> Result<Arc<StakeHistory>, InstructionError> {
+        get_entry(&self.stake_history)
+    }
+
+    pub fn get_last_restart_slot(&self) -> Result<Arc<LastRestartSlot>, InstructionError> {
+        get_entry(&self.last_restart_slot)
+    }
+
+    /// The serialized account data of a cached sysvar.
+    #[allow(deprecated)]
+    pub fn get_sysvar_data(&self, sysvar_id: &Pubkey) -> Result<Vec<u8>, InstructionError> {
+        if sysvar::clock::check_id(sysvar_id) {
+            serialize_entry(&self.clock)
+        } else if sysvar::epoch_schedule::check_id(sysvar_id) {
+            serialize_entry(&self.epoch_schedule)
+        } else if sysvar::epoch_rewards::check_id(sysvar_id) {
+            serialize_entry(&self.epoch_rewards)
+        } else if sysvar::fees::check_id(sysvar_id) {
+            serialize_entry(&self.fees)
+        } else if sysvar::rent::check_id(sysvar_id) {
+            serialize_entry(&self.rent)
+        } else if sysvar::slot_hashes::check_id(sysvar_id) {
+            serialize_entry(&self.slot_hashes)
+        } else if sysvar::recent_blockhashes::check_id(sysvar_id) {
+            serialize_entry(&self.recent_blockhashes)
+        } else if sysvar::stake_history::check_id(sysvar_id) {
+            serialize_entry(&self.stake_history)
+        } else if sysvar::last_restart_slot::check_id(sysvar_id) {
+            serialize_entry(&self.last_restart_slot)
+        } else {
+            Err(InstructionError::UnsupportedSysvar)
+        }
+    }
+
+    /// Copy `buf.len()` bytes of a cached sysvar's account data, starting at
+    /// `offset`, into `buf`.
+    pub fn read_sysvar_into(
+        &self,
+        sysvar_id: &Pubkey,
+        offset: usize,
+        buf: &mut [u8],
+    ) -> Result<(), InstructionError> {
+        let data = self.get_sysvar_data(sysvar_id)?;
+        let end = offset
+            .checked_add(buf.len())
+            .ok_or(InstructionError::ArithmeticOverflow)?;
+        let bytes = data
+            .get(offset..end)
+            .ok_or(InstructionError::InvalidArgument)?;
+        buf.copy_from_slice(bytes);
+        Ok(())
+    }
+
+    /// The slot the cache was last filled for, if it was filled since it was
+    /// last reset.
+    pub fn filled_for_slot(&self) -> Option<Slot> {
+        self.filled_for_slot
+    }
+
+    /// Populate every empty entry from its sysvar account, fetched through
+    /// `get_account`, and mark the cache as filled for `slot`. Entries whose
+    /// account is missing or fails to deserialize remain empty, as sysvars
+    /// such as the deprecated `Fees` are usually absent.
+    ///
+    /// Overridden sysvars are filled from their overrides instead. Entries
+    /// already present are kept, so the cache must be reset whenever the
+    /// overrides change.
+    #[allow(deprecated)]
+    pub fn fill_missing_entries<F>(
+        &mut self,
+        slot: Slot,
+        sysvar_overrides: Option<&SysvarOverrides>,
+        mut get_account: F,
+    ) where
+        F: FnMut(&Pubkey) -> Option<AccountSharedData>,
+    {
+        fill_entry(&mut self.clock, sysvar_overrides, &mut get_account);
+        fill_entry(&mut self.epoch_schedule, sysvar_overrides, &mut get_account);
+        fill_entry(&mut self.epoch_rewards, sysvar_overrides, &mut get_account);
+        fill_entry(&mut self.fees, sysvar_overrides, &mut get_account);
+        fill_entry(&mut self.rent, sysvar_overrides, &mut get_account);
+        fill_entry(&mut self.slot_hashes, sysvar_overrides, &mut get_account);
+        fill_entry(
+            &mut self.recent_blockhashes,
+            sysvar_overrides,
+            &mut get_account,
+        );
+        fill_entry(&mut self.stake_history, sysvar_overrides, &mut get_account);
+        fill_entry(
+            &mut self.last_restart_slot,
+            sysvar_overrides,
+            &mut get_account,
+        );
+        self.filled_for_slot = Some(slot);
+    }
+
+    /// Clear every entry.
+    pub fn reset(&mut self) {
+        *self = SysvarCache::default();
+    }
+}
+
+fn get_entry<S>(entry: &Option<Arc<S>>) -> Result<Arc<S>, InstructionError> {
+    entry.clone().ok_or(InstructionError::UnsupportedSysvar)
+}
+
+fn serialize_entry<S: Sysvar>(entry: &Option<Arc<S>>) -> Result<Vec<u8>, InstructionError> {
+    let entry = entry.as_ref().ok_or(InstructionError::UnsupportedSysvar)?;
+    let mut data =
+        bincode::serialize(entry.as_ref()).map_err(|_| InstructionError::GenericError)?;
+    // Sysvar accounts are allocated at their maximum size.
+    data.resize(data.len().max(S::size_of()), 0);
+    Ok(data)
+}
+
+fn fill_entry<S, F>(
+    entry: &mut Option<Arc<S>>,
+    sysvar_overrides: Option<&SysvarOverrides>,
+    get_account: &mut F,
+) where
+    S: Sysvar,
+    F: FnMut(&Pubkey) -> Option<AccountSharedData>,
+{
+    if entry.is_some() {
+        return;
+    }
+    let sysvar_id = S::id();
+    *entry = match sysvar_overrides.and_then(|overrides| overrides.get(&sysvar_id)) {
+        Some(account) => from_account::<S, _>(account),
+        None => get_account(&sysvar_id).and_then(|account| from_account::<S, _>(&account)),
+    }
+    .map(Arc::new);
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        solana_sdk::{
+            account::{create_account_shared_data_for_test, ReadableAccount},
+            hash::Hash,
+            stake_history::StakeHistoryEntry,
+            sysvar::SysvarId,
+        },
+        std::collections::HashSet,
+    };
+
+    fn clock() -> Clock {
+        Clock {
+            slot: 5,
+            unix_timestamp: 1_700_000_000,
+            ..Clock::default()
+        }
+    }
+
+    fn epoch_rewards() -> EpochRewards {
+        EpochRewards {
+            total_rewards: 100,
+            distributed_rewards: 10,
+            ..EpochRewards::default()
+        }
+    }
+
+    fn rent() -> Rent {
+        Rent {
+            lamports_per_byte_year: 1,
+            ..Rent::default()
+        }
+    }
+
+    fn slot_hashes() -> SlotHashes {
+        SlotHashes::new(&[(4, Hash::new(&[4; 32])), (3, Hash::new(&[3; 32]))])
+    }
+
+    fn stake_history() -> StakeHistory {
+        let mut stake_history = StakeHistory::default();
+        stake_history.add(1, StakeHistoryEntry::with_effective(10));
+        stake_history
+    }
+
+    fn last_restart_slot() -> LastRestartSlot {
+        LastRestartSlot {
+            last_restart_slot: 3,
+        }
+    }
+
+    /// The accounts of every sysvar except the deprecated ones.
+    fn sysvar_accounts() -> HashMap<Pubkey, AccountSharedData> {
+        let mut accounts = HashMap::new();
+        let mut insert = |sysvar_id, account| {
+            accounts.insert(sysvar_id, account);
+        };
+        insert(Clock::id(), create_account_shared_data_for_test(&clock()));
+        insert(
+            EpochSchedule::id(),
+            create_account_shared_data_for_test(&EpochSchedule::custom(32, 32, false)),
+        );
+        insert(
+            EpochRewards::id(),
+            create_account_shared_data_for_test(&epoch_rewards()),
+        );
+        insert(Rent::id(), create_account_shared_data_for_test(&rent()));
+        insert(
+            SlotHashes::id(),
+            create_account_shared_data_for_test(&slot_hashes()),
+        );
+        insert(
+            StakeHistory::id(),
+            create_account_shared_data_for_test(&stake_history()),
+        );
+        insert(
+            LastRestartSlot::id(),
+            create_account_shared_data_for_test(&last_restart_slot()),
+        );
+        accounts
+    }
+
+    fn filled_cache(accounts: &HashMap<Pubkey, AccountSharedData>) -> SysvarCache {
+        let mut sysvar_cache = SysvarCache::default();
+        sysvar_cache.fill_missing_entries(0, None, |pubkey| accounts.get(pubkey).cloned());
+        sysvar_cache
+    }
+
+    #[test]
+    fn test_fill_missing_entries() {
+        let accounts = sysvar_accounts();
+        let mut requested = HashSet::new();
+        let mut sysvar_cache = SysvarCache::default();
+        assert_eq!(sysvar_cache.filled_for_slot(), None);
+        sysvar_cache.fill_missing_entries(5, None, |pubkey| {
+            requested.insert(*pubkey);
+            accounts.get(pubkey).cloned()
+        });
+
+        // The deprecated sysvars are absent, yet the cache counts as filled.
+        assert_eq!(sysvar_cache.filled_for_slot(), Some(5));
+
+        assert!(accounts
+            .keys()
+            .all(|sysvar_id| requested.contains(sysvar_id)));
+        assert_eq!(*sysvar_cache.get_clock().unwrap(), clock());
+        assert_eq!(
+            *sysvar_cache.get_epoch_schedule().unwrap(),
+            EpochSchedule::custom(32, 32, false)
+        );
+        assert_eq!(*sysvar_cache.get_epoch_rewards().unwrap(), epoch_rewards());
+        assert_eq!(*sysvar_cache.get_rent().unwrap(), rent());
+        assert_eq!(*sysvar_cache.get_slot_hashes().unwrap(), slot_hashes());
+        assert_eq!(*sysvar_cache.get_stake_history().unwrap(), stake_history());
+        assert_eq!(
+            *sysvar_cache.get_last_restart_slot().unwrap(),
+            last_restart_slot()
+        );
+    }
+
+    #[test]
+    fn test_fill_missing_entries_missing_or_corrupt_account() {
+        let mut accounts = sysvar_accounts();
+        accounts.remove(&Rent::id());
+        accounts.insert(Clock::id(), AccountSharedData::new(1, 3, &sysvar::id()));
+        let sysvar_cache = filled_cache(&accounts);
+
+        assert_eq!(
+            sysvar_cache.get_rent(),
+            Err(InstructionError::UnsupportedSysvar)
+        );
+        assert_eq!(
+            sysvar_cache.get_clock(),
+            Err(InstructionError::UnsupportedSysvar)
+        );
+        assert_eq!(*sysvar_cache.get_slot_hashes().unwrap(), slot_hashes());
+    }
+
+    #[test]
+    fn test_fill_missing_entries_keeps_present_entries() {
+        let cached_clock = Clock {
+            slot: 1,
+            ..Clock::default()
+        };
+        let mut sysvar_cache = SysvarCache {
+            clock: Some(Arc::new(cached_clock.clone())),
+            ..SysvarCache::default()
+        };
+        let accounts = sysvar_accounts();
+        sysvar_cache.fill_missing_entries(0, None, |pubkey| {
+            assert_ne!(*pubkey, Clock::id());
+            accounts.get(pubkey).cloned()
+        });
+
+        assert_eq!(*sysvar_cache.get_clock().unwrap(), cached_clock);
+        assert_eq!(*sysvar_cache.get_rent().unwrap(), rent());
+    }
+
+    #[test]
+    fn test_sysvar_overrides() {
+        let overridden_clock = Clock {
+            slot: 9,
+            ..Clock::default()
+        };
+        let mut sysvar_overrides = SysvarOverrides::default();
+        sysvar_overrides.set_sysvar(&overridden_clock);
+        let account = sysvar_overrides.get(&Clock::id()).unwrap();
+        assert!(Rent::default().is_exempt(account.lamports(), account.data().len()));
+        assert_eq!(account.rent_epoch(), RENT_EXEMPT_RENT_EPOCH);
+
+        let accounts = sysvar_accounts();
+        let mut sysvar_cache = SysvarCache::default();
+        sysvar_cache.fill_missing_entries(0, Some(&sysvar_overrides), |pubkey| {
+            assert_ne!(*pubkey, Clock::id());
+            accounts.get(pubkey).cloned()
+        });
+        assert_eq!(*sysvar_cache.get_clock().unwrap(), overridden_clock);
+        assert_eq!(*sysvar_cache.get_rent().unwrap(), rent());
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut sysvar_cache = filled_cache(&sysvar_accounts());
+        assert!(sysvar_cache.get_clock().is_ok());
+        assert_eq!(sysvar_cache.filled_for_slot(), Some(0));
+
+        sysvar_cache.reset();
+        assert_eq!(sysvar_cache.filled_for_slot(), None);
+        assert!(sysvar_cache.get_clock().is_err());
+        assert!(sysvar_cache.get_epoch_schedule().is_err());
+        assert!(sysvar_cache.get_epoch_rewards().is_err());
+        assert!(sysvar_cache.get_rent().is_err());
+        assert!(sysvar_cache.get_slot_hashes().is_err());
+        assert!(sysvar_cache.get_stake_history().is_err());
+        assert!(sysvar_cache.get_last_restart_slot().is_err());
+    }
+
+    #[test]
+    fn test_absent_sysvar() {
+        let sysvar_cache = SysvarCache::default();
+        assert_eq!(
+            sysvar_cache.get_clock(),
+            Err(InstructionError::UnsupportedSysvar)
+        );
+        assert_eq!(
+            sysvar_cache.get_sysvar_data(&Clock::id()),
+            Err(InstructionError::UnsupportedSysvar)
+        );
+        assert_eq!(
+            sysvar_cache.read_sysvar_into(&Clock::id(), 0, &mut [0; 8]),
+            Err(InstructionError::UnsupportedSysvar)
+        );
+
+        // Not a sysvar at all.
+        let sysvar_cache = filled_cache(&sysvar_accounts());
+        assert_eq!(
+            sysvar_cache.get_sysvar_data(&Pubkey::new_unique()),
+            Err(InstructionError::UnsupportedSysvar)
+        );
+    }
+
+    #[test]
+    fn test_read_sysvar_into() {
+        let sysvar_cache = filled_cache(&sysvar_accounts());
+        let data = sysvar_cache.get_sysvar_data(&Clock::id()).unwrap();
+        assert_eq!(data.len(), Clock::size_of());
+
+        let mut slot = [0; 8];
+        sysvar_cache
+            .read_sysvar_into(&Clock::id(), 0, &mut slot)
+            .unwrap();
+        assert_eq!(u64::from_le_bytes(slot), clock().slot);
+        let mut tail = [0; 8];
+        sysvar_cache
+            .read_sysvar_into(&Clock::id(), data.len() - 8, &mut tail)
+            .unwrap();
+        assert_eq!(tail, data[data.len() - 8..]);
+        sysvar_cache
+            .read_sysvar_into(&Clock::id(), data.len(), &mut [])
+            .unwrap();
+
+        assert_eq!(
+            sysvar_cache.read_sysvar_into(&Clock::id(), data.len() - 7, &mut [0; 8]),
+            Err(InstructionError::InvalidArgument)
+        );
+        assert_eq!(
+            sysvar_cache.read_sysvar_into(&Clock::id(), data.len() + 1, &mut []),
+            Err(InstructionError::InvalidArgument)
+        );
+        assert_eq!(
+            sysvar_cache.read_sysvar_into(&Clock::id(), usize::MAX, &mut [0; 1]),
+            Err(InstructionError::ArithmeticOverflow)
+        );
+    }
+
+    #[test]
+    fn test_get_sysvar_data_padded_to_account_size() {
+        let accounts = sysvar_accounts();
+        let sysvar_cache = filled_cache(&accounts);
+
+        let data = sysvar_cache.get_sysvar_data(&SlotHashes::id()).unwrap();
+        assert_eq!(data.len(), SlotHashes::size_of());
+        assert_eq!(data, accounts[&SlotHashes::id()].data());
+        assert_eq!(
+            bincode::deserialize::<SlotHashes>(&data).unwrap(),
+            slot_hashes()
+        );
+
+        let data = sysvar_cache.get_sysvar_data(&StakeHistory::id()).unwrap();
+        assert_eq!(data.len(), StakeHistory::size_of());
+        assert_eq!(data, accounts[&StakeHistory::id()].data());
+        assert_eq!(
+            bincode::deserialize::<StakeHistory>(&data).unwrap(),
+            stake_history()
+        );
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_deprecated_and_epoch_rewards_sysvars() {
+        use solana_sdk::{fee_calculator::FeeCalculator, sysvar::recent_blockhashes::IterItem};
+
+        let fees = Fees::new(&FeeCalculator::new(5000));
+        let blockhash = Hash::new(&[1; 32]);
+        let recent_blockhashes = RecentBlockhashes::from_iter([IterItem(0, &blockhash, 5000)]);
+        let sysvar_cache = SysvarCache {
+            fees: Some(Arc::new(fees.clone())),
+            recent_blockhashes: Some(Arc::new(recent_blockhashes.clone())),
+            epoch_rewards: Some(Arc::new(epoch_rewards())),
+            ..SysvarCache::default()
+        };
+
+        assert_eq!(*sysvar_cache.get_fees().unwrap(), fees);
+        let data = sysvar_cache.get_sysvar_data(&Fees::id()).unwrap();
+        assert_eq!(bincode::deserialize::<Fees>(&data).unwrap(), fees);
+
+        assert_eq!(
+            *sysvar_cache.get_recent_blockhashes().unwrap(),
+            recent_blockhashes
+        );
+        let data = sysvar_cache
+            .get_sysvar_data(&RecentBlockhashes::id())
+            .unwrap();
+        assert_eq!(data.len(), RecentBlockhashes::size_of());
+        assert_eq!(
+            bincode::deserialize::<RecentBlockhashes>(&data).unwrap(),
+            recent_blockhashes
+        );
+
+        assert_eq!(*sysvar_cache.get_epoch_rewards().unwrap(), epoch_rewards());
+        let data = sysvar_cache.get_sysvar_data(&EpochRewards::id()).unwrap();
+        assert_eq!(
+            bincode::deserialize::<EpochRewards>(&data).unwrap(),
+            epoch_rewards()
+        );
+    }
 }
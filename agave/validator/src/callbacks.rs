@@ -4,23 +4,70 @@ use {
         account::AccountSharedData, feature_set::FeatureSet, hash::Hash, pubkey::Pubkey,
         rent_collector::RentCollector,
     },
-    std::sync::Arc,
+    std::{collections::HashMap, sync::Arc},
 };
 
+/// The blockhashes recorded by the validator, each with the lamports per
+/// signature in effect when it was recorded.
+#[derive(Default)]
+pub struct BlockhashQueue {
+    /// The index of each blockhash, and its lamports per signature.
+    blockhashes: HashMap<Hash, (u64, u64)>,
+    last_hash: Option<Hash>,
+    last_hash_index: u64,
+}
+
+impl BlockhashQueue {
+    /// Record a new blockhash as the last one.
+    pub fn register_hash(&mut self, hash: Hash, lamports_per_signature: u64) {
+        self.last_hash_index = self.last_hash_index.saturating_add(1);
+        self.blockhashes
+            .insert(hash, (self.last_hash_index, lamports_per_signature));
+        self.last_hash = Some(hash);
+    }
+
+    /// The lamports per signature of `hash`, if it is one of the last
+    /// `max_age` blockhashes.
+    pub fn get_lamports_per_signature(&self, hash: &Hash, max_age: usize) -> Option<u64> {
+        let (hash_index, lamports_per_signature) = self.blockhashes.get(hash)?;
+        let age = self.last_hash_index - hash_index;
+        (age <= max_age as u64).then_some(*lamports_per_signature)
+    }
+}
+
 /// Simply a mock runtime callback implementation for the Agave Validator.
-pub struct AgaveValidatorRuntimeTransactionProcessingCallback;
+#[derive(Default)]
+pub struct AgaveValidatorRuntimeTransactionProcessingCallback {
+    /// The accounts visible to the validator.
+    pub accounts: HashMap<Pubkey, AccountSharedData>,
+    pub blockhash_queue: BlockhashQueue,
+}
 
 impl TransactionProcessingCallback for AgaveValidatorRuntimeTransactionProcessingCallback {
     fn account_matches_owners(&self, _account: &Pubkey, _owners: &[Pubkey]) -> Option<usize> {
         todo!()
     }
 
-    fn get_account_shared_data(&self, _pubkey: &Pubkey) -> Option<AccountSharedData> {
-        todo!()
+    fn get_account_shared_data(&self, pubkey: &Pubkey) -> Option<AccountSharedData> {
+        self.accounts.get(pubkey).cloned()
     }
 
     fn get_last_blockhash_and_lamports_per_signature(&self) -> (Hash, u64) {
-        todo!()
+        let last_hash = self.blockhash_queue.last_hash.unwrap_or_default();
+        let lamports_per_signature = self
+            .blockhash_queue
+            .get_lamports_per_signature(&last_hash, 0)
+            .unwrap_or_default();
+        (last_hash, lamports_per_signature)
+    }
+
+    fn get_lamports_per_signature_for_blockhash(
+        &self,
+        blockhash: &Hash,
+        max_age: usize,
+    ) -> Option<u64> {
+        self.blockhash_queue
+            .get_lamports_per_signature(blockhash, max_age)
     }
 
     fn get_rent_collector(&self) -> &RentCollector {
@@ -31,3 +78,38 @@ impl TransactionProcessingCallback for AgaveValidatorRuntimeTransactionProcessin
         todo!()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blockhash_queue_age() {
+        let mut queue = BlockhashQueue::default();
+        let first_hash = Hash::new_unique();
+        queue.register_hash(first_hash, 5000);
+        assert_eq!(queue.get_lamports_per_signature(&first_hash, 0), Some(5000));
+
+        let last_hash = Hash::new_unique();
+        queue.register_hash(last_hash, 10_000);
+        assert_eq!(queue.get_lamports_per_signature(&first_hash, 0), None);
+        assert_eq!(queue.get_lamports_per_signature(&first_hash, 1), Some(5000));
+        assert_eq!(
+            queue.get_lamports_per_signature(&last_hash, 0),
+            Some(10_000)
+        );
+        assert_eq!(
+            queue.get_lamports_per_signature(&Hash::new_unique(), usize::MAX),
+            None
+        );
+
+        let callbacks = AgaveValidatorRuntimeTransactionProcessingCallback {
+            blockhash_queue: queue,
+            ..Default::default()
+        };
+        assert_eq!(
+            callbacks.get_last_blockhash_and_lamports_per_signature(),
+            (last_hash, 10_000)
+        );
+    }
+}
@@ -4,8 +4,10 @@ mod callbacks;
 
 use {
     crate::callbacks::AgaveValidatorRuntimeTransactionProcessingCallback,
-    agave_program_cache::ForkGraph, agave_runtime::AgaveValidatorRuntime,
-    agave_svm::AgaveTransactionBatchProcessor,
+    agave_program_cache::{ForkGraph, ProgramRuntimeEnvironments},
+    agave_runtime::AgaveValidatorRuntime,
+    agave_svm::{AgaveTransactionBatchProcessor, RuntimeEnvironment},
+    std::sync::Arc,
 };
 
 // This is a grossly over-simplified demonstration of an adapter, bridging the
@@ -19,3 +21,105 @@ type Svm<FG> =
 pub struct AgaveValidator<FG: ForkGraph> {
     pub runtime: AgaveValidatorRuntime<Svm<FG>>,
 }
+
+impl<FG: ForkGraph> AgaveValidator<FG> {
+    /// Move the validator to a new slot, described by `runtime_environment`.
+    ///
+    /// `upcoming_environments` are the program runtime environments of the
+    /// next epoch, if they differ from the current ones.
+    pub fn new_slot(
+        &mut self,
+        runtime_environment: Arc<RuntimeEnvironment>,
+        upcoming_environments: Option<ProgramRuntimeEnvironments>,
+    ) {
+        let batch_processor = &mut self.runtime.batch_processor;
+        batch_processor.runtime_environment = runtime_environment;
+        // Sysvars such as the clock change with every slot.
+        batch_processor.reset_sysvar_cache();
+        batch_processor.prepare_program_cache_for_upcoming_epoch(upcoming_environments);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        agave_program_cache::{fork_graph::InMemoryForkGraph, ProgramCache},
+        agave_svm::{ExecutionRecordingConfig, RuntimeConfig},
+        solana_sdk::{
+            account::{create_account_shared_data_for_test, AccountSharedData},
+            clock::{Clock, Slot},
+            epoch_schedule::EpochSchedule,
+            fee::FeeStructure,
+            sysvar,
+        },
+        std::sync::RwLock,
+    };
+
+    fn runtime_environment(slot: Slot) -> Arc<RuntimeEnvironment> {
+        Arc::new(RuntimeEnvironment {
+            epoch: 0,
+            epoch_schedule: EpochSchedule::default(),
+            fee_structure: FeeStructure::default(),
+            slot,
+        })
+    }
+
+    fn clock_account(slot: Slot) -> AccountSharedData {
+        create_account_shared_data_for_test(&Clock {
+            slot,
+            ..Clock::default()
+        })
+    }
+
+    #[test]
+    fn test_new_slot_refreshes_sysvars() {
+        let mut callbacks = AgaveValidatorRuntimeTransactionProcessingCallback::default();
+        callbacks
+            .accounts
+            .insert(sysvar::clock::id(), clock_account(1));
+        let mut program_cache = ProgramCache::new(0, 0);
+        program_cache.set_fork_graph(Arc::new(RwLock::new(InMemoryForkGraph::new(
+            0,
+            EpochSchedule::default(),
+        ))));
+        let mut validator = AgaveValidator {
+            runtime: AgaveValidatorRuntime {
+                batch_processor: Svm::new(
+                    callbacks,
+                    ExecutionRecordingConfig {
+                        enable_cpi_recording: false,
+                        enable_log_recording: false,
+                        enable_return_data_recording: false,
+                        limit_to_load_programs: false,
+                        log_messages_bytes_limit: None,
+                    },
+                    Arc::new(RuntimeConfig {
+                        compute_budget: None,
+                        log_messages_bytes_limit: None,
+                        transaction_account_lock_limit: None,
+                    }),
+                    runtime_environment(1),
+                    Arc::new(RwLock::new(program_cache)),
+                ),
+            },
+        };
+        let get_clock_slot = |validator: &AgaveValidator<InMemoryForkGraph>| {
+            let batch_processor = &validator.runtime.batch_processor;
+            batch_processor.fill_missing_sysvar_cache_entries();
+            let clock = batch_processor.sysvar_cache.read().unwrap().get_clock();
+            clock.unwrap().slot
+        };
+        assert_eq!(get_clock_slot(&validator), 1);
+
+        // The bank updates the clock account before the validator moves on.
+        validator
+            .runtime
+            .batch_processor
+            .callbacks
+            .accounts
+            .insert(sysvar::clock::id(), clock_account(2));
+        validator.new_slot(runtime_environment(2), None);
+        assert_eq!(get_clock_slot(&validator), 2);
+    }
+}
@@ -4,6 +4,11 @@ use {
 };
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "test-harness",
+    derive(serde::Deserialize, serde::Serialize),
+    serde(default)
+)]
 pub struct ComputeBudget {
     pub compute_unit_limit: u64,
     pub log_64_units: u64,
@@ -1,14 +1,30 @@
 use {
     crate::prioritization_fee::{PrioritizationFeeDetails, PrioritizationFeeType},
     solana_sdk::{
-        entrypoint::HEAP_LENGTH as MIN_HEAP_FRAME_BYTES, fee::FeeBudgetLimits,
-        instruction::CompiledInstruction, pubkey::Pubkey, transaction::TransactionError,
+        address_lookup_table,
+        borsh1::try_from_slice_unchecked,
+        bpf_loader, bpf_loader_deprecated, bpf_loader_upgradeable,
+        compute_budget::{self, ComputeBudgetInstruction},
+        config, ed25519_program,
+        entrypoint::HEAP_LENGTH as MIN_HEAP_FRAME_BYTES,
+        fee::FeeBudgetLimits,
+        instruction::{CompiledInstruction, InstructionError},
+        loader_v4,
+        pubkey::Pubkey,
+        secp256k1_program, stake, system_program,
+        transaction::TransactionError,
+        vote,
     },
 };
 
 pub const DEFAULT_HEAP_COST: u64 = 8;
 
+pub const MAX_HEAP_FRAME_BYTES: u32 = 256 * 1024;
+
 pub const DEFAULT_INSTRUCTION_COMPUTE_UNIT_LIMIT: u32 = 200_000;
+/// The default compute unit limit of an instruction invoking a builtin
+/// program, which consumes far fewer units than a user space program.
+pub const MAX_BUILTIN_ALLOCATION_COMPUTE_UNIT_LIMIT: u32 = 3_000;
 pub const MAX_COMPUTE_UNIT_LIMIT: u32 = 1_400_000;
 
 pub const MAX_LOADED_ACCOUNTS_DATA_SIZE_BYTES: u32 = 64 * 1024 * 1024;
@@ -50,15 +66,249 @@ impl From<ComputeBudgetLimits> for FeeBudgetLimits {
 }
 
 pub fn process_compute_budget_instructions<'a>(
-    _instructions: impl Iterator<Item = (&'a Pubkey, &'a CompiledInstruction)>,
+    instructions: impl Iterator<Item = (&'a Pubkey, &'a CompiledInstruction)>,
 ) -> Result<ComputeBudgetLimits, TransactionError> {
-    /*
-     * Function simplified for brevity.
-     */
+    let mut num_builtin_instructions: u32 = 0;
+    let mut num_non_builtin_instructions: u32 = 0;
+    let mut updated_compute_unit_limit = None;
+    let mut updated_compute_unit_price = None;
+    let mut requested_heap_size = None;
+    let mut updated_loaded_accounts_data_size_limit = None;
+
+    for (i, (program_id, instruction)) in instructions.enumerate() {
+        if compute_budget::check_id(program_id) {
+            let invalid_instruction_data_error = TransactionError::InstructionError(
+                i as u8,
+                InstructionError::InvalidInstructionData,
+            );
+            let duplicate_instruction_error = TransactionError::DuplicateInstruction(i as u8);
+
+            match try_from_slice_unchecked(&instruction.data) {
+                Ok(ComputeBudgetInstruction::RequestHeapFrame(bytes)) => {
+                    if requested_heap_size.is_some() {
+                        return Err(duplicate_instruction_error);
+                    }
+                    if sanitize_requested_heap_size(bytes) {
+                        requested_heap_size = Some(bytes);
+                    } else {
+                        return Err(invalid_instruction_data_error);
+                    }
+                }
+                Ok(ComputeBudgetInstruction::SetComputeUnitLimit(compute_unit_limit)) => {
+                    if updated_compute_unit_limit.is_some() {
+                        return Err(duplicate_instruction_error);
+                    }
+                    updated_compute_unit_limit = Some(compute_unit_limit);
+                }
+                Ok(ComputeBudgetInstruction::SetComputeUnitPrice(micro_lamports)) => {
+                    if updated_compute_unit_price.is_some() {
+                        return Err(duplicate_instruction_error);
+                    }
+                    updated_compute_unit_price = Some(micro_lamports);
+                }
+                Ok(ComputeBudgetInstruction::SetLoadedAccountsDataSizeLimit(bytes)) => {
+                    if updated_loaded_accounts_data_size_limit.is_some() {
+                        return Err(duplicate_instruction_error);
+                    }
+                    updated_loaded_accounts_data_size_limit = Some(bytes);
+                }
+                _ => return Err(invalid_instruction_data_error),
+            }
+        } else if is_builtin_program(program_id) {
+            num_builtin_instructions = num_builtin_instructions.saturating_add(1);
+        } else {
+            num_non_builtin_instructions = num_non_builtin_instructions.saturating_add(1);
+        }
+    }
+
+    // sanitize limits
+    let updated_heap_bytes = requested_heap_size
+        .unwrap_or(u32::try_from(MIN_HEAP_FRAME_BYTES).unwrap()) // loader's default heap_size
+        .min(MAX_HEAP_FRAME_BYTES);
+
+    let compute_unit_limit = updated_compute_unit_limit
+        .unwrap_or_else(|| {
+            num_non_builtin_instructions
+                .saturating_mul(DEFAULT_INSTRUCTION_COMPUTE_UNIT_LIMIT)
+                .saturating_add(
+                    num_builtin_instructions
+                        .saturating_mul(MAX_BUILTIN_ALLOCATION_COMPUTE_UNIT_LIMIT),
+                )
+        })
+        .min(MAX_COMPUTE_UNIT_LIMIT);
+
+    let compute_unit_price = updated_compute_unit_price.unwrap_or(0);
+
+    let loaded_accounts_bytes = updated_loaded_accounts_data_size_limit
+        .unwrap_or(MAX_LOADED_ACCOUNTS_DATA_SIZE_BYTES)
+        .min(MAX_LOADED_ACCOUNTS_DATA_SIZE_BYTES);
+
     Ok(ComputeBudgetLimits {
-        updated_heap_bytes: 0,
-        compute_unit_limit: 0,
-        compute_unit_price: 0,
-        loaded_accounts_bytes: 0,
+        updated_heap_bytes,
+        compute_unit_limit,
+        compute_unit_price,
+        loaded_accounts_bytes,
     })
 }
+
+/// Whether `program_id` is a builtin program, which is allocated
+/// `MAX_BUILTIN_ALLOCATION_COMPUTE_UNIT_LIMIT` compute units by default.
+pub fn is_builtin_program(program_id: &Pubkey) -> bool {
+    system_program::check_id(program_id)
+        || compute_budget::check_id(program_id)
+        || vote::program::check_id(program_id)
+        || stake::program::check_id(program_id)
+        || address_lookup_table::program::check_id(program_id)
+        || config::program::check_id(program_id)
+        || bpf_loader_upgradeable::check_id(program_id)
+        || bpf_loader_deprecated::check_id(program_id)
+        || bpf_loader::check_id(program_id)
+        || loader_v4::check_id(program_id)
+        || secp256k1_program::check_id(program_id)
+        || ed25519_program::check_id(program_id)
+}
+
+fn sanitize_requested_heap_size(bytes: u32) -> bool {
+    (u32::try_from(MIN_HEAP_FRAME_BYTES).unwrap()..=MAX_HEAP_FRAME_BYTES).contains(&bytes)
+        && bytes.is_multiple_of(1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        solana_sdk::{instruction::Instruction, message::Message, system_instruction},
+    };
+
+    fn process(instructions: &[Instruction]) -> Result<ComputeBudgetLimits, TransactionError> {
+        let payer = Pubkey::new_unique();
+        let message = Message::new(instructions, Some(&payer));
+        process_compute_budget_instructions(
+            message
+                .instructions
+                .iter()
+                .map(|ix| (&message.account_keys[ix.program_id_index as usize], ix)),
+        )
+    }
+
+    fn transfer() -> Instruction {
+        system_instruction::transfer(&Pubkey::new_unique(), &Pubkey::new_unique(), 1)
+    }
+
+    fn user_space() -> Instruction {
+        Instruction::new_with_bytes(Pubkey::new_unique(), &[], vec![])
+    }
+
+    #[test]
+    fn test_defaults() {
+        assert_eq!(
+            process(&[]),
+            Ok(ComputeBudgetLimits {
+                compute_unit_limit: 0,
+                ..ComputeBudgetLimits::default()
+            })
+        );
+        assert_eq!(
+            process(&[transfer(), transfer()]),
+            Ok(ComputeBudgetLimits {
+                compute_unit_limit: 2 * MAX_BUILTIN_ALLOCATION_COMPUTE_UNIT_LIMIT,
+                ..ComputeBudgetLimits::default()
+            })
+        );
+        assert_eq!(
+            process(&[user_space(), transfer(), user_space()]),
+            Ok(ComputeBudgetLimits {
+                compute_unit_limit: 2 * DEFAULT_INSTRUCTION_COMPUTE_UNIT_LIMIT
+                    + MAX_BUILTIN_ALLOCATION_COMPUTE_UNIT_LIMIT,
+                ..ComputeBudgetLimits::default()
+            })
+        );
+        // The compute budget instructions themselves are not counted.
+        assert_eq!(
+            process(&[
+                ComputeBudgetInstruction::set_compute_unit_price(1),
+                user_space()
+            ])
+            .map(|limits| limits.compute_unit_limit),
+            Ok(DEFAULT_INSTRUCTION_COMPUTE_UNIT_LIMIT)
+        );
+        // The default limit is capped.
+        assert_eq!(
+            process(&vec![user_space(); 8]).map(|limits| limits.compute_unit_limit),
+            Ok(MAX_COMPUTE_UNIT_LIMIT)
+        );
+    }
+
+    #[test]
+    fn test_requested_limits() {
+        assert_eq!(
+            process(&[
+                ComputeBudgetInstruction::set_compute_unit_limit(MAX_COMPUTE_UNIT_LIMIT + 1),
+                ComputeBudgetInstruction::set_compute_unit_price(42),
+                ComputeBudgetInstruction::request_heap_frame(MAX_HEAP_FRAME_BYTES),
+                ComputeBudgetInstruction::set_loaded_accounts_data_size_limit(1024),
+                transfer(),
+            ]),
+            Ok(ComputeBudgetLimits {
+                updated_heap_bytes: MAX_HEAP_FRAME_BYTES,
+                compute_unit_limit: MAX_COMPUTE_UNIT_LIMIT,
+                compute_unit_price: 42,
+                loaded_accounts_bytes: 1024,
+            })
+        );
+    }
+
+    #[test]
+    fn test_heap_size_alignment() {
+        let min_heap_frame_bytes = u32::try_from(MIN_HEAP_FRAME_BYTES).unwrap();
+        for bytes in [
+            min_heap_frame_bytes - 1024,
+            min_heap_frame_bytes + 1,
+            MAX_HEAP_FRAME_BYTES + 1024,
+        ] {
+            assert_eq!(
+                process(&[ComputeBudgetInstruction::request_heap_frame(bytes)]),
+                Err(TransactionError::InstructionError(
+                    0,
+                    InstructionError::InvalidInstructionData
+                ))
+            );
+        }
+        assert_eq!(
+            process(&[ComputeBudgetInstruction::request_heap_frame(
+                min_heap_frame_bytes + 1024
+            )])
+            .map(|limits| limits.updated_heap_bytes),
+            Ok(min_heap_frame_bytes + 1024)
+        );
+    }
+
+    #[test]
+    fn test_duplicate_instructions() {
+        for instruction in [
+            ComputeBudgetInstruction::set_compute_unit_limit(1),
+            ComputeBudgetInstruction::set_compute_unit_price(1),
+            ComputeBudgetInstruction::request_heap_frame(MAX_HEAP_FRAME_BYTES),
+            ComputeBudgetInstruction::set_loaded_accounts_data_size_limit(1),
+        ] {
+            assert_eq!(
+                process(&[instruction.clone(), transfer(), instruction]),
+                Err(TransactionError::DuplicateInstruction(2))
+            );
+        }
+    }
+
+    #[test]
+    fn test_invalid_instruction_data() {
+        assert_eq!(
+            process(&[
+                transfer(),
+                Instruction::new_with_bytes(compute_budget::id(), &[255], vec![]),
+            ]),
+            Err(TransactionError::InstructionError(
+                1,
+                InstructionError::InvalidInstructionData
+            ))
+        );
+    }
+}
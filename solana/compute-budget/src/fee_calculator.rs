@@ -0,0 +1,244 @@
+use solana_sdk::{
+    ed25519_program,
+    fee::{FeeBudgetLimits, FeeStructure},
+    message::SanitizedMessage,
+    secp256k1_program,
+};
+
+/// The fee charged for a transaction, broken down by component.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FeeDetails {
+    /// The fee for the transaction's signatures, including the signatures
+    /// verified by the secp256k1 and ed25519 precompiles.
+    pub signature_fee: u64,
+    /// The fee for the accounts write-locked by the transaction.
+    pub write_lock_fee: u64,
+    /// The fee for the requested compute units and loaded accounts data.
+    pub compute_fee: u64,
+    /// The fee bid by the transaction through its compute unit price.
+    pub prioritization_fee: u64,
+}
+
+impl FeeDetails {
+    /// The total fee charged for the transaction.
+    pub fn total_fee(&self) -> u64 {
+        self.signature_fee
+            .saturating_add(self.write_lock_fee)
+            .saturating_add(self.compute_fee)
+            .saturating_add(self.prioritization_fee)
+    }
+}
+
+/// Calculate the fee charged for a transaction, given the lamports per
+/// signature of its recent blockhash or durable nonce.
+///
+/// A `lamports_per_signature` of zero makes the transaction free.
+pub fn calculate_fee_details(
+    message: &SanitizedMessage,
+    fee_structure: &FeeStructure,
+    lamports_per_signature: u64,
+    budget_limits: &FeeBudgetLimits,
+    include_loaded_accounts_data_size_in_fee: bool,
+) -> FeeDetails {
+    if lamports_per_signature == 0 {
+        return FeeDetails::default();
+    }
+
+    let signature_fee =
+        num_signatures(message).saturating_mul(fee_structure.lamports_per_signature);
+    let write_lock_fee = message
+        .num_write_locks()
+        .saturating_mul(fee_structure.lamports_per_write_lock);
+
+    // `compute_fee` covers costs for both the requested compute units and
+    // the requested loaded accounts data size.
+    let loaded_accounts_data_size_cost = if include_loaded_accounts_data_size_in_fee {
+        FeeStructure::calculate_memory_usage_cost(
+            budget_limits.loaded_accounts_data_size_limit,
+            budget_limits.heap_cost,
+        )
+    } else {
+        0
+    };
+    let total_compute_units =
+        loaded_accounts_data_size_cost.saturating_add(budget_limits.compute_unit_limit);
+    let compute_fee = fee_structure
+        .compute_fee_bins
+        .iter()
+        .find(|bin| total_compute_units <= bin.limit)
+        .or_else(|| fee_structure.compute_fee_bins.last())
+        .map(|bin| bin.fee)
+        .unwrap_or_default();
+
+    FeeDetails {
+        signature_fee,
+        write_lock_fee,
+        compute_fee,
+        prioritization_fee: budget_limits.prioritization_fee,
+    }
+}
+
+/// The number of signatures in a message, counting each signature verified
+/// by the secp256k1 and ed25519 precompiles.
+fn num_signatures(message: &SanitizedMessage) -> u64 {
+    message
+        .program_instructions_iter()
+        .filter(|(program_id, _)| {
+            secp256k1_program::check_id(program_id) || ed25519_program::check_id(program_id)
+        })
+        .filter_map(|(_, instruction)| instruction.data.first())
+        .fold(
+            u64::from(message.header().num_required_signatures),
+            |num_signatures, num_verifies| num_signatures.saturating_add(u64::from(*num_verifies)),
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        solana_sdk::{
+            fee::FeeBin, instruction::Instruction, message::Message, pubkey::Pubkey,
+            system_instruction,
+        },
+    };
+
+    fn fee_structure() -> FeeStructure {
+        FeeStructure {
+            lamports_per_signature: 5000,
+            lamports_per_write_lock: 100,
+            compute_fee_bins: vec![
+                FeeBin {
+                    limit: 1000,
+                    fee: 10,
+                },
+                FeeBin {
+                    limit: 2000,
+                    fee: 20,
+                },
+            ],
+        }
+    }
+
+    fn budget_limits(compute_unit_limit: u64) -> FeeBudgetLimits {
+        FeeBudgetLimits {
+            loaded_accounts_data_size_limit: 0,
+            heap_cost: 0,
+            compute_unit_limit,
+            prioritization_fee: 0,
+        }
+    }
+
+    fn message(instructions: &[Instruction]) -> SanitizedMessage {
+        let payer = Pubkey::new_unique();
+        let mut instructions = instructions.to_vec();
+        instructions.push(system_instruction::transfer(
+            &payer,
+            &Pubkey::new_unique(),
+            1,
+        ));
+        SanitizedMessage::try_from(Message::new(&instructions, Some(&payer))).unwrap()
+    }
+
+    #[test]
+    fn test_calculate_fee_details() {
+        let budget_limits = FeeBudgetLimits {
+            prioritization_fee: 7,
+            ..budget_limits(500)
+        };
+        let fee_details =
+            calculate_fee_details(&message(&[]), &fee_structure(), 5000, &budget_limits, false);
+        assert_eq!(
+            fee_details,
+            FeeDetails {
+                signature_fee: 5000,
+                write_lock_fee: 200,
+                compute_fee: 10,
+                prioritization_fee: 7,
+            }
+        );
+        assert_eq!(fee_details.total_fee(), 5217);
+    }
+
+    #[test]
+    fn test_calculate_fee_details_free() {
+        // A zero lamports per signature waives every component of the fee.
+        assert_eq!(
+            calculate_fee_details(
+                &message(&[]),
+                &fee_structure(),
+                0,
+                &budget_limits(500),
+                false
+            ),
+            FeeDetails::default()
+        );
+    }
+
+    #[test]
+    fn test_calculate_fee_details_precompile_signatures() {
+        let message = message(&[
+            Instruction::new_with_bytes(secp256k1_program::id(), &[2], vec![]),
+            Instruction::new_with_bytes(ed25519_program::id(), &[3], vec![]),
+            // An instruction without data verifies no signatures.
+            Instruction::new_with_bytes(ed25519_program::id(), &[], vec![]),
+        ]);
+        assert_eq!(
+            calculate_fee_details(&message, &fee_structure(), 5000, &budget_limits(0), false)
+                .signature_fee,
+            6 * 5000
+        );
+    }
+
+    #[test]
+    fn test_calculate_fee_details_compute_fee_bins() {
+        let compute_fee = |compute_unit_limit| {
+            calculate_fee_details(
+                &message(&[]),
+                &fee_structure(),
+                5000,
+                &budget_limits(compute_unit_limit),
+                false,
+            )
+            .compute_fee
+        };
+        assert_eq!(compute_fee(0), 10);
+        assert_eq!(compute_fee(1000), 10);
+        assert_eq!(compute_fee(1001), 20);
+        // Compute units beyond the last bin are charged its fee.
+        assert_eq!(compute_fee(u64::MAX), 20);
+
+        let no_bins = FeeStructure {
+            compute_fee_bins: vec![],
+            ..fee_structure()
+        };
+        assert_eq!(
+            calculate_fee_details(&message(&[]), &no_bins, 5000, &budget_limits(500), false)
+                .compute_fee,
+            0
+        );
+    }
+
+    #[test]
+    fn test_calculate_fee_details_loaded_accounts_data_size() {
+        // One page of loaded accounts data costs 8 compute units, taking the
+        // total over the first bin's limit.
+        let budget_limits = FeeBudgetLimits {
+            loaded_accounts_data_size_limit: 32 * 1024,
+            heap_cost: 8,
+            ..budget_limits(995)
+        };
+        let compute_fee = |include_loaded_accounts_data_size_in_fee| {
+            calculate_fee_details(
+                &message(&[]),
+                &fee_structure(),
+                5000,
+                &budget_limits,
+                include_loaded_accounts_data_size_in_fee,
+            )
+            .compute_fee
+        };
+        assert_eq!(compute_fee(false), 10);
+        assert_eq!(compute_fee(true), 20);
+    }
+}
@@ -2,4 +2,5 @@
 
 pub mod compute_budget;
 pub mod compute_budget_processor;
+pub mod fee_calculator;
 pub mod prioritization_fee;
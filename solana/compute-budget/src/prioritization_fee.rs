@@ -4,6 +4,30 @@ type MicroLamports = u128;
 
 pub enum PrioritizationFeeType {
     ComputeUnitPrice(u64),
+    /// A flat fee in lamports, from which the compute unit price is implied.
+    Deprecated(u64),
+}
+
+/// How fractional amounts are rounded when converting between compute unit
+/// prices and fees.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RoundingMode {
+    #[default]
+    Up,
+    Down,
+    /// Round to the nearest integer, rounding halves up.
+    Nearest,
+}
+
+impl RoundingMode {
+    fn divide(self, dividend: u128, divisor: u128) -> Option<u128> {
+        let adjustment = match self {
+            RoundingMode::Up => divisor.saturating_sub(1),
+            RoundingMode::Down => 0,
+            RoundingMode::Nearest => divisor / 2,
+        };
+        dividend.saturating_add(adjustment).checked_div(divisor)
+    }
 }
 
 #[derive(Default, Debug, PartialEq, Eq)]
@@ -14,16 +38,38 @@ pub struct PrioritizationFeeDetails {
 
 impl PrioritizationFeeDetails {
     pub fn new(fee_type: PrioritizationFeeType, compute_unit_limit: u64) -> Self {
+        Self::new_with_rounding_mode(fee_type, compute_unit_limit, RoundingMode::Up)
+    }
+
+    pub fn new_with_rounding_mode(
+        fee_type: PrioritizationFeeType,
+        compute_unit_limit: u64,
+        rounding_mode: RoundingMode,
+    ) -> Self {
         match fee_type {
             PrioritizationFeeType::ComputeUnitPrice(compute_unit_price) => {
                 let micro_lamport_fee: MicroLamports =
                     (compute_unit_price as u128).saturating_mul(compute_unit_limit as u128);
-                let fee = micro_lamport_fee
-                    .saturating_add(MICRO_LAMPORTS_PER_LAMPORT.saturating_sub(1) as u128)
-                    .checked_div(MICRO_LAMPORTS_PER_LAMPORT as u128)
+                let fee = rounding_mode
+                    .divide(micro_lamport_fee, MICRO_LAMPORTS_PER_LAMPORT as u128)
                     .and_then(|fee| u64::try_from(fee).ok())
                     .unwrap_or(u64::MAX);
 
+                Self {
+                    fee,
+                    compute_unit_price,
+                }
+            }
+            PrioritizationFeeType::Deprecated(fee) => {
+                // Without compute units there is no price to imply, and a
+                // price too large for a u64 saturates.
+                let compute_unit_price = if compute_unit_limit == 0 {
+                    0
+                } else {
+                    compute_unit_price_for_fee(fee, compute_unit_limit, rounding_mode)
+                        .unwrap_or(u64::MAX)
+                };
+
                 Self {
                     fee,
                     compute_unit_price,
@@ -40,3 +86,121 @@ impl PrioritizationFeeDetails {
         self.compute_unit_price
     }
 }
+
+/// The compute unit price at which `compute_unit_limit` compute units cost
+/// `fee` lamports, or `None` if there is no such price.
+///
+/// Rounding up yields the lowest price whose fee is at least `fee`.
+pub fn compute_unit_price_for_fee(
+    fee: u64,
+    compute_unit_limit: u64,
+    rounding_mode: RoundingMode,
+) -> Option<u64> {
+    let micro_lamport_fee: MicroLamports =
+        (fee as u128).saturating_mul(MICRO_LAMPORTS_PER_LAMPORT as u128);
+    rounding_mode
+        .divide(micro_lamport_fee, compute_unit_limit as u128)
+        .and_then(|compute_unit_price| u64::try_from(compute_unit_price).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn details(
+        fee_type: PrioritizationFeeType,
+        compute_unit_limit: u64,
+        rounding_mode: RoundingMode,
+    ) -> (u64, u64) {
+        let details = PrioritizationFeeDetails::new_with_rounding_mode(
+            fee_type,
+            compute_unit_limit,
+            rounding_mode,
+        );
+        (details.get_fee(), details.get_compute_unit_price())
+    }
+
+    #[test]
+    fn test_rounding_mode_divide() {
+        assert_eq!(RoundingMode::Up.divide(14, 10), Some(2));
+        assert_eq!(RoundingMode::Down.divide(14, 10), Some(1));
+        assert_eq!(RoundingMode::Nearest.divide(14, 10), Some(1));
+        assert_eq!(RoundingMode::Nearest.divide(15, 10), Some(2));
+        // Exact quotients are never rounded, and nothing divides by zero.
+        for rounding_mode in [RoundingMode::Up, RoundingMode::Down, RoundingMode::Nearest] {
+            assert_eq!(rounding_mode.divide(20, 10), Some(2));
+            assert_eq!(rounding_mode.divide(0, 10), Some(0));
+            assert_eq!(rounding_mode.divide(1, 0), None);
+        }
+    }
+
+    #[test]
+    fn test_compute_unit_price_fee() {
+        let fee_type = || PrioritizationFeeType::ComputeUnitPrice(1);
+        assert_eq!(details(fee_type(), 1_400_000, RoundingMode::Up), (2, 1));
+        assert_eq!(details(fee_type(), 1_400_000, RoundingMode::Down), (1, 1));
+        assert_eq!(
+            details(fee_type(), 1_400_000, RoundingMode::Nearest),
+            (1, 1)
+        );
+        assert_eq!(
+            details(fee_type(), 1_500_000, RoundingMode::Nearest),
+            (2, 1)
+        );
+        assert_eq!(details(fee_type(), 0, RoundingMode::Up), (0, 1));
+        assert_eq!(
+            PrioritizationFeeDetails::new(fee_type(), 1_400_000),
+            PrioritizationFeeDetails {
+                fee: 2,
+                compute_unit_price: 1,
+            }
+        );
+
+        // A fee too large for a u64 saturates.
+        assert_eq!(
+            details(
+                PrioritizationFeeType::ComputeUnitPrice(u64::MAX),
+                u64::MAX,
+                RoundingMode::Down
+            ),
+            (u64::MAX, u64::MAX)
+        );
+    }
+
+    #[test]
+    fn test_deprecated_fee() {
+        let fee_type = || PrioritizationFeeType::Deprecated(1);
+        assert_eq!(details(fee_type(), 3_000_000, RoundingMode::Up), (1, 1));
+        assert_eq!(details(fee_type(), 3_000_000, RoundingMode::Down), (1, 0));
+        assert_eq!(
+            details(fee_type(), 3_000_000, RoundingMode::Nearest),
+            (1, 0)
+        );
+        assert_eq!(
+            details(fee_type(), 2_000_000, RoundingMode::Nearest),
+            (1, 1)
+        );
+        assert_eq!(
+            compute_unit_price_for_fee(3, 2_000_000, RoundingMode::Up),
+            Some(2)
+        );
+
+        // There is no price for zero compute units.
+        assert_eq!(details(fee_type(), 0, RoundingMode::Up), (1, 0));
+        assert_eq!(compute_unit_price_for_fee(1, 0, RoundingMode::Up), None);
+
+        // A price too large for a u64 saturates.
+        assert_eq!(
+            details(
+                PrioritizationFeeType::Deprecated(u64::MAX),
+                1,
+                RoundingMode::Down
+            ),
+            (u64::MAX, u64::MAX)
+        );
+        assert_eq!(
+            compute_unit_price_for_fee(u64::MAX, 1, RoundingMode::Down),
+            None
+        );
+    }
+}
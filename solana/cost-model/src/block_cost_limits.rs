@@ -0,0 +1,62 @@
+//! Cost limits of a block, and the costs of the operations performed by a
+//! transaction, in compute units.
+
+use solana_sdk::{
+    address_lookup_table, bpf_loader, bpf_loader_deprecated, bpf_loader_upgradeable,
+    compute_budget, config, ed25519_program, loader_v4, pubkey::Pubkey, secp256k1_program, stake,
+    system_program, vote,
+};
+
+/// The number of compute units executed per microsecond, used to convert
+/// benchmarked operation times into compute units.
+pub const COMPUTE_UNIT_TO_US_RATIO: u64 = 30;
+/// The cost of verifying a transaction signature.
+pub const SIGNATURE_COST: u64 = COMPUTE_UNIT_TO_US_RATIO * 24;
+/// The cost of verifying a signature in the secp256k1 precompile.
+pub const SECP256K1_VERIFY_COST: u64 = COMPUTE_UNIT_TO_US_RATIO * 223;
+/// The cost of verifying a signature in the ed25519 precompile.
+pub const ED25519_VERIFY_COST: u64 = COMPUTE_UNIT_TO_US_RATIO * 76;
+/// The cost of write-locking an account.
+pub const WRITE_LOCK_UNITS: u64 = COMPUTE_UNIT_TO_US_RATIO * 10;
+/// The number of instruction data bytes that cost one compute unit.
+pub const INSTRUCTION_DATA_BYTES_COST: u64 = 140 / COMPUTE_UNIT_TO_US_RATIO;
+
+/// The maximum cost of a block.
+pub const MAX_BLOCK_UNITS: u64 = 48_000_000;
+/// The maximum cost of the simple vote transactions in a block.
+pub const MAX_VOTE_UNITS: u64 = 36_000_000;
+/// The maximum cost of the transactions write-locking a single account in a
+/// block.
+pub const MAX_WRITABLE_ACCOUNT_UNITS: u64 = 12_000_000;
+/// The maximum number of account data bytes a block may allocate.
+pub const MAX_BLOCK_ACCOUNTS_DATA_SIZE_DELTA: u64 = 100_000_000;
+
+/// The execution cost of an instruction of the given builtin program, or
+/// `None` if the program is not a builtin.
+pub fn builtin_instruction_cost(program_id: &Pubkey) -> Option<u64> {
+    let cost = if system_program::check_id(program_id) || compute_budget::check_id(program_id) {
+        150
+    } else if vote::program::check_id(program_id) {
+        2_100
+    } else if stake::program::check_id(program_id)
+        || address_lookup_table::program::check_id(program_id)
+    {
+        750
+    } else if config::program::check_id(program_id) {
+        450
+    } else if bpf_loader_upgradeable::check_id(program_id) {
+        2_370
+    } else if bpf_loader_deprecated::check_id(program_id) {
+        1_140
+    } else if bpf_loader::check_id(program_id) {
+        570
+    } else if loader_v4::check_id(program_id) {
+        2_000
+    } else if secp256k1_program::check_id(program_id) || ed25519_program::check_id(program_id) {
+        // Signature verification is charged in the signature cost.
+        0
+    } else {
+        return None;
+    };
+    Some(cost)
+}
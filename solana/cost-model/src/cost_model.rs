@@ -0,0 +1,403 @@
+//! Estimates the cost of a transaction from its message and compute budget
+//! instructions, before it is executed.
+
+use {
+    crate::{
+        block_cost_limits::{
+            builtin_instruction_cost, ED25519_VERIFY_COST, INSTRUCTION_DATA_BYTES_COST,
+            SECP256K1_VERIFY_COST, SIGNATURE_COST, WRITE_LOCK_UNITS,
+        },
+        transaction_cost::TransactionCost,
+    },
+    solana_compute_budget::compute_budget_processor::{
+        process_compute_budget_instructions, DEFAULT_INSTRUCTION_COMPUTE_UNIT_LIMIT,
+        MAX_COMPUTE_UNIT_LIMIT,
+    },
+    solana_sdk::{
+        borsh1::try_from_slice_unchecked,
+        compute_budget::{self, ComputeBudgetInstruction},
+        ed25519_program,
+        feature_set::{self, FeatureSet},
+        fee::{FeeBudgetLimits, FeeStructure},
+        instruction::CompiledInstruction,
+        message::SanitizedMessage,
+        program_utils::limited_deserialize,
+        pubkey::Pubkey,
+        secp256k1_program,
+        system_instruction::{
+            SystemInstruction, MAX_PERMITTED_ACCOUNTS_DATA_ALLOCATIONS_PER_TRANSACTION,
+        },
+        system_program,
+        transaction::SanitizedTransaction,
+    },
+};
+
+pub struct CostModel;
+
+impl CostModel {
+    /// Estimate the cost of packing a transaction into a block.
+    pub fn calculate_cost(
+        transaction: &SanitizedTransaction,
+        feature_set: &FeatureSet,
+    ) -> TransactionCost {
+        let message = transaction.message();
+        let writable_accounts = Self::get_writable_accounts(message);
+        let (programs_execution_cost, loaded_accounts_data_size_cost, data_bytes_cost) =
+            Self::get_transaction_cost(message, feature_set);
+
+        TransactionCost {
+            signature_cost: Self::get_signature_cost(message),
+            write_lock_cost: WRITE_LOCK_UNITS.saturating_mul(writable_accounts.len() as u64),
+            data_bytes_cost,
+            programs_execution_cost,
+            loaded_accounts_data_size_cost,
+            account_data_size: Self::calculate_allocated_accounts_data_size(message),
+            writable_accounts,
+            is_simple_vote: transaction.is_simple_vote_transaction(),
+        }
+    }
+
+    fn get_signature_cost(message: &SanitizedMessage) -> u64 {
+        let mut signature_cost =
+            u64::from(message.header().num_required_signatures).saturating_mul(SIGNATURE_COST);
+        for (program_id, instruction) in message.program_instructions_iter() {
+            let verify_cost = if secp256k1_program::check_id(program_id) {
+                SECP256K1_VERIFY_COST
+            } else if ed25519_program::check_id(program_id) {
+                ED25519_VERIFY_COST
+            } else {
+                continue;
+            };
+            let num_verifies = instruction.data.first().copied().unwrap_or_default();
+            signature_cost =
+                signature_cost.saturating_add(u64::from(num_verifies).saturating_mul(verify_cost));
+        }
+        signature_cost
+    }
+
+    fn get_writable_accounts(message: &SanitizedMessage) -> Vec<Pubkey> {
+        message
+            .account_keys()
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| message.is_writable(*index))
+            .map(|(_, key)| *key)
+            .collect()
+    }
+
+    /// Returns the programs execution cost, the loaded accounts data size cost
+    /// and the data bytes cost of a message.
+    fn get_transaction_cost(
+        message: &SanitizedMessage,
+        feature_set: &FeatureSet,
+    ) -> (u64, u64, u64) {
+        let mut programs_execution_cost = 0u64;
+        let mut loaded_accounts_data_size_cost = 0u64;
+        let mut data_bytes_len_total = 0u64;
+        let mut compute_unit_limit_is_set = false;
+        let mut has_user_space_instructions = false;
+
+        for (program_id, instruction) in message.program_instructions_iter() {
+            let instruction_execution_cost =
+                builtin_instruction_cost(program_id).unwrap_or_else(|| {
+                    has_user_space_instructions = true;
+                    u64::from(DEFAULT_INSTRUCTION_COMPUTE_UNIT_LIMIT)
+                });
+            programs_execution_cost = programs_execution_cost
+                .saturating_add(instruction_execution_cost)
+                .min(u64::from(MAX_COMPUTE_UNIT_LIMIT));
+            data_bytes_len_total =
+                data_bytes_len_total.saturating_add(instruction.data.len() as u64);
+
+            if compute_budget::check_id(program_id) {
+                if let Ok(ComputeBudgetInstruction::SetComputeUnitLimit(_)) =
+                    try_from_slice_unchecked(&instruction.data)
+                {
+                    compute_unit_limit_is_set = true;
+                }
+            }
+        }
+
+        // A transaction with invalid compute budget instructions fails
+        // without executing any instruction.
+        match process_compute_budget_instructions(message.program_instructions_iter()) {
+            Ok(compute_budget_limits) => {
+                // Builtins do not consume the requested compute units, so the
+                // requested limit only replaces the estimate when the
+                // transaction invokes a user space program.
+                if has_user_space_instructions && compute_unit_limit_is_set {
+                    programs_execution_cost = u64::from(compute_budget_limits.compute_unit_limit);
+                }
+
+                if feature_set.is_active(
+                    &feature_set::include_loaded_accounts_data_size_in_fee_calculation::id(),
+                ) {
+                    let fee_budget_limits = FeeBudgetLimits::from(compute_budget_limits);
+                    loaded_accounts_data_size_cost = FeeStructure::calculate_memory_usage_cost(
+                        fee_budget_limits.loaded_accounts_data_size_limit,
+                        fee_budget_limits.heap_cost,
+                    );
+                }
+            }
+            Err(_) => {
+                programs_execution_cost = 0;
+            }
+        }
+
+        (
+            programs_execution_cost,
+            loaded_accounts_data_size_cost,
+            data_bytes_len_total / INSTRUCTION_DATA_BYTES_COST,
+        )
+    }
+
+    /// The number of account data bytes allocated by a message's system
+    /// program instructions.
+    ///
+    /// Returns zero if any system program instruction is invalid, since the
+    /// transaction fails without allocating anything.
+    fn calculate_allocated_accounts_data_size(message: &SanitizedMessage) -> u64 {
+        let mut allocated_accounts_data_size = 0u64;
+        for (program_id, instruction) in message.program_instructions_iter() {
+            let Some(space) =
+                Self::calculate_account_data_size_on_instruction(program_id, instruction)
+            else {
+                return 0;
+            };
+            allocated_accounts_data_size = allocated_accounts_data_size.saturating_add(space);
+        }
+
+        allocated_accounts_data_size
+            .min(MAX_PERMITTED_ACCOUNTS_DATA_ALLOCATIONS_PER_TRANSACTION as u64)
+    }
+
+    /// The number of account data bytes allocated by an instruction, or
+    /// `None` if it is an invalid system program instruction.
+    fn calculate_account_data_size_on_instruction(
+        program_id: &Pubkey,
+        instruction: &CompiledInstruction,
+    ) -> Option<u64> {
+        if !system_program::check_id(program_id) {
+            return Some(0);
+        }
+
+        let space = match limited_deserialize(&instruction.data).ok()? {
+            SystemInstruction::CreateAccount { space, .. }
+            | SystemInstruction::CreateAccountWithSeed { space, .. }
+            | SystemInstruction::Allocate { space }
+            | SystemInstruction::AllocateWithSeed { space, .. } => space,
+            _ => 0,
+        };
+        Some(space)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        solana_sdk::{
+            instruction::Instruction, message::Message, system_instruction,
+            transaction::Transaction,
+        },
+    };
+
+    fn calculate_cost(instructions: &[Instruction], feature_set: &FeatureSet) -> TransactionCost {
+        let payer = Pubkey::new_unique();
+        let message = Message::new(instructions, Some(&payer));
+        let transaction =
+            SanitizedTransaction::from_transaction_for_tests(Transaction::new_unsigned(message));
+        CostModel::calculate_cost(&transaction, feature_set)
+    }
+
+    fn transfer() -> Instruction {
+        system_instruction::transfer(&Pubkey::new_unique(), &Pubkey::new_unique(), 1)
+    }
+
+    fn user_space(data: &[u8]) -> Instruction {
+        Instruction::new_with_bytes(Pubkey::new_unique(), data, vec![])
+    }
+
+    #[test]
+    fn test_signature_cost() {
+        let feature_set = FeatureSet::default();
+        // The payer and the transfer's sender sign.
+        let cost = calculate_cost(&[transfer()], &feature_set);
+        assert_eq!(cost.signature_cost, 2 * SIGNATURE_COST);
+
+        // Precompile instructions start with their number of signatures.
+        let cost = calculate_cost(
+            &[
+                Instruction::new_with_bytes(secp256k1_program::id(), &[2], vec![]),
+                Instruction::new_with_bytes(ed25519_program::id(), &[3], vec![]),
+                Instruction::new_with_bytes(ed25519_program::id(), &[], vec![]),
+            ],
+            &feature_set,
+        );
+        assert_eq!(
+            cost.signature_cost,
+            SIGNATURE_COST + 2 * SECP256K1_VERIFY_COST + 3 * ED25519_VERIFY_COST
+        );
+        assert_eq!(cost.programs_execution_cost, 0);
+    }
+
+    #[test]
+    fn test_write_lock_cost() {
+        let payer = Pubkey::new_unique();
+        let to = Pubkey::new_unique();
+        let message = Message::new(
+            &[system_instruction::transfer(&payer, &to, 1)],
+            Some(&payer),
+        );
+        let transaction =
+            SanitizedTransaction::from_transaction_for_tests(Transaction::new_unsigned(message));
+        let cost = CostModel::calculate_cost(&transaction, &FeatureSet::default());
+        assert_eq!(cost.writable_accounts, vec![payer, to]);
+        assert_eq!(cost.write_lock_cost, 2 * WRITE_LOCK_UNITS);
+    }
+
+    #[test]
+    fn test_data_bytes_cost() {
+        let cost = calculate_cost(
+            &[user_space(&[0; 100]), user_space(&[0; 3])],
+            &FeatureSet::default(),
+        );
+        assert_eq!(cost.data_bytes_cost, 103 / INSTRUCTION_DATA_BYTES_COST);
+    }
+
+    #[test]
+    fn test_programs_execution_cost() {
+        let feature_set = FeatureSet::default();
+        let system_cost = builtin_instruction_cost(&system_program::id()).unwrap();
+        let cost = calculate_cost(&[transfer(), transfer()], &feature_set);
+        assert_eq!(cost.programs_execution_cost, 2 * system_cost);
+
+        let cost = calculate_cost(&[transfer(), user_space(&[])], &feature_set);
+        assert_eq!(
+            cost.programs_execution_cost,
+            system_cost + u64::from(DEFAULT_INSTRUCTION_COMPUTE_UNIT_LIMIT)
+        );
+
+        let cost = calculate_cost(&vec![user_space(&[]); 10], &feature_set);
+        assert_eq!(
+            cost.programs_execution_cost,
+            u64::from(MAX_COMPUTE_UNIT_LIMIT)
+        );
+    }
+
+    #[test]
+    fn test_programs_execution_cost_compute_unit_limit() {
+        let feature_set = FeatureSet::default();
+        let set_limit = ComputeBudgetInstruction::set_compute_unit_limit(12_345);
+
+        let cost = calculate_cost(&[set_limit.clone(), user_space(&[])], &feature_set);
+        assert_eq!(cost.programs_execution_cost, 12_345);
+
+        // Builtins do not consume the requested units.
+        let cost = calculate_cost(&[set_limit.clone(), transfer()], &feature_set);
+        assert_eq!(
+            cost.programs_execution_cost,
+            builtin_instruction_cost(&compute_budget::id()).unwrap()
+                + builtin_instruction_cost(&system_program::id()).unwrap()
+        );
+
+        // Other compute budget instructions keep the default estimate.
+        let cost = calculate_cost(
+            &[
+                ComputeBudgetInstruction::set_compute_unit_price(1),
+                user_space(&[]),
+            ],
+            &feature_set,
+        );
+        assert_eq!(
+            cost.programs_execution_cost,
+            builtin_instruction_cost(&compute_budget::id()).unwrap()
+                + u64::from(DEFAULT_INSTRUCTION_COMPUTE_UNIT_LIMIT)
+        );
+    }
+
+    #[test]
+    fn test_programs_execution_cost_invalid_compute_budget() {
+        let feature_set = FeatureSet::all_enabled();
+        let duplicate_limits = calculate_cost(
+            &[
+                ComputeBudgetInstruction::set_compute_unit_limit(1),
+                ComputeBudgetInstruction::set_compute_unit_limit(2),
+                user_space(&[]),
+            ],
+            &feature_set,
+        );
+        assert_eq!(duplicate_limits.programs_execution_cost, 0);
+        assert_eq!(duplicate_limits.loaded_accounts_data_size_cost, 0);
+
+        let invalid_data = calculate_cost(
+            &[
+                Instruction::new_with_bytes(compute_budget::id(), &[255], vec![]),
+                transfer(),
+            ],
+            &feature_set,
+        );
+        assert_eq!(invalid_data.programs_execution_cost, 0);
+    }
+
+    #[test]
+    fn test_loaded_accounts_data_size_cost() {
+        let instructions = [
+            ComputeBudgetInstruction::set_loaded_accounts_data_size_limit(64 * 1024),
+            transfer(),
+        ];
+        let cost = calculate_cost(&instructions, &FeatureSet::default());
+        assert_eq!(cost.loaded_accounts_data_size_cost, 0);
+
+        let mut feature_set = FeatureSet::default();
+        feature_set.activate(
+            &feature_set::include_loaded_accounts_data_size_in_fee_calculation::id(),
+            0,
+        );
+        // Each started 32 KiB page costs the default heap cost of 8 units.
+        let cost = calculate_cost(&instructions, &feature_set);
+        assert_eq!(cost.loaded_accounts_data_size_cost, 16);
+    }
+
+    #[test]
+    fn test_account_data_size() {
+        let feature_set = FeatureSet::default();
+        let owner = Pubkey::new_unique();
+        let payer = Pubkey::new_unique();
+        let cost = calculate_cost(
+            &[
+                system_instruction::create_account(&payer, &Pubkey::new_unique(), 1, 100, &owner),
+                system_instruction::allocate(&Pubkey::new_unique(), 50),
+                transfer(),
+                user_space(&[]),
+            ],
+            &feature_set,
+        );
+        assert_eq!(cost.account_data_size, 150);
+
+        let cost = calculate_cost(
+            &vec![
+                system_instruction::allocate(
+                    &Pubkey::new_unique(),
+                    MAX_PERMITTED_ACCOUNTS_DATA_ALLOCATIONS_PER_TRANSACTION as u64,
+                );
+                2
+            ],
+            &feature_set,
+        );
+        assert_eq!(
+            cost.account_data_size,
+            MAX_PERMITTED_ACCOUNTS_DATA_ALLOCATIONS_PER_TRANSACTION as u64
+        );
+
+        // An invalid system instruction allocates nothing.
+        let cost = calculate_cost(
+            &[
+                system_instruction::allocate(&Pubkey::new_unique(), 50),
+                Instruction::new_with_bytes(system_program::id(), &[255], vec![]),
+            ],
+            &feature_set,
+        );
+        assert_eq!(cost.account_data_size, 0);
+    }
+}
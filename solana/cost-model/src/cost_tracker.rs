@@ -0,0 +1,374 @@
+//! Tracks the cost of the transactions packed into a block, rejecting any
+//! transaction that would take the block over its cost limits.
+
+use {
+    crate::{
+        block_cost_limits::{
+            MAX_BLOCK_ACCOUNTS_DATA_SIZE_DELTA, MAX_BLOCK_UNITS, MAX_VOTE_UNITS,
+            MAX_WRITABLE_ACCOUNT_UNITS,
+        },
+        transaction_cost::TransactionCost,
+    },
+    solana_sdk::pubkey::Pubkey,
+    std::collections::HashMap,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CostTrackerError {
+    /// Adding the transaction would exceed the block cost limit.
+    WouldExceedBlockMaxLimit,
+    /// Adding the simple vote transaction would exceed the block's vote cost
+    /// limit.
+    WouldExceedVoteMaxLimit,
+    /// Adding the transaction would exceed the cost limit of one of its
+    /// writable accounts.
+    WouldExceedAccountMaxLimit,
+    /// Adding the transaction would exceed the block's limit on allocated
+    /// account data.
+    WouldExceedAccountDataBlockLimit,
+}
+
+#[derive(Debug)]
+pub struct CostTracker {
+    account_cost_limit: u64,
+    block_cost_limit: u64,
+    vote_cost_limit: u64,
+    account_data_size_limit: u64,
+    cost_by_writable_accounts: HashMap<Pubkey, u64>,
+    block_cost: u64,
+    vote_cost: u64,
+    transaction_count: u64,
+    account_data_size: u64,
+}
+
+impl Default for CostTracker {
+    fn default() -> Self {
+        Self::new(MAX_WRITABLE_ACCOUNT_UNITS, MAX_BLOCK_UNITS, MAX_VOTE_UNITS)
+    }
+}
+
+impl CostTracker {
+    pub fn new(account_cost_limit: u64, block_cost_limit: u64, vote_cost_limit: u64) -> Self {
+        Self {
+            account_cost_limit,
+            block_cost_limit,
+            vote_cost_limit,
+            account_data_size_limit: MAX_BLOCK_ACCOUNTS_DATA_SIZE_DELTA,
+            cost_by_writable_accounts: HashMap::new(),
+            block_cost: 0,
+            vote_cost: 0,
+            transaction_count: 0,
+            account_data_size: 0,
+        }
+    }
+
+    pub fn set_limits(
+        &mut self,
+        account_cost_limit: u64,
+        block_cost_limit: u64,
+        vote_cost_limit: u64,
+        account_data_size_limit: u64,
+    ) {
+        self.account_cost_limit = account_cost_limit;
+        self.block_cost_limit = block_cost_limit;
+        self.vote_cost_limit = vote_cost_limit;
+        self.account_data_size_limit = account_data_size_limit;
+    }
+
+    /// Add a transaction to the block if it fits within the block's limits,
+    /// returning the cost of the costliest of its writable accounts.
+    pub fn try_add(&mut self, tx_cost: &TransactionCost) -> Result<u64, CostTrackerError> {
+        self.would_fit(tx_cost)?;
+        Ok(self.add_transaction_cost(tx_cost))
+    }
+
+    /// Replace the estimated execution cost of a previously added
+    /// transaction with the compute units it actually consumed.
+    pub fn update_execution_cost(
+        &mut self,
+        estimated_tx_cost: &TransactionCost,
+        actual_execution_units: u64,
+    ) {
+        let estimated_execution_units = estimated_tx_cost.programs_execution_cost;
+        if actual_execution_units > estimated_execution_units {
+            self.add_transaction_execution_cost(
+                estimated_tx_cost,
+                actual_execution_units - estimated_execution_units,
+            );
+        } else {
+            self.sub_transaction_execution_cost(
+                estimated_tx_cost,
+                estimated_execution_units - actual_execution_units,
+            );
+        }
+    }
+
+    /// Remove a previously added transaction from the block.
+    pub fn remove(&mut self, tx_cost: &TransactionCost) {
+        self.sub_transaction_execution_cost(tx_cost, tx_cost.sum());
+        self.account_data_size = self
+            .account_data_size
+            .saturating_sub(tx_cost.account_data_size);
+        self.transaction_count = self.transaction_count.saturating_sub(1);
+    }
+
+    pub fn block_cost(&self) -> u64 {
+        self.block_cost
+    }
+
+    pub fn vote_cost(&self) -> u64 {
+        self.vote_cost
+    }
+
+    pub fn transaction_count(&self) -> u64 {
+        self.transaction_count
+    }
+
+    pub fn account_data_size(&self) -> u64 {
+        self.account_data_size
+    }
+
+    /// The cost of the transactions added to the block that write-lock
+    /// `account`.
+    pub fn account_cost(&self, account: &Pubkey) -> u64 {
+        self.cost_by_writable_accounts
+            .get(account)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    fn would_fit(&self, tx_cost: &TransactionCost) -> Result<(), CostTrackerError> {
+        let cost = tx_cost.sum();
+
+        if self.block_cost.saturating_add(cost) > self.block_cost_limit {
+            return Err(CostTrackerError::WouldExceedBlockMaxLimit);
+        }
+
+        if tx_cost.is_simple_vote && self.vote_cost.saturating_add(cost) > self.vote_cost_limit {
+            return Err(CostTrackerError::WouldExceedVoteMaxLimit);
+        }
+
+        // A single transaction may not exceed the account limit on its own.
+        if cost > self.account_cost_limit {
+            return Err(CostTrackerError::WouldExceedAccountMaxLimit);
+        }
+
+        for account in &tx_cost.writable_accounts {
+            if self.account_cost(account).saturating_add(cost) > self.account_cost_limit {
+                return Err(CostTrackerError::WouldExceedAccountMaxLimit);
+            }
+        }
+
+        if self
+            .account_data_size
+            .saturating_add(tx_cost.account_data_size)
+            > self.account_data_size_limit
+        {
+            return Err(CostTrackerError::WouldExceedAccountDataBlockLimit);
+        }
+
+        Ok(())
+    }
+
+    /// Returns the cost of the costliest of the transaction's writable
+    /// accounts.
+    fn add_transaction_cost(&mut self, tx_cost: &TransactionCost) -> u64 {
+        let costliest_account_cost = self.add_transaction_execution_cost(tx_cost, tx_cost.sum());
+        self.account_data_size = self
+            .account_data_size
+            .saturating_add(tx_cost.account_data_size);
+        self.transaction_count = self.transaction_count.saturating_add(1);
+        costliest_account_cost
+    }
+
+    /// Returns the cost of the costliest of the transaction's writable
+    /// accounts.
+    fn add_transaction_execution_cost(
+        &mut self,
+        tx_cost: &TransactionCost,
+        adjustment: u64,
+    ) -> u64 {
+        let mut costliest_account_cost = 0;
+        for account in &tx_cost.writable_accounts {
+            let account_cost = self.cost_by_writable_accounts.entry(*account).or_default();
+            *account_cost = account_cost.saturating_add(adjustment);
+            costliest_account_cost = costliest_account_cost.max(*account_cost);
+        }
+        self.block_cost = self.block_cost.saturating_add(adjustment);
+        if tx_cost.is_simple_vote {
+            self.vote_cost = self.vote_cost.saturating_add(adjustment);
+        }
+        costliest_account_cost
+    }
+
+    fn sub_transaction_execution_cost(&mut self, tx_cost: &TransactionCost, adjustment: u64) {
+        for account in &tx_cost.writable_accounts {
+            if let Some(account_cost) = self.cost_by_writable_accounts.get_mut(account) {
+                *account_cost = account_cost.saturating_sub(adjustment);
+            }
+        }
+        self.block_cost = self.block_cost.saturating_sub(adjustment);
+        if tx_cost.is_simple_vote {
+            self.vote_cost = self.vote_cost.saturating_sub(adjustment);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tx_cost(writable_accounts: &[Pubkey], cost: u64) -> TransactionCost {
+        TransactionCost {
+            writable_accounts: writable_accounts.to_vec(),
+            programs_execution_cost: cost,
+            ..TransactionCost::default()
+        }
+    }
+
+    #[test]
+    fn test_try_add() {
+        let (a, b) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut tracker = CostTracker::new(100, 1000, 1000);
+
+        assert_eq!(tracker.try_add(&tx_cost(&[a], 10)), Ok(10));
+        assert_eq!(tracker.try_add(&tx_cost(&[b], 5)), Ok(5));
+        // Only the transaction's own writable accounts are considered.
+        assert_eq!(tracker.try_add(&tx_cost(&[a, b], 20)), Ok(30));
+        assert_eq!(tracker.account_cost(&a), 30);
+        assert_eq!(tracker.account_cost(&b), 25);
+        assert_eq!(tracker.block_cost(), 35);
+        assert_eq!(tracker.vote_cost(), 0);
+        assert_eq!(tracker.transaction_count(), 3);
+    }
+
+    #[test]
+    fn test_account_limit() {
+        let (a, b) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut tracker = CostTracker::new(100, 1000, 1000);
+
+        // A single transaction may not exceed the limit on its own.
+        assert_eq!(
+            tracker.try_add(&tx_cost(&[], 101)),
+            Err(CostTrackerError::WouldExceedAccountMaxLimit)
+        );
+
+        assert_eq!(tracker.try_add(&tx_cost(&[a], 60)), Ok(60));
+        assert_eq!(
+            tracker.try_add(&tx_cost(&[b, a], 41)),
+            Err(CostTrackerError::WouldExceedAccountMaxLimit)
+        );
+        assert_eq!(tracker.try_add(&tx_cost(&[b, a], 40)), Ok(100));
+        assert_eq!(tracker.try_add(&tx_cost(&[b], 60)), Ok(100));
+        assert_eq!(tracker.block_cost(), 160);
+        assert_eq!(tracker.transaction_count(), 3);
+    }
+
+    #[test]
+    fn test_block_limit() {
+        let mut tracker = CostTracker::new(100, 150, 1000);
+
+        assert_eq!(
+            tracker.try_add(&tx_cost(&[Pubkey::new_unique()], 100)),
+            Ok(100)
+        );
+        assert_eq!(
+            tracker.try_add(&tx_cost(&[Pubkey::new_unique()], 51)),
+            Err(CostTrackerError::WouldExceedBlockMaxLimit)
+        );
+        assert_eq!(
+            tracker.try_add(&tx_cost(&[Pubkey::new_unique()], 50)),
+            Ok(50)
+        );
+        assert_eq!(tracker.block_cost(), 150);
+        assert_eq!(tracker.transaction_count(), 2);
+    }
+
+    #[test]
+    fn test_vote_limit() {
+        let mut tracker = CostTracker::new(100, 1000, 150);
+        let vote_cost = |cost| TransactionCost {
+            is_simple_vote: true,
+            ..tx_cost(&[Pubkey::new_unique()], cost)
+        };
+
+        assert_eq!(tracker.try_add(&vote_cost(100)), Ok(100));
+        assert_eq!(
+            tracker.try_add(&vote_cost(51)),
+            Err(CostTrackerError::WouldExceedVoteMaxLimit)
+        );
+        // Other transactions are not subject to the vote limit.
+        assert_eq!(
+            tracker.try_add(&tx_cost(&[Pubkey::new_unique()], 100)),
+            Ok(100)
+        );
+        assert_eq!(tracker.try_add(&vote_cost(50)), Ok(50));
+        assert_eq!(tracker.vote_cost(), 150);
+        assert_eq!(tracker.block_cost(), 250);
+
+        // Votes still count against the block limit.
+        tracker.set_limits(100, 250, 1000, MAX_BLOCK_ACCOUNTS_DATA_SIZE_DELTA);
+        assert_eq!(
+            tracker.try_add(&vote_cost(1)),
+            Err(CostTrackerError::WouldExceedBlockMaxLimit)
+        );
+    }
+
+    #[test]
+    fn test_account_data_limit() {
+        let mut tracker = CostTracker::default();
+        tracker.set_limits(100, 1000, 1000, 1024);
+        let allocation = |account_data_size| TransactionCost {
+            account_data_size,
+            ..tx_cost(&[Pubkey::new_unique()], 10)
+        };
+
+        assert_eq!(tracker.try_add(&allocation(1000)), Ok(10));
+        assert_eq!(
+            tracker.try_add(&allocation(25)),
+            Err(CostTrackerError::WouldExceedAccountDataBlockLimit)
+        );
+        assert_eq!(tracker.try_add(&allocation(24)), Ok(10));
+        assert_eq!(tracker.account_data_size(), 1024);
+    }
+
+    #[test]
+    fn test_update_execution_cost() {
+        let a = Pubkey::new_unique();
+        let estimated_tx_cost = TransactionCost {
+            is_simple_vote: true,
+            ..tx_cost(&[a], 50)
+        };
+        for actual_execution_units in [80, 20] {
+            let mut tracker = CostTracker::new(100, 1000, 1000);
+            tracker.try_add(&estimated_tx_cost).unwrap();
+
+            tracker.update_execution_cost(&estimated_tx_cost, actual_execution_units);
+            assert_eq!(tracker.account_cost(&a), actual_execution_units);
+            assert_eq!(tracker.block_cost(), actual_execution_units);
+            assert_eq!(tracker.vote_cost(), actual_execution_units);
+        }
+    }
+
+    #[test]
+    fn test_remove() {
+        let (a, b) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut tracker = CostTracker::new(100, 1000, 1000);
+        let added_tx_cost = TransactionCost {
+            account_data_size: 10,
+            ..tx_cost(&[a], 30)
+        };
+        tracker.try_add(&added_tx_cost).unwrap();
+        tracker.try_add(&tx_cost(&[a], 20)).unwrap();
+
+        tracker.remove(&added_tx_cost);
+        assert_eq!(tracker.account_cost(&a), 20);
+        assert_eq!(tracker.block_cost(), 20);
+        assert_eq!(tracker.account_data_size(), 0);
+        assert_eq!(tracker.transaction_count(), 1);
+
+        // Removing a transaction never tracks its accounts.
+        tracker.remove(&tx_cost(&[b], 5));
+        assert!(!tracker.cost_by_writable_accounts.contains_key(&b));
+    }
+}
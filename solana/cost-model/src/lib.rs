@@ -0,0 +1,9 @@
+//! Solana Cost Model.
+//!
+//! Estimates the cost of transactions in compute units, and tracks the cost
+//! of a block as transactions are packed into it.
+
+pub mod block_cost_limits;
+pub mod cost_model;
+pub mod cost_tracker;
+pub mod transaction_cost;
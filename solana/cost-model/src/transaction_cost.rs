@@ -0,0 +1,35 @@
+use solana_sdk::pubkey::Pubkey;
+
+/// The estimated cost of a transaction, broken down by component.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TransactionCost {
+    /// The accounts write-locked by the transaction.
+    pub writable_accounts: Vec<Pubkey>,
+    /// The cost of verifying the transaction's signatures, including the
+    /// signatures verified by the secp256k1 and ed25519 precompiles.
+    pub signature_cost: u64,
+    /// The cost of write-locking the transaction's writable accounts.
+    pub write_lock_cost: u64,
+    /// The cost of the transaction's instruction data.
+    pub data_bytes_cost: u64,
+    /// The cost of executing the transaction's instructions.
+    pub programs_execution_cost: u64,
+    /// The cost of the accounts data the transaction may load.
+    pub loaded_accounts_data_size_cost: u64,
+    /// The number of account data bytes the transaction allocates.
+    pub account_data_size: u64,
+    /// Whether the transaction is a simple vote, whose cost also counts
+    /// against the block's vote limit.
+    pub is_simple_vote: bool,
+}
+
+impl TransactionCost {
+    /// The total cost of the transaction.
+    pub fn sum(&self) -> u64 {
+        self.signature_cost
+            .saturating_add(self.write_lock_cost)
+            .saturating_add(self.data_bytes_cost)
+            .saturating_add(self.programs_execution_cost)
+            .saturating_add(self.loaded_accounts_data_size_cost)
+    }
+}
@@ -11,8 +11,13 @@ use {
 pub trait ValidatorRuntime<TB: TransactionBatch, TP: TransactionBatchProcessor> {
     /// Get the batch processor.
     fn batch_processor(&self) -> &TP;
-    /// Load and execute a batch of transactions.
-    fn load_and_execute_transactions(&self, batch: &TB) -> LoadAndExecuteTransactionsOutput;
+    /// Load and execute a batch of transactions, rejecting those whose recent
+    /// blockhash is older than `max_age` blockhashes.
+    fn load_and_execute_transactions(
+        &self,
+        batch: &TB,
+        max_age: usize,
+    ) -> LoadAndExecuteTransactionsOutput;
 }
 
 /// A batch of Solana transactions.
@@ -2,7 +2,7 @@
 
 use solana_sdk::{
     inner_instruction::InnerInstructionsList,
-    nonce_info::{NonceFull, NonceInfo},
+    nonce_info::{NonceFull, NonceInfo, NoncePartial},
     rent_debits::RentDebits,
     transaction::{self, SanitizedTransaction, TransactionError},
     transaction_context::{IndexOfAccount, TransactionAccount, TransactionReturnData},
@@ -11,11 +11,26 @@ use solana_sdk::{
 /// The Solana SVM Transaction Batch Processor.
 /// Primary component of the Solana SVM.
 pub trait TransactionBatchProcessor {
+    /// Check the age and processing status of a batch of sanitized
+    /// transactions, before any of their accounts are loaded.
+    ///
+    /// Transactions whose recent blockhash is older than `max_age` blockhashes
+    /// and which are not valid durable nonce transactions fail with
+    /// `BlockhashNotFound`. Transactions which were already processed fail
+    /// with `AlreadyProcessed`.
+    fn check_transactions(
+        &self,
+        sanitized_txs: &[SanitizedTransaction],
+        max_age: usize,
+    ) -> Vec<TransactionCheckResult>;
+
     /// The entrypoint to the SVM.
-    /// Load and execute a batch of sanitized transactions.
+    /// Load and execute a batch of sanitized transactions, checking them
+    /// against `max_age` first.
     fn load_and_execute_sanitized_transactions(
         &self,
         sanitized_txs: &[SanitizedTransaction],
+        max_age: usize,
     ) -> LoadAndExecuteSanitizedTransactionsOutput;
 }
 
@@ -25,8 +40,14 @@ pub struct LoadAndExecuteSanitizedTransactionsOutput {
     pub execution_results: Vec<TransactionExecutionResult>,
 }
 
+/// A transaction check result, containing the nonce of a durable nonce
+/// transaction and the lamports per signature to charge if the transaction
+/// may be loaded, or the error if not.
+pub type TransactionCheckResult = (transaction::Result<()>, Option<NoncePartial>, Option<u64>);
+
 /// A transaction load result, containing the loaded transaction and the nonce.
 pub type TransactionLoadResult = (transaction::Result<LoadedTransaction>, Option<NonceFull>);
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct LoadedTransaction {
     pub accounts: Vec<TransactionAccount>,
     pub program_indices: Vec<Vec<IndexOfAccount>>,
@@ -36,12 +57,16 @@ pub struct LoadedTransaction {
 
 /// A transaction execution result, containing the execution details if
 /// successful, or the error if unsuccesful.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "test-harness", derive(serde::Deserialize, serde::Serialize))]
 pub enum TransactionExecutionResult {
     Executed {
         details: TransactionExecutionDetails,
     },
     NotExecuted(TransactionError),
 }
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "test-harness", derive(serde::Deserialize, serde::Serialize))]
 pub struct TransactionExecutionDetails {
     pub status: transaction::Result<()>,
     pub log_messages: Option<Vec<String>>,
@@ -51,6 +76,8 @@ pub struct TransactionExecutionDetails {
     pub executed_units: u64,
     pub accounts_data_len_delta: i64,
 }
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "test-harness", derive(serde::Deserialize, serde::Serialize))]
 pub enum DurableNonceFee {
     Valid(u64),
     Invalid,
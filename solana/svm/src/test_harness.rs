@@ -1,8 +1,136 @@
 //! Solana SVM Specification Test Harness
 
-use crate::specification::TransactionBatchProcessor;
+pub mod fixture;
+
+use {
+    crate::{
+        specification::{
+            DurableNonceFee, LoadedTransaction, TransactionBatchProcessor,
+            TransactionExecutionDetails, TransactionExecutionResult, TransactionLoadResult,
+        },
+        test_harness::fixture::{FixtureReport, SvmFixture},
+    },
+    solana_compute_budget::compute_budget::ComputeBudget,
+    solana_sdk::{
+        account::{AccountSharedData, WritableAccount},
+        clock::MAX_PROCESSING_AGE,
+        compute_budget::{self, ComputeBudgetInstruction},
+        feature_set::FeatureSet,
+        hash::Hash,
+        instruction::{Instruction, InstructionError},
+        native_loader,
+        native_token::LAMPORTS_PER_SOL,
+        nonce::{
+            state::{Data, DurableNonce, Versions},
+            State as NonceState,
+        },
+        nonce_info::NonceFull,
+        pubkey::Pubkey,
+        rent_collector::RENT_EXEMPT_RENT_EPOCH,
+        rent_debits::RentDebits,
+        signature::{Keypair, Signer},
+        system_instruction, system_program, sysvar,
+        transaction::{self, SanitizedTransaction, Transaction, TransactionError},
+        transaction_context::IndexOfAccount,
+    },
+    std::{collections::HashMap, fs, io, path::Path},
+};
+
+/// The lamports per signature used by every test environment.
+pub const LAMPORTS_PER_SIGNATURE: u64 = 5_000;
+
+/// The environment a conformance case is run against.
+pub struct SvmTestEnvironment {
+    /// The accounts available to the processor. Any account not listed here
+    /// does not exist.
+    pub accounts: Vec<(Pubkey, AccountSharedData)>,
+    /// The most recent blockhash.
+    pub blockhash: Hash,
+    /// The lamports per signature associated with the most recent blockhash.
+    pub lamports_per_signature: u64,
+    /// The active feature set.
+    pub feature_set: FeatureSet,
+    /// The compute budget to use for every transaction, overriding any
+    /// compute budget instructions.
+    pub compute_budget: Option<ComputeBudget>,
+}
+
+impl SvmTestEnvironment {
+    /// Create a new test environment containing the provided accounts, as
+    /// well as the system program, the compute budget program and the recent
+    /// blockhashes sysvar.
+    pub fn new(accounts: Vec<(Pubkey, AccountSharedData)>) -> Self {
+        let blockhash = Hash::new_unique();
+        #[allow(deprecated)]
+        let recent_blockhashes =
+            solana_sdk::recent_blockhashes_account::create_account_with_data_and_fields(
+                vec![sysvar::recent_blockhashes::IterItem(
+                    0,
+                    &blockhash,
+                    LAMPORTS_PER_SIGNATURE,
+                )],
+                (1, RENT_EXEMPT_RENT_EPOCH),
+            );
+        let mut environment = Self {
+            accounts: vec![
+                (system_program::id(), builtin_account("system_program")),
+                (
+                    compute_budget::id(),
+                    builtin_account("compute_budget_program"),
+                ),
+                #[allow(deprecated)]
+                (sysvar::recent_blockhashes::id(), recent_blockhashes),
+            ],
+            blockhash,
+            lamports_per_signature: LAMPORTS_PER_SIGNATURE,
+            feature_set: FeatureSet::all_enabled(),
+            compute_budget: None,
+        };
+        environment.accounts.extend(accounts);
+        environment
+    }
+
+    fn accounts_map(&self) -> HashMap<Pubkey, AccountSharedData> {
+        self.accounts.iter().cloned().collect()
+    }
+
+    fn transaction(&self, instructions: &[Instruction], payer: &Keypair) -> SanitizedTransaction {
+        self.transaction_with_blockhash(instructions, payer, self.blockhash)
+    }
+
+    fn transaction_with_blockhash(
+        &self,
+        instructions: &[Instruction],
+        payer: &Keypair,
+        blockhash: Hash,
+    ) -> SanitizedTransaction {
+        SanitizedTransaction::from_transaction_for_tests(Transaction::new_signed_with_payer(
+            instructions,
+            Some(&payer.pubkey()),
+            &[payer],
+            blockhash,
+        ))
+    }
+}
+
+/// A transaction batch processor which can be instantiated by the test
+/// harness.
+///
+/// Processors created for the harness are expected to:
+/// * Provide the system program and the compute budget program as builtins.
+/// * Use the default fee structure and rent.
+/// * Disable log, CPI and return data recording.
+pub trait TestTransactionBatchProcessor: TransactionBatchProcessor {
+    /// The compute units consumed by a single system or compute budget
+    /// program instruction.
+    const BUILTIN_COMPUTE_UNITS: u64;
+
+    /// Create a processor backed by the provided test environment.
+    fn from_test_environment(environment: &SvmTestEnvironment) -> Self;
+}
 
 /// The Solana SVM Specification Test Harness.
+#[derive(Default)]
 pub struct SolanaSvmTestHarness;
 
 impl SolanaSvmTestHarness {
@@ -10,26 +138,401 @@ impl SolanaSvmTestHarness {
         Self
     }
 
-    pub fn case_1<T: TransactionBatchProcessor>(&self, _processor: &T) {
-        //
+    /// A transaction whose fee payer does not exist fails to load.
+    pub fn fee_payer_not_found<T: TestTransactionBatchProcessor>(&self) {
+        let payer = Keypair::new();
+        let recipient = Pubkey::new_unique();
+        let environment =
+            SvmTestEnvironment::new(vec![(recipient, system_account(LAMPORTS_PER_SOL))]);
+        let tx = environment.transaction(
+            &[system_instruction::transfer(
+                &payer.pubkey(),
+                &recipient,
+                1_000,
+            )],
+            &payer,
+        );
+
+        assert_batch::<T>(
+            &environment,
+            &[tx],
+            vec![not_loaded(TransactionError::AccountNotFound)],
+        );
     }
 
-    pub fn case_2<T: TransactionBatchProcessor>(&self, _processor: &T) {
-        //
+    /// A transaction whose fee payer cannot cover the fee fails to load.
+    pub fn insufficient_funds_for_fee<T: TestTransactionBatchProcessor>(&self) {
+        let payer = Keypair::new();
+        let recipient = Pubkey::new_unique();
+        let environment = SvmTestEnvironment::new(vec![
+            (
+                payer.pubkey(),
+                system_account(LAMPORTS_PER_SIGNATURE.saturating_sub(1)),
+            ),
+            (recipient, system_account(LAMPORTS_PER_SOL)),
+        ]);
+        let tx = environment.transaction(
+            &[system_instruction::transfer(
+                &payer.pubkey(),
+                &recipient,
+                1_000,
+            )],
+            &payer,
+        );
+
+        assert_batch::<T>(
+            &environment,
+            &[tx],
+            vec![not_loaded(TransactionError::InsufficientFundsForFee)],
+        );
     }
 
-    pub fn case_3<T: TransactionBatchProcessor>(&self, _processor: &T) {
-        //
+    /// A transaction write-locking an account already write-locked by an
+    /// earlier transaction in the same batch fails to load.
+    pub fn account_in_use<T: TestTransactionBatchProcessor>(&self) {
+        let payer_a = Keypair::new();
+        let payer_b = Keypair::new();
+        let recipient = Pubkey::new_unique();
+        let environment = SvmTestEnvironment::new(vec![
+            (payer_a.pubkey(), system_account(LAMPORTS_PER_SOL)),
+            (payer_b.pubkey(), system_account(LAMPORTS_PER_SOL)),
+            (recipient, system_account(LAMPORTS_PER_SOL)),
+        ]);
+        let tx_a = environment.transaction(
+            &[system_instruction::transfer(
+                &payer_a.pubkey(),
+                &recipient,
+                1_000,
+            )],
+            &payer_a,
+        );
+        let tx_b = environment.transaction(
+            &[system_instruction::transfer(
+                &payer_b.pubkey(),
+                &recipient,
+                1_000,
+            )],
+            &payer_b,
+        );
+
+        let mut post_accounts = environment.accounts_map();
+        debit(
+            &mut post_accounts,
+            &payer_a.pubkey(),
+            LAMPORTS_PER_SIGNATURE + 1_000,
+        );
+        credit(&mut post_accounts, &recipient, 1_000);
+        let expected_a = (
+            (Ok(loaded_transaction(&tx_a, &post_accounts)), None),
+            executed(Ok(()), T::BUILTIN_COMPUTE_UNITS, None),
+        );
+
+        assert_batch::<T>(
+            &environment,
+            &[tx_a, tx_b],
+            vec![expected_a, not_loaded(TransactionError::AccountInUse)],
+        );
     }
 
-    pub fn case_4<T: TransactionBatchProcessor>(&self, _processor: &T) {
-        //
+    /// A transaction whose recent blockhash is unknown, and which is not a
+    /// durable nonce transaction, is rejected before loading.
+    pub fn blockhash_not_found<T: TestTransactionBatchProcessor>(&self) {
+        let payer = Keypair::new();
+        let recipient = Pubkey::new_unique();
+        let environment = SvmTestEnvironment::new(vec![
+            (payer.pubkey(), system_account(LAMPORTS_PER_SOL)),
+            (recipient, system_account(LAMPORTS_PER_SOL)),
+        ]);
+        let tx = environment.transaction_with_blockhash(
+            &[system_instruction::transfer(
+                &payer.pubkey(),
+                &recipient,
+                1_000,
+            )],
+            &payer,
+            Hash::new_unique(),
+        );
+
+        assert_batch::<T>(
+            &environment,
+            &[tx],
+            vec![not_loaded(TransactionError::BlockhashNotFound)],
+        );
     }
 
-    pub fn run_all<T: TransactionBatchProcessor>(&self, processor: &T) {
-        self.case_1(processor);
-        self.case_2(processor);
-        self.case_3(processor);
-        self.case_4(processor);
+    /// A transaction invoking a non-executable account fails to load.
+    pub fn invalid_program<T: TestTransactionBatchProcessor>(&self) {
+        let payer = Keypair::new();
+        let program_id = Pubkey::new_unique();
+        let environment = SvmTestEnvironment::new(vec![
+            (payer.pubkey(), system_account(LAMPORTS_PER_SOL)),
+            (program_id, system_account(LAMPORTS_PER_SOL)),
+        ]);
+        let tx = environment.transaction(
+            &[Instruction::new_with_bytes(program_id, &[], vec![])],
+            &payer,
+        );
+
+        assert_batch::<T>(
+            &environment,
+            &[tx],
+            vec![not_loaded(TransactionError::InvalidProgramForExecution)],
+        );
+    }
+
+    /// A system transfer is charged the signature fee and moves lamports.
+    pub fn successful_transfer<T: TestTransactionBatchProcessor>(&self) {
+        let payer = Keypair::new();
+        let recipient = Pubkey::new_unique();
+        let environment = SvmTestEnvironment::new(vec![
+            (payer.pubkey(), system_account(LAMPORTS_PER_SOL)),
+            (recipient, system_account(LAMPORTS_PER_SOL)),
+        ]);
+        let tx = environment.transaction(
+            &[system_instruction::transfer(
+                &payer.pubkey(),
+                &recipient,
+                1_000,
+            )],
+            &payer,
+        );
+
+        let mut post_accounts = environment.accounts_map();
+        debit(
+            &mut post_accounts,
+            &payer.pubkey(),
+            LAMPORTS_PER_SIGNATURE + 1_000,
+        );
+        credit(&mut post_accounts, &recipient, 1_000);
+        let expected = (
+            (Ok(loaded_transaction(&tx, &post_accounts)), None),
+            executed(Ok(()), T::BUILTIN_COMPUTE_UNITS, None),
+        );
+
+        assert_batch::<T>(&environment, &[tx], vec![expected]);
+    }
+
+    /// A durable nonce transaction is accepted with the nonce as its
+    /// blockhash, and advances the nonce to the most recent blockhash.
+    pub fn durable_nonce_advance<T: TestTransactionBatchProcessor>(&self) {
+        let payer = Keypair::new();
+        let recipient = Pubkey::new_unique();
+        let nonce_address = Pubkey::new_unique();
+        let durable_nonce = DurableNonce::from_blockhash(&Hash::new_unique());
+        let mut environment = SvmTestEnvironment::new(vec![
+            (payer.pubkey(), system_account(LAMPORTS_PER_SOL)),
+            (recipient, system_account(LAMPORTS_PER_SOL)),
+        ]);
+        environment
+            .accounts
+            .push((nonce_address, nonce_account(&payer.pubkey(), durable_nonce)));
+        let tx = environment.transaction_with_blockhash(
+            &[
+                system_instruction::advance_nonce_account(&nonce_address, &payer.pubkey()),
+                system_instruction::transfer(&payer.pubkey(), &recipient, 1_000),
+            ],
+            &payer,
+            *durable_nonce.as_hash(),
+        );
+
+        let advanced_nonce_account = nonce_account(
+            &payer.pubkey(),
+            DurableNonce::from_blockhash(&environment.blockhash),
+        );
+        let mut post_accounts = environment.accounts_map();
+        debit(&mut post_accounts, &payer.pubkey(), LAMPORTS_PER_SIGNATURE);
+        let nonce = NonceFull::new(
+            nonce_address,
+            advanced_nonce_account.clone(),
+            post_accounts.get(&payer.pubkey()).cloned(),
+        );
+        debit(&mut post_accounts, &payer.pubkey(), 1_000);
+        credit(&mut post_accounts, &recipient, 1_000);
+        post_accounts.insert(nonce_address, advanced_nonce_account);
+        let expected = (
+            (Ok(loaded_transaction(&tx, &post_accounts)), Some(nonce)),
+            executed(
+                Ok(()),
+                T::BUILTIN_COMPUTE_UNITS * 2,
+                Some(DurableNonceFee::Valid(LAMPORTS_PER_SIGNATURE)),
+            ),
+        );
+
+        assert_batch::<T>(&environment, &[tx], vec![expected]);
     }
+
+    /// A transaction exceeding its requested compute unit limit is executed,
+    /// charged the fee, and fails at the instruction that ran out of units.
+    pub fn compute_unit_exhaustion<T: TestTransactionBatchProcessor>(&self) {
+        let payer = Keypair::new();
+        let recipient = Pubkey::new_unique();
+        let compute_unit_limit = T::BUILTIN_COMPUTE_UNITS as u32 + 50;
+        let environment = SvmTestEnvironment::new(vec![
+            (payer.pubkey(), system_account(LAMPORTS_PER_SOL)),
+            (recipient, system_account(LAMPORTS_PER_SOL)),
+        ]);
+        let tx = environment.transaction(
+            &[
+                ComputeBudgetInstruction::set_compute_unit_limit(compute_unit_limit),
+                system_instruction::transfer(&payer.pubkey(), &recipient, 1_000),
+            ],
+            &payer,
+        );
+
+        let mut post_accounts = environment.accounts_map();
+        debit(&mut post_accounts, &payer.pubkey(), LAMPORTS_PER_SIGNATURE);
+        let expected = (
+            (Ok(loaded_transaction(&tx, &post_accounts)), None),
+            executed(
+                Err(TransactionError::InstructionError(
+                    1,
+                    InstructionError::ComputationalBudgetExceeded,
+                )),
+                u64::from(compute_unit_limit),
+                None,
+            ),
+        );
+
+        assert_batch::<T>(&environment, &[tx], vec![expected]);
+    }
+
+    /// Run every conformance case against processors of type `T`.
+    ///
+    /// Each case creates its own processor through
+    /// `TestTransactionBatchProcessor::from_test_environment`, so unlike
+    /// earlier versions of the harness, `run_all` takes no processor
+    /// instance. Implementations previously passed to `run_all(&processor)`
+    /// should implement `TestTransactionBatchProcessor` and be run with
+    /// `run_all::<Processor>()`.
+    pub fn run_all<T: TestTransactionBatchProcessor>(&self) {
+        self.fee_payer_not_found::<T>();
+        self.insufficient_funds_for_fee::<T>();
+        self.account_in_use::<T>();
+        self.blockhash_not_found::<T>();
+        self.invalid_program::<T>();
+        self.successful_transfer::<T>();
+        self.durable_nonce_advance::<T>();
+        self.compute_unit_exhaustion::<T>();
+    }
+
+    /// Run every JSON fixture in the provided directory, returning a report
+    /// for each fixture.
+    pub fn run_fixtures<T: TestTransactionBatchProcessor>(
+        &self,
+        dir: &Path,
+    ) -> io::Result<Vec<FixtureReport>> {
+        let mut paths = fs::read_dir(dir)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<io::Result<Vec<_>>>()?;
+        paths.retain(|path| path.extension().is_some_and(|ext| ext == "json"));
+        paths.sort();
+        paths
+            .into_iter()
+            .map(|path| {
+                let diffs = SvmFixture::load(&path)?.run::<T>();
+                Ok(FixtureReport { path, diffs })
+            })
+            .collect()
+    }
+}
+
+type ExpectedResult = (TransactionLoadResult, TransactionExecutionResult);
+
+fn assert_batch<T: TestTransactionBatchProcessor>(
+    environment: &SvmTestEnvironment,
+    sanitized_txs: &[SanitizedTransaction],
+    expected: Vec<ExpectedResult>,
+) {
+    let processor = T::from_test_environment(environment);
+    let output =
+        processor.load_and_execute_sanitized_transactions(sanitized_txs, MAX_PROCESSING_AGE);
+    let (expected_loaded_transactions, expected_execution_results): (Vec<_>, Vec<_>) =
+        expected.into_iter().unzip();
+    assert_eq!(output.loaded_transactions, expected_loaded_transactions);
+    assert_eq!(output.execution_results, expected_execution_results);
+}
+
+fn not_loaded(err: TransactionError) -> ExpectedResult {
+    (
+        (Err(err.clone()), None),
+        TransactionExecutionResult::NotExecuted(err),
+    )
+}
+
+fn executed(
+    status: transaction::Result<()>,
+    executed_units: u64,
+    durable_nonce_fee: Option<DurableNonceFee>,
+) -> TransactionExecutionResult {
+    TransactionExecutionResult::Executed {
+        details: TransactionExecutionDetails {
+            status,
+            log_messages: None,
+            inner_instructions: None,
+            durable_nonce_fee,
+            return_data: None,
+            executed_units,
+            accounts_data_len_delta: 0,
+        },
+    }
+}
+
+fn loaded_transaction(
+    tx: &SanitizedTransaction,
+    post_accounts: &HashMap<Pubkey, AccountSharedData>,
+) -> LoadedTransaction {
+    let message = tx.message();
+    LoadedTransaction {
+        accounts: message
+            .account_keys()
+            .iter()
+            .map(|key| (*key, post_accounts.get(key).cloned().unwrap_or_default()))
+            .collect(),
+        program_indices: message
+            .instructions()
+            .iter()
+            .map(|ix| vec![IndexOfAccount::from(ix.program_id_index)])
+            .collect(),
+        rent: 0,
+        rent_debits: RentDebits::default(),
+    }
+}
+
+fn debit(accounts: &mut HashMap<Pubkey, AccountSharedData>, pubkey: &Pubkey, lamports: u64) {
+    accounts
+        .get_mut(pubkey)
+        .unwrap()
+        .checked_sub_lamports(lamports)
+        .unwrap();
+}
+
+fn credit(accounts: &mut HashMap<Pubkey, AccountSharedData>, pubkey: &Pubkey, lamports: u64) {
+    accounts
+        .get_mut(pubkey)
+        .unwrap()
+        .checked_add_lamports(lamports)
+        .unwrap();
+}
+
+fn builtin_account(name: &str) -> AccountSharedData {
+    native_loader::create_loadable_account_with_fields(name, (1, RENT_EXEMPT_RENT_EPOCH))
+}
+
+fn system_account(lamports: u64) -> AccountSharedData {
+    let mut account = AccountSharedData::new(lamports, 0, &system_program::id());
+    account.set_rent_epoch(RENT_EXEMPT_RENT_EPOCH);
+    account
+}
+
+fn nonce_account(authority: &Pubkey, durable_nonce: DurableNonce) -> AccountSharedData {
+    let state =
+        NonceState::Initialized(Data::new(*authority, durable_nonce, LAMPORTS_PER_SIGNATURE));
+    let mut account = AccountSharedData::new_data(
+        LAMPORTS_PER_SOL,
+        &Versions::new(state),
+        &system_program::id(),
+    )
+    .unwrap();
+    account.set_rent_epoch(RENT_EXEMPT_RENT_EPOCH);
+    account
 }
@@ -0,0 +1,304 @@
+//! Declarative SVM test fixtures.
+//!
+//! A fixture describes the environment a batch of transactions is run against,
+//! along with the output a spec-compliant processor must produce. Fixtures are
+//! stored as JSON, so cases can be shared between SVM implementations without
+//! recompiling the harness.
+
+use {
+    crate::{
+        specification::{LoadedTransaction, TransactionExecutionResult, TransactionLoadResult},
+        test_harness::{builtin_account, SvmTestEnvironment, TestTransactionBatchProcessor},
+    },
+    serde::{Deserialize, Serialize},
+    solana_compute_budget::compute_budget::ComputeBudget,
+    solana_sdk::{
+        account::{create_account_shared_data_with_fields, AccountSharedData},
+        clock::MAX_PROCESSING_AGE,
+        feature_set::FeatureSet,
+        hash::Hash,
+        nonce_info::NonceFull,
+        pubkey::Pubkey,
+        rent_collector::RENT_EXEMPT_RENT_EPOCH,
+        rent_debits::RentDebits,
+        sanitize::Sanitize,
+        sysvar::{
+            self, clock::Clock, epoch_rewards::EpochRewards, epoch_schedule::EpochSchedule,
+            last_restart_slot::LastRestartSlot, rent::Rent, slot_hashes::SlotHashes,
+            stake_history::StakeHistory, Sysvar,
+        },
+        transaction::{SanitizedTransaction, Transaction, TransactionError},
+        transaction_context::IndexOfAccount,
+    },
+    std::{
+        fs::File,
+        io::{self, BufReader, BufWriter},
+        path::{Path, PathBuf},
+    },
+};
+
+/// An on-disk SVM test fixture.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SvmFixture {
+    /// The accounts available to the processor.
+    pub accounts: Vec<(Pubkey, AccountSharedData)>,
+    /// Builtin programs, written into the accounts available to the
+    /// processor as native loader accounts. Every builtin the processor
+    /// provides must be listed, either here or among the accounts.
+    #[serde(default)]
+    pub builtins: Vec<FixtureBuiltin>,
+    /// Sysvars, written into the accounts available to the processor.
+    #[serde(default)]
+    pub sysvars: FixtureSysvars,
+    /// The active features. Every feature is active when omitted.
+    #[serde(default)]
+    pub features: Option<Vec<Pubkey>>,
+    /// The compute budget overriding any compute budget instructions.
+    #[serde(default)]
+    pub compute_budget: Option<ComputeBudget>,
+    /// The most recent blockhash.
+    pub blockhash: Hash,
+    /// The lamports per signature associated with the most recent blockhash.
+    pub lamports_per_signature: u64,
+    /// The batch of transactions to load and execute.
+    pub transactions: Vec<Transaction>,
+    /// The expected result of each transaction in the batch.
+    pub expected: Vec<FixtureTransactionResult>,
+}
+
+/// A builtin program, which the processor must implement natively.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct FixtureBuiltin {
+    pub program_id: Pubkey,
+    /// The name stored in the program's native loader account.
+    pub name: String,
+}
+
+/// Sysvars provided to a fixture's processor.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+#[allow(deprecated)]
+pub struct FixtureSysvars {
+    pub clock: Option<Clock>,
+    pub epoch_schedule: Option<EpochSchedule>,
+    pub epoch_rewards: Option<EpochRewards>,
+    pub fees: Option<sysvar::fees::Fees>,
+    pub last_restart_slot: Option<LastRestartSlot>,
+    pub recent_blockhashes: Option<sysvar::recent_blockhashes::RecentBlockhashes>,
+    pub rent: Option<Rent>,
+    pub slot_hashes: Option<SlotHashes>,
+    pub stake_history: Option<StakeHistory>,
+}
+
+impl FixtureSysvars {
+    /// The sysvar accounts of every provided sysvar.
+    #[allow(deprecated)]
+    fn accounts(&self) -> Vec<(Pubkey, AccountSharedData)> {
+        let mut accounts = Vec::new();
+        push_sysvar_account(&mut accounts, self.clock.as_ref());
+        push_sysvar_account(&mut accounts, self.epoch_schedule.as_ref());
+        push_sysvar_account(&mut accounts, self.epoch_rewards.as_ref());
+        push_sysvar_account(&mut accounts, self.fees.as_ref());
+        push_sysvar_account(&mut accounts, self.last_restart_slot.as_ref());
+        push_sysvar_account(&mut accounts, self.recent_blockhashes.as_ref());
+        push_sysvar_account(&mut accounts, self.rent.as_ref());
+        push_sysvar_account(&mut accounts, self.slot_hashes.as_ref());
+        push_sysvar_account(&mut accounts, self.stake_history.as_ref());
+        accounts
+    }
+}
+
+fn push_sysvar_account<S: Sysvar>(
+    accounts: &mut Vec<(Pubkey, AccountSharedData)>,
+    sysvar: Option<&S>,
+) {
+    if let Some(sysvar) = sysvar {
+        accounts.push((
+            S::id(),
+            create_account_shared_data_with_fields(sysvar, (1, RENT_EXEMPT_RENT_EPOCH)),
+        ));
+    }
+}
+
+/// The expected load and execution result of a single transaction.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct FixtureTransactionResult {
+    pub load_result: Result<FixtureLoadedTransaction, TransactionError>,
+    pub nonce: Option<FixtureNonce>,
+    pub execution_result: TransactionExecutionResult,
+}
+
+/// Serializable form of `LoadedTransaction`.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct FixtureLoadedTransaction {
+    pub accounts: Vec<(Pubkey, AccountSharedData)>,
+    pub program_indices: Vec<Vec<IndexOfAccount>>,
+    pub rent: u64,
+    pub rent_debits: Vec<FixtureRentDebit>,
+}
+
+/// Serializable form of a single `RentDebits` entry.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct FixtureRentDebit {
+    pub address: Pubkey,
+    pub rent_collected: u64,
+    pub post_balance: u64,
+}
+
+/// Serializable form of `NonceFull`.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct FixtureNonce {
+    pub address: Pubkey,
+    pub account: AccountSharedData,
+    pub fee_payer_account: Option<AccountSharedData>,
+}
+
+/// The outcome of running a single fixture.
+#[derive(Debug)]
+pub struct FixtureReport {
+    pub path: PathBuf,
+    /// Every difference between the expected and actual output.
+    pub diffs: Vec<String>,
+}
+
+impl FixtureReport {
+    pub fn passed(&self) -> bool {
+        self.diffs.is_empty()
+    }
+}
+
+impl SvmFixture {
+    /// Load a fixture from a JSON file.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let reader = BufReader::new(File::open(path)?);
+        Ok(serde_json::from_reader(reader)?)
+    }
+
+    /// Write the fixture to a JSON file.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let writer = BufWriter::new(File::create(path)?);
+        Ok(serde_json::to_writer_pretty(writer, self)?)
+    }
+
+    /// Build the test environment described by the fixture.
+    pub fn environment(&self) -> SvmTestEnvironment {
+        let mut accounts = self.accounts.clone();
+        accounts.extend(
+            self.builtins
+                .iter()
+                .map(|builtin| (builtin.program_id, builtin_account(&builtin.name))),
+        );
+        accounts.extend(self.sysvars.accounts());
+
+        let feature_set = match &self.features {
+            Some(features) => {
+                let mut feature_set = FeatureSet::default();
+                for feature_id in features {
+                    feature_set.activate(feature_id, 0);
+                }
+                feature_set
+            }
+            None => FeatureSet::all_enabled(),
+        };
+
+        SvmTestEnvironment {
+            accounts,
+            blockhash: self.blockhash,
+            lamports_per_signature: self.lamports_per_signature,
+            feature_set,
+            compute_budget: self.compute_budget,
+        }
+    }
+
+    /// Run the fixture against a processor, returning every difference
+    /// between the expected and actual output.
+    pub fn run<T: TestTransactionBatchProcessor>(&self) -> Vec<String> {
+        let mut sanitized_txs = Vec::with_capacity(self.transactions.len());
+        for (index, tx) in self.transactions.iter().enumerate() {
+            if let Err(err) = tx.sanitize() {
+                return vec![format!("transaction {index}: failed to sanitize: {err}")];
+            }
+            sanitized_txs.push(SanitizedTransaction::from_transaction_for_tests(tx.clone()));
+        }
+
+        let processor = T::from_test_environment(&self.environment());
+        let output =
+            processor.load_and_execute_sanitized_transactions(&sanitized_txs, MAX_PROCESSING_AGE);
+
+        let mut diffs = Vec::new();
+        if output.loaded_transactions.len() != self.expected.len()
+            || output.execution_results.len() != self.expected.len()
+        {
+            diffs.push(format!(
+                "expected {} results, got {} load results and {} execution results",
+                self.expected.len(),
+                output.loaded_transactions.len(),
+                output.execution_results.len(),
+            ));
+        }
+        for (index, ((load_result, execution_result), expected)) in output
+            .loaded_transactions
+            .iter()
+            .zip(output.execution_results.iter())
+            .zip(self.expected.iter())
+            .enumerate()
+        {
+            let expected_load_result = expected.load_result();
+            if *load_result != expected_load_result {
+                diffs.push(format!(
+                    "transaction {index}: load result\n  expected: {expected_load_result:?}\n  \
+                     actual:   {load_result:?}"
+                ));
+            }
+            if *execution_result != expected.execution_result {
+                diffs.push(format!(
+                    "transaction {index}: execution result\n  expected: {:?}\n  actual:   \
+                     {execution_result:?}",
+                    expected.execution_result,
+                ));
+            }
+        }
+        diffs
+    }
+}
+
+impl FixtureTransactionResult {
+    fn load_result(&self) -> TransactionLoadResult {
+        let loaded_transaction = self
+            .load_result
+            .as_ref()
+            .map(LoadedTransaction::from)
+            .map_err(Clone::clone);
+        let nonce = self.nonce.as_ref().map(NonceFull::from);
+        (loaded_transaction, nonce)
+    }
+}
+
+impl From<&FixtureLoadedTransaction> for LoadedTransaction {
+    fn from(loaded_transaction: &FixtureLoadedTransaction) -> Self {
+        let mut rent_debits = RentDebits::default();
+        for rent_debit in &loaded_transaction.rent_debits {
+            rent_debits.insert(
+                &rent_debit.address,
+                rent_debit.rent_collected,
+                rent_debit.post_balance,
+            );
+        }
+        LoadedTransaction {
+            accounts: loaded_transaction.accounts.clone(),
+            program_indices: loaded_transaction.program_indices.clone(),
+            rent: loaded_transaction.rent,
+            rent_debits,
+        }
+    }
+}
+
+impl From<&FixtureNonce> for NonceFull {
+    fn from(nonce: &FixtureNonce) -> Self {
+        NonceFull::new(
+            nonce.address,
+            nonce.account.clone(),
+            nonce.fee_payer_account.clone(),
+        )
+    }
+}